@@ -0,0 +1,2329 @@
+//! The xrun command line, which parses the flags and runs the chosen action on the command.
+
+use std::{
+    cell::OnceCell,
+    collections::BTreeMap,
+    env, fs,
+    io::{self, IsTerminal, Write},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::process::{CommandExt, ExitStatusExt},
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    cache,
+    cli::{is_builtin_flag, parse_args, Arg, VALUE_FLAGS},
+    command_parser::{
+        check_config, export_command_toml, get_command, get_command_ancestors, get_command_details,
+        get_command_help, get_command_help_all, get_command_list, get_command_steps,
+        get_command_steps_named, get_command_steps_stdin_from_args, get_command_tree,
+        get_flag_aliases, get_help_passthrough, is_runnable, parse_timeout, resolve_dir,
+        strict_prelude, trace_command_resolution, verify_config_hash, verify_has_commands,
+        CommandDetails, CommandHelp, CommandNode, CommandParseError, HelpPair, MissingShell,
+        ResolvedCommand, ShellConvention, StdinMode, RESERVED_KEYS,
+    },
+    completions::{completion_script, install_path, Shell},
+    PROG_NAME,
+};
+
+#[derive(PartialEq)]
+enum Action {
+    Exec,
+    Help,
+    DumpResolved,
+    PrintShell,
+    Version,
+    EnvDump,
+    Describe,
+    DryRun,
+    TraceResolve,
+    Check,
+    Completions(Shell),
+    CompareConfig,
+    InitCompletions,
+    Init,
+    Where,
+    List,
+    Tree(Option<usize>),
+    PrintEnvFile,
+}
+
+/// Settings given on the command line that override those of the resolved command.
+#[derive(Default)]
+struct CliOverrides {
+    no_stdin: bool,
+    stdin_from_args: bool,
+    trace_shell: bool,
+    run_from: Option<PathBuf>,
+    no_cache: bool,
+    refresh: bool,
+    /// The names of the steps to run, or `None` to run all of them.
+    steps: Option<Vec<String>>,
+    /// The timeout replacing that of the command, zero meaning none.
+    timeout: Option<Duration>,
+}
+
+impl CliOverrides {
+    fn apply(&self, resolved: &mut ResolvedCommand) {
+        if self.no_stdin {
+            resolved.stdin = StdinMode::Null;
+        }
+        if self.trace_shell {
+            resolved.trace = true;
+        }
+        if let Some(run_from) = &self.run_from {
+            resolved.cwd = Some(run_from.to_owned());
+        }
+        if let Some(timeout) = self.timeout {
+            resolved.timeout = Some(timeout).filter(|timeout| !timeout.is_zero());
+        }
+        if self.no_cache {
+            resolved.cache = None;
+        } else if let Some(cache) = &mut resolved.cache {
+            if self.refresh {
+                // Cached output is never fresh, so the command runs and its output is re-cached.
+                *cache = Duration::ZERO;
+            }
+        }
+    }
+
+    /// Gets the steps of a command with the overrides applied to each.
+    fn get_steps(
+        &self,
+        path: &Path,
+        command: &[&str],
+    ) -> Result<Vec<ResolvedCommand>, CommandParseError> {
+        let mut steps = match &self.steps {
+            Some(names) => get_command_steps_named(path, command, names, self.stdin_from_args)?,
+            None if self.stdin_from_args => get_command_steps_stdin_from_args(path, command)?,
+            None => get_command_steps(path, command)?,
+        };
+        for step in &mut steps {
+            self.apply(step);
+        }
+        Ok(steps)
+    }
+}
+
+/// Filters given on the command line selecting the commands `--list` and `--tree` print.
+#[derive(Default)]
+struct CommandFilter {
+    /// Only commands whose path or description matches this are printed.
+    include: Option<Regex>,
+    /// Commands whose path or description matches this are not printed.
+    exclude: Option<Regex>,
+    /// Only commands configuring this key are printed.
+    with_key: Option<String>,
+    /// Commands configuring this key are not printed.
+    without_key: Option<String>,
+}
+
+impl CommandFilter {
+    /// Creates a case-insensitive pattern for the filter.
+    ///
+    /// * `pattern` - The substring to match, or regex if `is_regex`.
+    /// * `is_regex` - Whether `pattern` is a regex.
+    ///
+    /// returns - The pattern or the error in the regex.
+    fn pattern(pattern: &str, is_regex: bool) -> Result<Regex, regex::Error> {
+        let pattern = if is_regex {
+            pattern.to_owned()
+        } else {
+            regex::escape(pattern)
+        };
+        RegexBuilder::new(&pattern).case_insensitive(true).build()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.include.is_none()
+            && self.exclude.is_none()
+            && self.with_key.is_none()
+            && self.without_key.is_none()
+    }
+
+    /// Checks whether a command passes the filter.
+    ///
+    /// * `path` - The path of the command.
+    /// * `desc` - The description of the command if defined.
+    /// * `keys` - The keys configuring the command.
+    fn matches(&self, path: &[String], desc: Option<&str>, keys: &[String]) -> bool {
+        let path = path.join(" ");
+        let is_match =
+            |regex: &Regex| regex.is_match(&path) || desc.is_some_and(|desc| regex.is_match(desc));
+        let has_key = |key: &String| keys.contains(key);
+        self.include.as_ref().is_none_or(is_match)
+            && !self.exclude.as_ref().is_some_and(is_match)
+            && self.with_key.as_ref().is_none_or(has_key)
+            && !self.without_key.as_ref().is_some_and(has_key)
+    }
+}
+
+/// Logs what xrun does to stderr, so it doesn't mix with the output of commands or
+/// `--passthrough`. Each `--verbose` raises the level by one, and nothing is logged by default.
+#[derive(Default)]
+struct Logger {
+    level: usize,
+    show_secrets: bool,
+}
+
+impl Logger {
+    /// Logs `message` if the level is at least `level`.
+    fn log(&self, level: usize, message: &str) {
+        if self.level >= level {
+            eprintln!("xrun: {}", message);
+        }
+    }
+
+    /// Logs how a command is executed, before it is spawned.
+    ///
+    /// * `resolved` - The command.
+    /// * `shell` - The shell it is executed with, or an empty string if it has none.
+    fn log_execution(&self, resolved: &ResolvedCommand, shell: &str) {
+        if self.level == 0 {
+            return;
+        }
+        self.log(
+            1,
+            &format!("shell: {}", if shell.is_empty() { "none" } else { shell }),
+        );
+        if let Some(cwd) = resolved.cwd.clone().or_else(|| env::current_dir().ok()) {
+            self.log(1, &format!("cwd: {}", cwd.display()));
+        }
+        for (key, value) in &resolved.env {
+            if !self.show_secrets && is_secret(key) {
+                self.log(1, &format!("env: {}=***", key));
+            } else {
+                self.log(1, &format!("env: {}={}", key, value));
+            }
+        }
+        self.log(1, &format!("command: {}", resolved.command));
+    }
+}
+
+/// When help is colored, as chosen by `--color`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Decides whether to color output.
+    ///
+    /// * `no_color` - Whether `$NO_COLOR` is set to a non-empty value.
+    /// * `is_terminal` - Whether stdout is a terminal.
+    ///
+    /// returns - Whether to color output, which `Auto` does only for a terminal without
+    /// `$NO_COLOR`.
+    fn use_color(self, no_color: bool, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => !no_color && is_terminal,
+        }
+    }
+}
+
+/// ANSI style of names in colored help.
+const BOLD: &str = "1";
+/// ANSI style of descriptions in colored help.
+const DIM: &str = "2";
+
+/// Wraps `s` in an ANSI style if `color`, resetting the style after it.
+///
+/// * `s` - The text to style.
+/// * `style` - The ANSI style, e.g. `BOLD`.
+/// * `color` - Whether to style at all.
+fn paint(s: &str, style: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", style, s)
+    } else {
+        s.to_string()
+    }
+}
+
+#[derive(PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Toml,
+    /// `path<TAB>desc` lines to pick from with fzf.
+    Fzf,
+    /// A JSON value per line.
+    JsonLines,
+}
+
+/// How often a command with a timeout is checked for having exited.
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+/// How long a command that timed out has to exit after SIGTERM before it is sent SIGKILL.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// The config written by `--init`.
+const SAMPLE_CONFIG: &str = include_str!("sample_command.toml");
+
+/// Runs xrun with the arguments it was started with.
+///
+/// returns - The exit code xrun should exit with.
+pub fn run() -> i32 {
+    let raw_args: Vec<String> = env::args().skip(1).collect();
+    run_args(&raw_args)
+}
+
+/// Runs xrun with the given command line.
+///
+/// * `raw_args` - The command line, without the program name.
+///
+/// returns - The exit code xrun should exit with.
+fn run_args(raw_args: &[String]) -> i32 {
+    // The config is needed to tell arguments from flags and to expand flag aliases, possibly
+    // before reaching the flags choosing it, so those are found first.
+    let args = match parse_args(raw_args, |_, _| false, |_| false) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    };
+    let config = args.iter().rev().find_map(|arg| match arg {
+        Arg::Flag("--config" | "-c", Some(path)) => Some(Path::new(*path)),
+        _ => None,
+    });
+    let local = !args.contains(&Arg::Flag("--no-local", None));
+    let flag_aliases = OnceCell::new();
+    // Only load the config for aliases when needed so a broken config doesn't break flags like
+    // --version that don't need it.
+    let get_flag_aliases = || {
+        flag_aliases.get_or_init(|| match find_config(config, local) {
+            Ok(Some(path)) => get_flag_aliases(&path),
+            Ok(None) => Ok(BTreeMap::new()),
+            Err(err) => Err(err),
+        })
+    };
+    let args = parse_args(
+        raw_args,
+        |command, flag| {
+            let flag = flag.split_once('=').map_or(flag, |(flag, _)| flag);
+            // An error loading the aliases is reported once the flag is handled.
+            get_flag_aliases()
+                .as_ref()
+                .is_ok_and(|aliases| !aliases.contains_key(flag.trim_start_matches('-')))
+                && find_config(config, local)
+                    .ok()
+                    .flatten()
+                    .is_some_and(|path| is_runnable(&path, command))
+        },
+        |flag| {
+            get_flag_aliases().as_ref().is_ok_and(|aliases| {
+                aliases
+                    .get(flag.trim_start_matches('-'))
+                    .is_some_and(|expanded| VALUE_FLAGS.contains(&expanded.as_str()))
+            })
+        },
+    );
+    let args = match args {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    };
+
+    let mut action = Action::Exec;
+    let mut passthrough = false;
+    let mut all = false;
+    let mut count = false;
+    let mut stats = false;
+    let mut context = false;
+    let mut show_secrets = false;
+    let mut verbosity = 0;
+    let mut color = ColorChoice::Auto;
+    let mut force = false;
+    let mut overrides = CliOverrides::default();
+    let mut format = OutputFormat::Text;
+    let mut filter = CommandFilter::default();
+    let mut match_pattern = None;
+    let mut invert_match_pattern = None;
+    let mut match_regex = false;
+    let mut command = vec![];
+    for &arg in &args {
+        let (flag, value) = match arg {
+            Arg::Flag(flag, value) => (flag.to_string(), value.map(|s| s.to_string())),
+            Arg::Command(token) => {
+                command.push(token);
+                continue;
+            }
+        };
+        let (flag, value) = if is_builtin_flag(&flag) {
+            (flag, value)
+        } else {
+            let aliases = match get_flag_aliases() {
+                Ok(aliases) => aliases,
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    return err.exit_code();
+                }
+            };
+            match aliases.get(flag.trim_start_matches('-')) {
+                Some(expanded) if is_builtin_flag(expanded) => match expanded.split_once('=') {
+                    Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                    None => (expanded.to_owned(), value),
+                },
+                _ => (flag, value),
+            }
+        };
+        match (flag.as_str(), value.as_deref()) {
+            ("--help" | "-h", None) => action = Action::Help,
+            ("--passthrough" | "-p", None) => passthrough = true,
+            ("--all", None) => all = true,
+            ("--context", None) => context = true,
+            ("--help-all", None) => {
+                action = Action::Help;
+                all = true;
+            }
+            ("--dump-resolved", None) => action = Action::DumpResolved,
+            ("--print-shell", None) => action = Action::PrintShell,
+            ("--version" | "-V", None) => action = Action::Version,
+            ("--env-dump", None) => action = Action::EnvDump,
+            ("--print-env-file", None) => action = Action::PrintEnvFile,
+            ("--describe", None) => action = Action::Describe,
+            ("--dry-run" | "-n", None) => action = Action::DryRun,
+            ("--trace-resolve", None) => action = Action::TraceResolve,
+            ("--check", None) => action = Action::Check,
+            ("--compare-config", None) => action = Action::CompareConfig,
+            ("--completions", Some(shell)) => match Shell::from_name(shell) {
+                Some(shell) => action = Action::Completions(shell),
+                None => {
+                    eprintln!("Unsupported shell for --completions: {}", shell);
+                    return 1;
+                }
+            },
+            ("--init-completions", None) => action = Action::InitCompletions,
+            ("--init", None) => action = Action::Init,
+            ("--where", None) => action = Action::Where,
+            ("--force", None) => force = true,
+            ("--list", None) => action = Action::List,
+            ("--count", None) => count = true,
+            ("--json-lines", None) => format = OutputFormat::JsonLines,
+            ("--stats", None) => stats = true,
+            ("--tree", None) => action = Action::Tree(None),
+            ("--tree", Some(depth)) => match depth.parse() {
+                Ok(depth) if depth > 0 => action = Action::Tree(Some(depth)),
+                _ => {
+                    eprintln!("Invalid depth for --tree: {}", depth);
+                    return 1;
+                }
+            },
+            ("--match", Some(pattern)) => match_pattern = Some(pattern.to_owned()),
+            ("--invert-match", Some(pattern)) => invert_match_pattern = Some(pattern.to_owned()),
+            ("--match-regex", None) => match_regex = true,
+            ("--only-with" | "--only-without", Some(key)) if !RESERVED_KEYS.contains(&key) => {
+                eprintln!("Unknown key for {}: {}", flag, key);
+                return 1;
+            }
+            ("--only-with", Some(key)) => filter.with_key = Some(key.to_owned()),
+            ("--only-without", Some(key)) => filter.without_key = Some(key.to_owned()),
+            ("--show-secrets", None) => show_secrets = true,
+            ("--verbose" | "-v", None) => verbosity += 1,
+            ("--no-stdin", None) => overrides.no_stdin = true,
+            ("--stdin-from-args", None) => overrides.stdin_from_args = true,
+            ("--trace-shell", None) => overrides.trace_shell = true,
+            ("--no-cache", None) => overrides.no_cache = true,
+            ("--refresh", None) => overrides.refresh = true,
+            ("--timeout", Some(timeout)) => match parse_timeout(timeout) {
+                Some(timeout) => overrides.timeout = Some(timeout),
+                None => {
+                    eprintln!("Invalid timeout: {}", timeout);
+                    return 1;
+                }
+            },
+            ("--steps", Some(names)) => {
+                overrides.steps = Some(names.split(',').map(|name| name.to_string()).collect())
+            }
+            ("--config" | "-c", Some(_)) | ("--no-local", None) => {} // already found
+            ("--run-from", Some(dir)) => match env::current_dir() {
+                Ok(current_dir) => overrides.run_from = Some(resolve_dir(dir, &current_dir)),
+                Err(err) => return report(Err(err.into())),
+            },
+            ("--color", Some(value)) => {
+                color = match value {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    unknown => {
+                        eprintln!("Unknown color: {}", unknown);
+                        return 1;
+                    }
+                }
+            }
+            ("--format", Some(value)) => {
+                format = match value {
+                    "text" => OutputFormat::Text,
+                    "json" => OutputFormat::Json,
+                    "toml" => OutputFormat::Toml,
+                    "fzf" => OutputFormat::Fzf,
+                    "json-lines" => OutputFormat::JsonLines,
+                    unknown => {
+                        eprintln!("Unknown format: {}", unknown);
+                        return 1;
+                    }
+                }
+            }
+            (flag, Some(value)) => {
+                eprintln!("Unknown flag: {}={}", flag, value);
+                return 1;
+            }
+            (flag, None) => {
+                eprintln!("Unknown flag: {}", flag);
+                return 1;
+            }
+        }
+    }
+
+    if action == Action::Version {
+        return version_runner(format);
+    }
+
+    for (flag, pattern, regex) in [
+        ("--match", match_pattern, &mut filter.include),
+        ("--invert-match", invert_match_pattern, &mut filter.exclude),
+    ] {
+        if let Some(pattern) = pattern {
+            match CommandFilter::pattern(&pattern, match_regex) {
+                Ok(pattern) => *regex = Some(pattern),
+                Err(err) => {
+                    eprintln!("Invalid regex for {}: {}", flag, err);
+                    return 1;
+                }
+            }
+        }
+    }
+
+    if overrides.no_stdin && overrides.stdin_from_args {
+        eprintln!("Error: --stdin-from-args cannot be used with --no-stdin");
+        return 1;
+    }
+
+    if command.is_empty()
+        && !matches!(
+            action,
+            Action::Help
+                | Action::List
+                | Action::Tree(_)
+                | Action::Check
+                | Action::Completions(_)
+                | Action::InitCompletions
+                | Action::Init
+                | Action::Where
+        )
+    {
+        eprintln!("Error: No command provided");
+        return 1;
+    }
+
+    if action == Action::CompareConfig {
+        return report(compare_runner(&command));
+    }
+
+    // The config is created rather than found, so this must not depend on it existing.
+    if action == Action::Init {
+        return report(init_runner(config, force));
+    }
+
+    let logger = Logger {
+        level: verbosity,
+        show_secrets,
+    };
+    let path = match find_config(config, local) {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            eprintln!(
+                "Error: No config found in --config, $XRUN_CONFIG, the current directory or its \
+                 parents, or the config directory (in that order of precedence)\nRun `{} --init` \
+                 to create a sample config",
+                PROG_NAME
+            );
+            return 1;
+        }
+        Err(err) => return report(Err(err)),
+    };
+    logger.log(1, &format!("config: {}", path.display()));
+    if action == Action::Where {
+        // Printed before the config is read so a broken config can still be located.
+        return report(where_runner(&path));
+    }
+    let path: &Path = path.as_path();
+    let result = match env::var("XRUN_CONFIG_SHA256") {
+        Ok(expected) => verify_config_hash(path, &expected),
+        Err(_) => Ok(()),
+    }
+    .and_then(|_| verify_has_commands(path))
+    .and_then(|_| match action {
+        Action::Exec => command_runner(path, &command, passthrough, stats, &overrides, &logger),
+        Action::Help => {
+            let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+            let color = color.use_color(no_color, io::stdout().is_terminal());
+            help_runner(path, &command, all, context, color, &logger)
+        }
+        Action::DumpResolved => dump_runner(path, &command, &overrides),
+        Action::PrintShell => shell_runner(path, &command),
+        Action::EnvDump => env_dump_runner(path, &command, show_secrets),
+        Action::PrintEnvFile => env_file_runner(path, &command),
+        Action::Describe => describe_runner(path, &command),
+        Action::DryRun => dry_run_runner(path, &command, &overrides, show_secrets),
+        Action::TraceResolve => trace_resolve_runner(path, &command),
+        Action::Check => check_runner(path, &command),
+        Action::Completions(shell) => completions_runner(path, shell),
+        Action::InitCompletions => init_completions_runner(path, force),
+        Action::List if count => count_runner(path, &command, format, all, &filter),
+        Action::List => list_runner(path, &command, format, all, &filter, show_secrets),
+        Action::Tree(depth) => tree_runner(path, &command, depth, all, &filter),
+        // handled before the config is needed
+        Action::Version | Action::CompareConfig | Action::Init | Action::Where => unreachable!(),
+    });
+    report(result)
+}
+
+/// Reports the result of a runner.
+///
+/// returns - The exit code of the runner, or the exit code of its error after printing it.
+fn report(result: Result<i32, CommandParseError>) -> i32 {
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            err.exit_code()
+        }
+    }
+}
+
+/// Finds the config file, which is the first of `config`, `$XRUN_CONFIG`, a project-local config
+/// (see `find_local_config`) and `command.toml` in the config directory that is found.
+///
+/// * `config` - The config file given by `--config`.
+/// * `local` - Whether to look for a project-local config.
+///
+/// returns - The config file, `None` if none is found, or `CommandParseError::ConfigNotFound` if
+/// the config file given by `config` or `$XRUN_CONFIG` does not exist.
+fn find_config(config: Option<&Path>, local: bool) -> Result<Option<PathBuf>, CommandParseError> {
+    let config = config
+        .map(|config| config.to_owned())
+        .or_else(|| env::var_os("XRUN_CONFIG").map(PathBuf::from));
+    match config {
+        Some(config) if config.exists() => Ok(Some(config)),
+        Some(config) => Err(CommandParseError::ConfigNotFound(config)),
+        None => Ok(env::current_dir()
+            .ok()
+            .filter(|_| local)
+            .and_then(|current_dir| find_local_config(&current_dir))
+            .or_else(|| {
+                xdg::BaseDirectories::with_prefix(PROG_NAME).find_config_file("command.toml")
+            })),
+    }
+}
+
+/// Finds the config of the project `dir` is in, i.e. the `.xrun.toml` or `command.toml` in `dir`
+/// or the closest of its parents. Directories that can't be read are skipped.
+///
+/// * `dir` - The directory to start looking from.
+fn find_local_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|dir| [dir.join(".xrun.toml"), dir.join("command.toml")])
+        .find(|config| config.is_file())
+}
+
+fn command_runner(
+    path: &Path,
+    command: &[&str],
+    passthrough: bool,
+    stats: bool,
+    overrides: &CliOverrides,
+    logger: &Logger,
+) -> Result<i32, CommandParseError> {
+    if logger.level >= 2 {
+        for line in trace_command_resolution(path, command)? {
+            logger.log(2, &format!("resolve: {}", line));
+        }
+    }
+    let steps = overrides.get_steps(path, command)?;
+    if passthrough {
+        println!("{}", join_steps(&steps));
+        // Arbitrary exit code to indicate a shell command was returned.
+        return Ok(125);
+    }
+    // The timeout covers the whole sequence rather than each step.
+    let start = Instant::now();
+    let deadline = steps[0].timeout.map(|timeout| start + timeout);
+    let mut summary = RunSummary {
+        steps: steps.len(),
+        ..Default::default()
+    };
+    let mut result = Ok(0);
+    for step in &steps {
+        result = execute(step, deadline, logger);
+        summary.exit_codes.push(result.as_ref().ok().copied());
+        // Errors executing a step aren't ignored, only its exit code.
+        match result {
+            Ok(exit_code) if exit_code != 0 && !step.ignore_errors => break,
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    summary.duration = start.elapsed();
+    if stats {
+        for line in summary.lines() {
+            eprintln!("{}: {}", PROG_NAME, line);
+        }
+    }
+    result
+}
+
+/// The outcome of running the steps of a command, printed by `--stats`.
+#[derive(Default)]
+struct RunSummary {
+    /// The number of steps of the command.
+    steps: usize,
+    /// The exit code of each step that ran, in order, or `None` if it couldn't be executed or
+    /// timed out.
+    exit_codes: Vec<Option<i32>>,
+    /// How long the steps took to run.
+    duration: Duration,
+}
+
+impl RunSummary {
+    /// Renders the summary: the number of steps that succeeded, failed and didn't run, the exit
+    /// code of each step, and the total duration.
+    fn lines(&self) -> Vec<String> {
+        let succeeded = self
+            .exit_codes
+            .iter()
+            .filter(|&&code| code == Some(0))
+            .count();
+        let mut lines = vec![format!(
+            "steps: {} ({} succeeded, {} failed, {} not run)",
+            self.steps,
+            succeeded,
+            self.exit_codes.len() - succeeded,
+            self.steps - self.exit_codes.len()
+        )];
+        for i in 0..self.steps {
+            let outcome = match self.exit_codes.get(i) {
+                Some(Some(exit_code)) => format!("exit {}", exit_code),
+                Some(None) => "error".to_string(),
+                None => "not run".to_string(),
+            };
+            lines.push(format!("step {}: {}", i + 1, outcome));
+        }
+        lines.push(format!("duration: {:.2}s", self.duration.as_secs_f64()));
+        lines
+    }
+}
+
+/// Joins the steps of a command into a single shell command, which stops at the first failing
+/// step unless it ignores errors.
+fn join_steps(steps: &[ResolvedCommand]) -> String {
+    let mut joined = String::new();
+    for (i, step) in steps.iter().enumerate() {
+        joined += &step.command;
+        if i < steps.len() - 1 {
+            joined += if step.ignore_errors { "; " } else { " && " };
+        }
+    }
+    joined
+}
+
+/// Executes a resolved command.
+///
+/// * `resolved` - The command to execute.
+/// * `deadline` - When the command is killed if it is still running, or `None` to let it run
+///   indefinitely.
+/// * `logger` - Logs how the command is executed.
+///
+/// returns - The exit code of the command, or the error that occurred while executing it,
+/// including `CommandParseError::TimedOut` if it was killed at `deadline`.
+fn execute(
+    resolved: &ResolvedCommand,
+    deadline: Option<Instant>,
+    logger: &Logger,
+) -> Result<i32, CommandParseError> {
+    // Commands executed directly have no shell, which an empty shell stands in for.
+    let (mut command, shell) = match &resolved.argv {
+        Some(argv) => {
+            let mut command = Command::new(&argv[0]);
+            command.args(&argv[1..]);
+            (command, String::new())
+        }
+        None => {
+            let shell = select_shell(resolved)?;
+            if resolved.strict && strict_prelude(&shell).is_none() {
+                eprintln!(
+                    "Warning: strict mode is not supported by {}, ignoring",
+                    shell
+                );
+            }
+            let mut command = Command::new(&shell);
+            command.args(shell_argv(
+                &shell,
+                &resolved.shell_convention(&shell),
+                &resolved.shell_args,
+                resolved.trace,
+                &resolved.shell_command(&shell),
+            ));
+            (command, shell)
+        }
+    };
+    if let Some(cwd) = &resolved.cwd {
+        if !cwd.is_dir() {
+            return Err(CommandParseError::CwdNotFound(cwd.to_owned()));
+        }
+        command.current_dir(cwd);
+    }
+    logger.log_execution(resolved, &shell);
+    let cache_file = match resolved.cache {
+        Some(ttl) => {
+            let cwd = match &resolved.cwd {
+                Some(cwd) => cwd.to_owned(),
+                None => env::current_dir()?,
+            };
+            let cache_file = cache::cache_file(resolved, &shell, &cwd)?;
+            if let Some(output) = cache::read_fresh(&cache_file, ttl, SystemTime::now()) {
+                io::stdout().write_all(&output)?;
+                return Ok(0);
+            }
+            Some(cache_file)
+        }
+        None => None,
+    };
+    command
+        .envs(&resolved.env)
+        .stdout(match cache_file {
+            Some(_) => Stdio::piped(),
+            None => Stdio::inherit(),
+        })
+        .stdin(match resolved.stdin {
+            StdinMode::Inherit => Stdio::inherit(),
+            StdinMode::Null => Stdio::null(),
+            StdinMode::Data(_) => Stdio::piped(),
+        })
+        .stderr(Stdio::inherit());
+    // A command that may time out gets its own process group, so the processes it starts are
+    // stopped along with it.
+    let tty = deadline.and_then(|_| foreground_tty());
+    if deadline.is_some() {
+        command.process_group(0);
+    }
+    if let Some(tty) = tty {
+        // Set by both the command and xrun, whichever runs first, so the command never starts in
+        // the background.
+        // SAFETY: only async-signal-safe functions are called between fork and exec.
+        unsafe {
+            command.pre_exec(move || {
+                set_foreground(tty, libc::getpid());
+                Ok(())
+            });
+        }
+    }
+
+    let mut proc = match (command.spawn(), &resolved.argv) {
+        (Err(err), Some(argv)) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(CommandParseError::ProgramNotFound(argv[0].to_owned()))
+        }
+        (proc, _) => proc?,
+    };
+    let _foreground = tty.map(|tty| Foreground::new(tty, proc.id() as libc::pid_t));
+    if let (Some(mut stdin), StdinMode::Data(data)) = (proc.stdin.take(), &resolved.stdin) {
+        let data = data.to_owned();
+        // Written on another thread so a command writing lots of output before reading its stdin
+        // can't deadlock with xrun. Errors mean the command exited or closed its stdin early,
+        // which is up to the command.
+        thread::spawn(move || stdin.write_all(data.as_bytes()));
+    }
+    // Copied on another thread so the deadline is still enforced while the command is writing.
+    let output = proc
+        .stdout
+        .take()
+        .map(|stdout| thread::spawn(move || cache::tee(stdout, io::stdout())));
+    let status = match deadline {
+        Some(deadline) => wait_until(&mut proc, deadline, KILL_GRACE_PERIOD)?,
+        None => Some(proc.wait()?),
+    };
+    let exit_code = match status {
+        Some(status) => status_exit_code(status),
+        // The output isn't waited for, since processes the command started may still hold it.
+        None => {
+            return Err(CommandParseError::TimedOut(
+                resolved.timeout.unwrap_or_default(),
+            ))
+        }
+    };
+    let output = match output {
+        Some(tee) => Some(tee.join().expect("Copying the output panicked")?),
+        None => None,
+    };
+    // Only successful output is cached so failures are retried.
+    if let (Some(cache_file), Some(output)) = (cache_file, output) {
+        if exit_code == 0 {
+            if let Err(err) = fs::write(&cache_file, output) {
+                eprintln!("Warning: failed to cache output: {}", err);
+            }
+        }
+    }
+    Ok(exit_code)
+}
+
+/// Finds the terminal xrun is in the foreground process group of. A command in its own process
+/// group has to be given the terminal to read from it, and interactive shells stop until they
+/// are.
+///
+/// returns - A file descriptor of the terminal, or `None` if there is none or xrun is in the
+/// background.
+fn foreground_tty() -> Option<RawFd> {
+    [
+        io::stdin().as_raw_fd(),
+        io::stderr().as_raw_fd(),
+        io::stdout().as_raw_fd(),
+    ]
+    .into_iter()
+    .find(|&fd| unsafe { libc::isatty(fd) == 1 && libc::tcgetpgrp(fd) == libc::getpgrp() })
+}
+
+/// Makes a process group the foreground process group of a terminal. SIGTTOU, which a process
+/// outside the foreground process group doing so is sent, is ignored meanwhile.
+///
+/// * `tty` - A file descriptor of the terminal.
+/// * `pgid` - The process group.
+fn set_foreground(tty: RawFd, pgid: libc::pid_t) {
+    unsafe {
+        let handler = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        libc::tcsetpgrp(tty, pgid);
+        libc::signal(libc::SIGTTOU, handler);
+    }
+}
+
+/// The terminal given to a command's process group, which is given back to xrun's when dropped.
+struct Foreground(RawFd);
+
+impl Foreground {
+    /// Gives a terminal to a process group.
+    ///
+    /// * `tty` - A file descriptor of the terminal.
+    /// * `pgid` - The process group.
+    fn new(tty: RawFd, pgid: libc::pid_t) -> Foreground {
+        set_foreground(tty, pgid);
+        Foreground(tty)
+    }
+}
+
+impl Drop for Foreground {
+    fn drop(&mut self) {
+        set_foreground(self.0, unsafe { libc::getpgrp() });
+    }
+}
+
+/// Sends a signal to the process group of a command, which leads it.
+///
+/// * `proc` - The command.
+/// * `signal` - The signal to send.
+fn signal_group(proc: &Child, signal: libc::c_int) {
+    // Fails only if every process of the group has exited.
+    unsafe { libc::kill(-(proc.id() as libc::pid_t), signal) };
+}
+
+/// Waits for a command leading its own process group to exit until `deadline`, after which the
+/// group is sent SIGTERM, and then SIGKILL if the command is still running after `grace_period`.
+///
+/// * `proc` - The command.
+/// * `deadline` - When the command is stopped.
+/// * `grace_period` - How long the command has to exit after SIGTERM.
+///
+/// returns - The exit status of the command, `None` if it was stopped, or the error that occurred
+/// while waiting for it.
+fn wait_until(
+    proc: &mut Child,
+    deadline: Instant,
+    grace_period: Duration,
+) -> io::Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = proc.try_wait()? {
+            return Ok(Some(status));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::sleep(WAIT_INTERVAL.min(deadline - now));
+    }
+    signal_group(proc, libc::SIGTERM);
+    let grace_deadline = Instant::now() + grace_period;
+    while Instant::now() < grace_deadline {
+        if proc.try_wait()?.is_some() {
+            return Ok(None);
+        }
+        thread::sleep(WAIT_INTERVAL);
+    }
+    signal_group(proc, libc::SIGKILL);
+    proc.wait()?;
+    Ok(None)
+}
+
+/// Translates the exit status of a command into the exit code xrun exits with, which is the
+/// command's own exit code, or 128 plus the signal number like shells if a signal killed it.
+fn status_exit_code(status: ExitStatus) -> i32 {
+    match status.code() {
+        Some(code) => code,
+        None => match status.signal() {
+            Some(signal) => 128 + signal,
+            None => {
+                panic!("Unknown exit status {:?}", status);
+            }
+        },
+    }
+}
+
+fn dump_runner(
+    path: &Path,
+    command: &[&str],
+    overrides: &CliOverrides,
+) -> Result<i32, CommandParseError> {
+    let steps = overrides.get_steps(path, command)?;
+    // Commands executed directly are shell-quoted, so any POSIX shell reproduces them.
+    let shell = match steps[0].argv {
+        Some(_) => "sh".to_string(),
+        None => select_shell(&steps[0])?,
+    };
+    let mut script = steps[0].to_script(&shell, &env::current_dir()?);
+    for (prev, step) in steps.iter().zip(&steps[1..]) {
+        if !prev.ignore_errors {
+            script.pop();
+            script += " || exit\n";
+        }
+        script += &step.shell_command(&shell);
+        script += "\n";
+    }
+    print!("{}", script);
+    Ok(0)
+}
+
+fn shell_runner(path: &Path, command: &[&str]) -> Result<i32, CommandParseError> {
+    let resolved = get_command(path, command)?;
+    if resolved.argv.is_some() {
+        println!("none");
+        return Ok(0);
+    }
+    let shell = select_shell(&resolved)?;
+    let argv = shell_argv(
+        &shell,
+        &resolved.shell_convention(&shell),
+        &resolved.shell_args,
+        resolved.trace,
+        &resolved.command,
+    );
+    // Only show the shell's options, not the '-c <command>' that follows them.
+    let options = &argv[..argv.len() - 2];
+    if options.is_empty() {
+        println!("{}", shell);
+    } else {
+        println!("{} {}", shell, options.join(" "));
+    }
+    Ok(0)
+}
+
+fn list_runner(
+    path: &Path,
+    command: &[&str],
+    format: OutputFormat,
+    all: bool,
+    filter: &CommandFilter,
+    show_secrets: bool,
+) -> Result<i32, CommandParseError> {
+    match format {
+        OutputFormat::Text | OutputFormat::Fzf => {}
+        OutputFormat::Toml => {
+            print!("{}", export_command_toml(path, command)?);
+            return Ok(0);
+        }
+        OutputFormat::Json | OutputFormat::JsonLines => {
+            let objects: Vec<String> = get_command_details(path, command, all)?
+                .iter()
+                .filter(|details| {
+                    let entry = &details.entry;
+                    filter.matches(&entry.path, entry.desc.as_deref(), &entry.keys)
+                })
+                .map(|details| details_json(details, show_secrets))
+                .collect();
+            if format == OutputFormat::Json {
+                println!("[{}]", objects.join(","));
+            } else {
+                for object in objects {
+                    println!("{}", object);
+                }
+            }
+            return Ok(0);
+        }
+    }
+    let entries = get_command_list(path, command, all)?
+        .into_iter()
+        .filter(|entry| filter.matches(&entry.path, entry.desc.as_deref(), &entry.keys));
+    for entry in entries {
+        if format == OutputFormat::Fzf {
+            let desc = entry.desc.unwrap_or_default();
+            println!("{}\t{}", fzf_field(&entry.path.join(" ")), fzf_field(&desc));
+            continue;
+        }
+        let mut line = entry.path.join(" ");
+        if let Some(title) = entry.title {
+            line += &format!(" ({})", title);
+        }
+        if entry.hidden {
+            line += " [hidden]";
+        }
+        if let Some(desc) = entry.desc {
+            line += &format!(": {}", desc);
+        }
+        println!("{}", line);
+    }
+    Ok(0)
+}
+
+/// Serializes the details of a command for `--list --format=json`. Only the keys of its `env` are
+/// included, unless `show_secrets`, in which case the values are too, under `env_values`.
+///
+/// returns - The details as a JSON object.
+fn details_json(details: &CommandDetails, show_secrets: bool) -> String {
+    let entry = &details.entry;
+    let string_or_null = |s: Option<&str>| s.map_or("null".to_string(), json_string);
+    let group = (entry.path.len() > 1).then(|| entry.path[..entry.path.len() - 1].join(" "));
+    let cwd = details.cwd.as_ref().map(|cwd| cwd.to_string_lossy());
+    let mut fields = vec![
+        ("path", json_string(&entry.path.join(" "))),
+        ("group", string_or_null(group.as_deref())),
+        ("title", string_or_null(entry.title.as_deref())),
+        ("desc", string_or_null(entry.desc.as_deref())),
+        ("hidden", entry.hidden.to_string()),
+        (
+            "steps",
+            json_array(details.steps.iter().map(|step| json_string(step))),
+        ),
+        ("cwd", string_or_null(cwd.as_deref())),
+        (
+            "env",
+            json_array(details.env.keys().map(|key| json_string(key))),
+        ),
+        (
+            "timeout",
+            details
+                .timeout
+                .map_or("null".to_string(), |timeout| timeout.as_secs().to_string()),
+        ),
+        ("source", json_string(&details.source.to_string_lossy())),
+    ];
+    if show_secrets {
+        let values = details
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value)));
+        fields.push((
+            "env_values",
+            format!("{{{}}}", values.collect::<Vec<_>>().join(",")),
+        ));
+    }
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|(name, value)| format!("{}:{}", json_string(name), value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Serializes JSON values into a JSON array.
+fn json_array(values: impl Iterator<Item = String>) -> String {
+    format!("[{}]", values.collect::<Vec<String>>().join(","))
+}
+
+/// Quotes `s` as a JSON string, escaping `"`, `\` and control characters.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            '\t' => quoted += "\\t",
+            c if c.is_control() => quoted += &format!("\\u{:04x}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Prints the number of runnable (sub)commands at or beneath a (sub)command, along with the number
+/// of (sub)commands beneath it that only group others.
+fn count_runner(
+    path: &Path,
+    command: &[&str],
+    format: OutputFormat,
+    all: bool,
+    filter: &CommandFilter,
+) -> Result<i32, CommandParseError> {
+    let runnable = get_command_list(path, command, all)?
+        .iter()
+        .filter(|entry| filter.matches(&entry.path, entry.desc.as_deref(), &entry.keys))
+        .count();
+    let mut nodes = get_command_tree(path, command, all)?;
+    if !filter.is_empty() {
+        let mut command_path = command.iter().map(|s| s.to_string()).collect();
+        nodes = filter_tree(nodes, &mut command_path, filter);
+    }
+    let namespaces = count_namespaces(&nodes);
+    match format {
+        OutputFormat::Text => println!("runnable: {}\nnamespaces: {}", runnable, namespaces),
+        OutputFormat::Json => println!(
+            "{{\"runnable\":{},\"namespaces\":{}}}",
+            runnable, namespaces
+        ),
+        OutputFormat::Toml => return Ok(unsupported_format("--count", "toml")),
+        OutputFormat::Fzf => return Ok(unsupported_format("--count", "fzf")),
+        OutputFormat::JsonLines => return Ok(unsupported_format("--count", "json-lines")),
+    }
+    Ok(0)
+}
+
+/// Counts the (sub)commands in a tree that only group others.
+fn count_namespaces(nodes: &[CommandNode]) -> usize {
+    nodes
+        .iter()
+        .map(|node| usize::from(!node.is_command) + count_namespaces(&node.children))
+        .sum()
+}
+
+/// Makes `s` fit in a single field of a line of `--format=fzf` output by replacing the tabs and
+/// line breaks in it, which separate fields and lines, with spaces.
+fn fzf_field(s: &str) -> String {
+    s.replace(['\t', '\r', '\n'], " ")
+}
+
+fn tree_runner(
+    path: &Path,
+    command: &[&str],
+    depth: Option<usize>,
+    all: bool,
+    filter: &CommandFilter,
+) -> Result<i32, CommandParseError> {
+    let mut nodes = get_command_tree(path, command, all)?;
+    if !filter.is_empty() {
+        let mut command_path = command.iter().map(|s| s.to_string()).collect();
+        nodes = filter_tree(nodes, &mut command_path, filter);
+    }
+    if !command.is_empty() {
+        println!("{}", command.join(" "));
+    }
+    for line in tree_lines(&nodes, "", depth) {
+        println!("{}", line);
+    }
+    Ok(0)
+}
+
+/// Removes the (sub)commands that don't pass `filter` from a tree, keeping those with (sub)commands
+/// beneath them that do.
+///
+/// * `nodes` - The (sub)commands to filter.
+/// * `command_path` - The path of the parent of `nodes`. Restored to its original value on return.
+/// * `filter` - The filter the (sub)commands must pass.
+///
+/// returns - The remaining (sub)commands.
+fn filter_tree(
+    nodes: Vec<CommandNode>,
+    command_path: &mut Vec<String>,
+    filter: &CommandFilter,
+) -> Vec<CommandNode> {
+    let mut kept = vec![];
+    for mut node in nodes {
+        command_path.push(node.name.to_owned());
+        node.children = filter_tree(node.children, command_path, filter);
+        if !node.children.is_empty()
+            || (node.is_command && filter.matches(command_path, node.desc.as_deref(), &node.keys))
+        {
+            kept.push(node);
+        }
+        command_path.pop();
+    }
+    kept
+}
+
+/// Renders (sub)commands as a tree like a directory listing. (Sub)commands that only group
+/// others are suffixed with a `/`.
+///
+/// * `nodes` - The (sub)commands to render.
+/// * `prefix` - The prefix of each line, drawing the branches of the parents of `nodes`.
+/// * `depth` - The number of levels to render, or `None` to render every level.
+fn tree_lines(nodes: &[CommandNode], prefix: &str, depth: Option<usize>) -> Vec<String> {
+    let mut lines = vec![];
+    if depth == Some(0) {
+        return lines;
+    }
+    for (i, node) in nodes.iter().enumerate() {
+        let last = i == nodes.len() - 1;
+        let mut line = format!(
+            "{}{}{}",
+            prefix,
+            if last { "└── " } else { "├── " },
+            node.name
+        );
+        if !node.is_command {
+            line += "/";
+        }
+        if let Some(title) = &node.title {
+            line += &format!(" ({})", title);
+        }
+        if node.hidden {
+            line += " [hidden]";
+        }
+        if let Some(desc) = &node.desc {
+            line += &format!(": {}", desc);
+        }
+        lines.push(line);
+        let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        lines.extend(tree_lines(
+            &node.children,
+            &child_prefix,
+            depth.map(|depth| depth - 1),
+        ));
+    }
+    lines
+}
+
+fn dry_run_runner(
+    path: &Path,
+    command: &[&str],
+    overrides: &CliOverrides,
+    show_secrets: bool,
+) -> Result<i32, CommandParseError> {
+    let steps = overrides.get_steps(path, command)?;
+    let shell = match steps[0].argv {
+        Some(_) => "none".to_string(),
+        None => select_shell(&steps[0])?,
+    };
+    let cwd = match &steps[0].cwd {
+        Some(cwd) => cwd.to_owned(),
+        None => env::current_dir()?,
+    };
+    println!("shell: {}", shell);
+    println!("cwd: {}", cwd.display());
+    for (key, value) in &steps[0].env {
+        if !show_secrets && is_secret(key) {
+            println!("env: {}=***", key);
+        } else {
+            println!("env: {}={}", key, value);
+        }
+    }
+    if let [step] = steps.as_slice() {
+        println!("command: {}", step.command);
+    } else {
+        for (i, step) in steps.iter().enumerate() {
+            println!("step {}: {}", i + 1, step.command);
+        }
+    }
+    Ok(0)
+}
+
+fn trace_resolve_runner(path: &Path, command: &[&str]) -> Result<i32, CommandParseError> {
+    for line in trace_command_resolution(path, command)? {
+        eprintln!("{}", line);
+    }
+    get_command(path, command)?;
+    Ok(0)
+}
+
+fn check_runner(path: &Path, command: &[&str]) -> Result<i32, CommandParseError> {
+    let problems = check_config(path, command)?;
+    for (command_path, reason) in &problems {
+        if command_path.is_empty() {
+            println!("<root>: {}", reason);
+        } else {
+            println!("{}: {}", command_path.join(" "), reason);
+        }
+    }
+    Ok(if problems.is_empty() { 0 } else { 1 })
+}
+
+fn completions_runner(path: &Path, shell: Shell) -> Result<i32, CommandParseError> {
+    let nodes = get_command_tree(path, &[], false)?;
+    print!("{}", completion_script(shell, PROG_NAME, &nodes));
+    Ok(0)
+}
+
+/// Writes a sample config to `config`, or to `command.toml` in the config directory if not given.
+fn init_runner(config: Option<&Path>, force: bool) -> Result<i32, CommandParseError> {
+    let path = match config {
+        Some(config) => {
+            if let Some(dir) = config.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            config.to_owned()
+        }
+        None => xdg::BaseDirectories::with_prefix(PROG_NAME).place_config_file("command.toml")?,
+    };
+    if path.exists() && !force {
+        eprintln!(
+            "Error: Config already exists: {}\nUse --force to overwrite it",
+            path.display()
+        );
+        return Ok(1);
+    }
+    fs::write(&path, SAMPLE_CONFIG)?;
+    println!("Wrote sample config to {}", path.display());
+    Ok(0)
+}
+
+fn where_runner(path: &Path) -> Result<i32, CommandParseError> {
+    println!("{}", std::path::absolute(path)?.display());
+    Ok(0)
+}
+
+fn compare_runner(args: &[&str]) -> Result<i32, CommandParseError> {
+    let (old, new, command) = match args {
+        [old, new, command @ ..] if !command.is_empty() => (old, new, command),
+        _ => {
+            eprintln!("Error: --compare-config expects two config files followed by a command");
+            return Ok(1);
+        }
+    };
+    let (old, new) = match (
+        get_command_steps(Path::new(old), command),
+        get_command_steps(Path::new(new), command),
+    ) {
+        // There is nothing to compare, which is most likely a mistyped command.
+        (Err(err), Err(_)) => return Err(err),
+        (old, new) => (old, new),
+    };
+    let fields = |steps: Result<Vec<ResolvedCommand>, CommandParseError>| match steps {
+        Ok(steps) => resolution_fields(&steps),
+        Err(err) => vec![("error".to_string(), err.to_string())],
+    };
+    let diff = diff_fields(&fields(old), &fields(new));
+    for line in &diff {
+        println!("{}", line);
+    }
+    Ok(if diff.is_empty() { 0 } else { 1 })
+}
+
+/// Lists what a resolved command executes as `(name, value)` pairs, leaving out options that are
+/// at their defaults.
+///
+/// * `steps` - The steps of the command, of which there is at least one.
+fn resolution_fields(steps: &[ResolvedCommand]) -> Vec<(String, String)> {
+    let mut fields = vec![];
+    if let [step] = steps {
+        fields.push(("command".to_string(), step.command.to_owned()));
+    } else {
+        for (i, step) in steps.iter().enumerate() {
+            fields.push((format!("step {}", i + 1), step.command.to_owned()));
+        }
+    }
+    let resolved = &steps[0];
+    let shell = match (&resolved.argv, &resolved.shell) {
+        (Some(_), _) => "none",
+        (None, Some(shell)) => shell,
+        (None, None) => "$SHELL",
+    };
+    fields.push(("shell".to_string(), shell.to_string()));
+    if !resolved.shell_args.is_empty() {
+        fields.push(("shell_args".to_string(), resolved.shell_args.join(" ")));
+    }
+    if let Some(cwd) = &resolved.cwd {
+        fields.push(("cwd".to_string(), cwd.display().to_string()));
+    }
+    for (key, value) in &resolved.env {
+        fields.push((format!("env {}", key), value.to_owned()));
+    }
+    if resolved.stdin == StdinMode::Null {
+        fields.push(("stdin".to_string(), "null".to_string()));
+    }
+    if let Some(cache) = resolved.cache {
+        fields.push(("cache".to_string(), format!("{}s", cache.as_secs())));
+    }
+    if let Some(timeout) = resolved.timeout {
+        fields.push(("timeout".to_string(), format!("{}s", timeout.as_secs())));
+    }
+    for (name, enabled) in [
+        ("strict", resolved.strict),
+        ("trace", resolved.trace),
+        ("ignore_errors", resolved.ignore_errors),
+    ] {
+        if enabled {
+            fields.push((name.to_string(), "true".to_string()));
+        }
+    }
+    fields
+}
+
+/// Diffs two lists of `(name, value)` pairs by name, in the order the names first appear.
+///
+/// returns - A `- name: value` line for each old value that changed or is gone and a
+/// `+ name: value` line for each new value that changed or was added.
+fn diff_fields(old: &[(String, String)], new: &[(String, String)]) -> Vec<String> {
+    let find = |fields: &[(String, String)], name: &str| {
+        fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.to_owned())
+    };
+    let mut names: Vec<&str> = vec![];
+    for (name, _) in old.iter().chain(new) {
+        if !names.contains(&name.as_str()) {
+            names.push(name);
+        }
+    }
+    let mut lines = vec![];
+    for name in names {
+        let (old_value, new_value) = (find(old, name), find(new, name));
+        if old_value != new_value {
+            if let Some(value) = old_value {
+                lines.push(format!("- {}: {}", name, value));
+            }
+            if let Some(value) = new_value {
+                lines.push(format!("+ {}: {}", name, value));
+            }
+        }
+    }
+    lines
+}
+
+fn init_completions_runner(path: &Path, force: bool) -> Result<i32, CommandParseError> {
+    let shell_env = env::var("SHELL").unwrap_or_default();
+    let shell = match Shell::from_name(&shell_env) {
+        Some(shell) => shell,
+        None => {
+            eprintln!(
+                "Error: Unsupported shell for --init-completions: '{}', use --completions with \
+                 bash, zsh or fish instead",
+                shell_env
+            );
+            return Ok(1);
+        }
+    };
+    let install_path = install_path(shell, PROG_NAME)?;
+    if install_path.exists() && !force {
+        eprintln!(
+            "Error: Completion script already exists: {}\nUse --force to overwrite it",
+            install_path.display()
+        );
+        return Ok(1);
+    }
+    let nodes = get_command_tree(path, &[], false)?;
+    fs::write(&install_path, completion_script(shell, PROG_NAME, &nodes))?;
+    println!("Wrote completion script to {}", install_path.display());
+    if shell == Shell::Zsh {
+        let dir = install_path.parent().unwrap_or(Path::new("."));
+        println!("Add {} to $fpath before compinit to load it", dir.display());
+    }
+    Ok(0)
+}
+
+fn describe_runner(path: &Path, command: &[&str]) -> Result<i32, CommandParseError> {
+    let help = get_command_help(path, command)?;
+    let desc = help
+        .pairs
+        .into_iter()
+        .find(|e| e.0.is_none())
+        .and_then(|e| e.1);
+    match desc.as_deref().and_then(|desc| desc.lines().next()) {
+        Some(line) => {
+            println!("{}", line);
+            Ok(0)
+        }
+        None => Ok(1),
+    }
+}
+
+fn env_dump_runner(
+    path: &Path,
+    command: &[&str],
+    show_secrets: bool,
+) -> Result<i32, CommandParseError> {
+    let resolved = get_command(path, command)?;
+    for (key, value) in resolved.environment() {
+        if !show_secrets && is_secret(&key) {
+            println!("{}=***", key);
+        } else {
+            println!("{}={}", key, value);
+        }
+    }
+    Ok(0)
+}
+
+fn env_file_runner(path: &Path, command: &[&str]) -> Result<i32, CommandParseError> {
+    let resolved = get_command(path, command)?;
+    for (key, value) in &resolved.env {
+        println!("{}={}", key, dotenv_quote(value));
+    }
+    Ok(0)
+}
+
+/// Quotes `value` for a dotenv file if needed. Values that need quoting are double quoted with
+/// `\`, `"`, `$` and newlines escaped.
+fn dotenv_quote(value: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "_-./:@%+,".contains(c);
+    if !value.is_empty() && value.chars().all(is_plain) {
+        return value.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' | '"' | '$' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted += "\\n",
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Guesses whether an environment variable holds a secret from its name.
+fn is_secret(key: &str) -> bool {
+    const SECRET_MARKERS: &[&str] = &[
+        "SECRET",
+        "TOKEN",
+        "PASSWORD",
+        "PASSWD",
+        "API_KEY",
+        "PRIVATE_KEY",
+        "CREDENTIAL",
+    ];
+    let key = key.to_uppercase();
+    SECRET_MARKERS.iter().any(|marker| key.contains(marker))
+}
+
+fn version_runner(format: OutputFormat) -> i32 {
+    let version = env!("CARGO_PKG_VERSION");
+    match format {
+        OutputFormat::Text => println!("{} {}", PROG_NAME, version),
+        OutputFormat::Json => {
+            println!("{{\"name\":\"{}\",\"version\":\"{}\"}}", PROG_NAME, version)
+        }
+        OutputFormat::Toml => return unsupported_format("--version", "toml"),
+        OutputFormat::Fzf => return unsupported_format("--version", "fzf"),
+        OutputFormat::JsonLines => return unsupported_format("--version", "json-lines"),
+    }
+    0
+}
+
+/// Reports that `flag` can't output `format`.
+///
+/// returns - The exit code to exit with.
+fn unsupported_format(flag: &str, format: &str) -> i32 {
+    eprintln!("Error: {} does not support --format={}", flag, format);
+    1
+}
+
+/// Selects the shell used to execute `resolved`, which is its own 'shell' if defined. Otherwise it
+/// is `$SHELL`, or `sh` if `$SHELL` is unset or, depending on its `missing_shell`, does not exist.
+///
+/// returns - The shell or `CommandParseError::ShellNotFound` if it does not exist and `resolved`
+/// does not fall back. The shell of `resolved` itself never falls back.
+fn select_shell(resolved: &ResolvedCommand) -> Result<String, CommandParseError> {
+    if let Some(shell) = &resolved.shell {
+        if shell_exists(shell) {
+            return Ok(shell.to_owned());
+        }
+        return Err(CommandParseError::ShellNotFound(shell.to_owned()));
+    }
+    let shell = match env::var("SHELL") {
+        Ok(shell) => shell,
+        Err(_) => return Ok("sh".to_string()),
+    };
+    if shell_exists(&shell) {
+        return Ok(shell);
+    }
+    match resolved.missing_shell {
+        MissingShell::Fallback => {
+            eprintln!(
+                "Warning: shell '{}' does not exist, falling back to sh",
+                shell
+            );
+            Ok("sh".to_string())
+        }
+        MissingShell::Error => Err(CommandParseError::ShellNotFound(shell)),
+    }
+}
+
+/// Checks whether `shell` exists, either as a path or as a program in `$PATH`.
+fn shell_exists(shell: &str) -> bool {
+    if shell.contains('/') {
+        return Path::new(shell).is_file();
+    }
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(shell).is_file()))
+}
+
+/// Builds the arguments passed to `shell` to execute `exec_command`.
+///
+/// * `shell` - The shell the arguments are for.
+/// * `convention` - How `shell` is invoked. See `ShellConvention`.
+/// * `shell_args` - Extra arguments inserted before the command.
+/// * `trace` - Whether the shell echoes each line it executes.
+/// * `exec_command` - The command for the shell to execute.
+fn shell_argv(
+    shell: &str,
+    convention: &ShellConvention,
+    shell_args: &[String],
+    trace: bool,
+    exec_command: &str,
+) -> Vec<String> {
+    let mut argv = vec![];
+    if let Some(interactive_flag) = &convention.interactive_flag {
+        argv.push(interactive_flag.to_owned());
+    };
+    argv.extend(shell_args.iter().cloned());
+    if trace && !shell.ends_with("fish") {
+        argv.push("-x".to_string());
+    }
+    argv.push(convention.command_flag.to_owned());
+    argv.push(exec_command.to_string());
+    argv
+}
+
+fn help_runner(
+    path: &Path,
+    command: &[&str],
+    all: bool,
+    context: bool,
+    color: bool,
+    logger: &Logger,
+) -> Result<i32, CommandParseError> {
+    if all {
+        let command_path: Vec<String> = command.iter().map(|s| s.to_string()).collect();
+        for (i, (sub_path, help)) in get_command_help_all(path, command)?.into_iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            let depth = sub_path.len() - command_path.len();
+            let sub_path: Vec<&str> = sub_path.iter().map(|s| s.as_str()).collect();
+            for line in help_lines(&sub_path, help, color) {
+                for line in line.split('\n') {
+                    if line.is_empty() {
+                        println!();
+                    } else {
+                        println!("{}{}", "    ".repeat(depth), line);
+                    }
+                }
+            }
+        }
+    } else if let Some(resolved) = get_help_passthrough(path, command)? {
+        return execute(&resolved, None, logger);
+    } else {
+        let mut lines = help_lines(command, get_command_help(path, command)?, color);
+        if context {
+            if let Some(breadcrumb) = breadcrumb_line(&get_command_ancestors(path, command)?) {
+                lines.insert(1, breadcrumb);
+            }
+        }
+        for line in lines {
+            println!("{}", line);
+        }
+    }
+    Ok(0)
+}
+
+/// Renders the (sub)commands a (sub)command is nested in, e.g. `part of: s (s desc) > c1`.
+///
+/// * `ancestors` - The (sub)commands, outermost first. See `get_command_ancestors`.
+///
+/// returns - The rendered line, or `None` if there are no ancestors.
+fn breadcrumb_line(ancestors: &[HelpPair]) -> Option<String> {
+    if ancestors.is_empty() {
+        return None;
+    }
+    let crumbs: Vec<String> = ancestors
+        .iter()
+        .map(|HelpPair(name, desc)| {
+            let name = name.as_deref().unwrap_or_default();
+            match desc {
+                Some(desc) => format!("{} ({})", name, desc),
+                None => name.to_string(),
+            }
+        })
+        .collect();
+    Some(format!("part of: {}", crumbs.join(" > ")))
+}
+
+/// Renders the help of a (sub)command.
+///
+/// * `command` - The path of the (sub)command.
+/// * `help` - The help information of the (sub)command.
+/// * `color` - Whether to bold the names of the subcommands and dim their descriptions.
+///
+/// returns - The lines of the rendered help.
+fn help_lines(command: &[&str], help: CommandHelp, color: bool) -> Vec<String> {
+    let CommandHelp {
+        pairs: help_pairs,
+        exit_codes,
+        usage,
+        titles,
+        aliases,
+    } = help;
+    let mut lines = vec![];
+    let usage = match usage {
+        Some(usage) => format!("usage: {}", usage),
+        None => {
+            let mut usage = format!("usage: {}", PROG_NAME);
+            for command in command {
+                usage += &format!(" {}", command);
+            }
+            if help_pairs.len() > 1 {
+                usage += " [command]";
+            }
+            usage
+        }
+    };
+    lines.push(usage);
+    let base_command = help_pairs.iter().find(|e| e.0.is_none());
+    let mut has_desc = false;
+    if let Some(help_pair) = base_command {
+        if let Some(desc) = &help_pair.1 {
+            lines.push(desc.to_owned());
+            has_desc = true;
+            if help_pairs.len() > 1 {
+                lines.push(String::new());
+            }
+        }
+    }
+    let has_commands = help_pairs.len() > 1;
+    if has_commands {
+        lines.push("commands:".to_string());
+        for HelpPair(cmd, desc) in help_pairs {
+            let cmd = cmd.map(|cmd| {
+                let mut name = paint(&cmd, BOLD, color);
+                if let Some(title) = titles.get(&cmd) {
+                    name += &format!(" ({})", title);
+                }
+                if let Some(aliases) = aliases.get(&cmd) {
+                    name += &format!(" (aliases: {})", aliases.join(", "));
+                }
+                name
+            });
+            match (cmd, desc) {
+                (Some(cmd), Some(desc)) => {
+                    lines.push(format!("    {}: {}", cmd, paint(&desc, DIM, color)))
+                }
+                (Some(cmd), None) => lines.push(format!("    {}", cmd)),
+                (None, _) => {} // already shown
+            }
+        }
+    }
+    if !exit_codes.is_empty() {
+        if has_desc || has_commands {
+            lines.push(String::new());
+        }
+        lines.push("exit codes:".to_string());
+        for (code, meaning) in exit_codes {
+            lines.push(format!("    {}: {}", code, meaning));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+    use crate::command_parser::CommandEntry;
+
+    #[test_case(None, &["├── a", "│   └── b/: group", "│       └── c (C)", "└── d"] ; "all")]
+    #[test_case(Some(1), &["├── a", "└── d"] ; "depth")]
+    fn test_tree_lines(depth: Option<usize>, expected: &[&str]) {
+        let node = |name: &str, is_command: bool, children: Vec<CommandNode>| CommandNode {
+            name: name.to_string(),
+            desc: None,
+            title: None,
+            is_command,
+            hidden: false,
+            keys: vec![],
+            children,
+        };
+        let c = CommandNode {
+            title: Some("C".to_string()),
+            ..node("c", true, vec![])
+        };
+        let b = CommandNode {
+            desc: Some("group".to_string()),
+            ..node("b", false, vec![c])
+        };
+        let nodes = [node("a", true, vec![b]), node("d", true, vec![])];
+        assert_eq!(tree_lines(&nodes, "", depth), expected);
+    }
+
+    #[test_case(None, None, false, true ; "no filter")]
+    #[test_case(Some("BUILD"), None, false, true ; "match path")]
+    #[test_case(Some("project"), None, false, true ; "match desc")]
+    #[test_case(Some("test"), None, false, false ; "no match")]
+    #[test_case(None, Some("build"), false, false ; "invert match")]
+    #[test_case(None, Some("test"), false, true ; "invert no match")]
+    #[test_case(Some("build"), Some("release"), false, false ; "match and invert match")]
+    #[test_case(Some("^c.*release$"), None, true, true ; "regex")]
+    #[test_case(Some("c.*release"), None, false, false ; "not regex")]
+    fn test_command_filter_matches(
+        include: Option<&str>,
+        exclude: Option<&str>,
+        is_regex: bool,
+        expected: bool,
+    ) {
+        let filter = CommandFilter {
+            include: include.map(|pattern| CommandFilter::pattern(pattern, is_regex).unwrap()),
+            exclude: exclude.map(|pattern| CommandFilter::pattern(pattern, is_regex).unwrap()),
+            ..Default::default()
+        };
+        let path = [
+            "cargo".to_string(),
+            "build".to_string(),
+            "release".to_string(),
+        ];
+        assert_eq!(
+            filter.matches(&path, Some("Build the project"), &[]),
+            expected
+        );
+    }
+
+    #[test_case(None, None, true ; "no filter")]
+    #[test_case(Some("timeout"), None, true ; "with")]
+    #[test_case(Some("desc"), None, false ; "with missing")]
+    #[test_case(None, Some("desc"), true ; "without")]
+    #[test_case(None, Some("timeout"), false ; "without present")]
+    fn test_command_filter_keys(with_key: Option<&str>, without_key: Option<&str>, expected: bool) {
+        let filter = CommandFilter {
+            with_key: with_key.map(|key| key.to_string()),
+            without_key: without_key.map(|key| key.to_string()),
+            ..Default::default()
+        };
+        let keys = ["command".to_string(), "timeout".to_string()];
+        assert_eq!(filter.matches(&["a".to_string()], None, &keys), expected);
+    }
+
+    #[test]
+    fn test_command_filter_invalid_regex() {
+        assert!(CommandFilter::pattern("(", true).is_err());
+        assert!(CommandFilter::pattern("(", false).is_ok());
+    }
+
+    #[test]
+    fn test_filter_tree() {
+        let node = |name: &str, is_command: bool, children: Vec<CommandNode>| CommandNode {
+            name: name.to_string(),
+            desc: None,
+            title: None,
+            is_command,
+            hidden: false,
+            keys: vec![],
+            children,
+        };
+        let nodes = vec![
+            node(
+                "a",
+                true,
+                vec![node("b", true, vec![]), node("c", true, vec![])],
+            ),
+            node("d", false, vec![node("e", true, vec![])]),
+            node("f", true, vec![]),
+        ];
+        let filter = CommandFilter {
+            include: Some(CommandFilter::pattern("a c", false).unwrap()),
+            ..Default::default()
+        };
+        let expected = vec![node("a", true, vec![node("c", true, vec![])])];
+        assert_eq!(filter_tree(nodes, &mut vec![], &filter), expected);
+    }
+
+    #[test]
+    fn test_count_namespaces() {
+        let node = |name: &str, is_command: bool, children: Vec<CommandNode>| CommandNode {
+            name: name.to_string(),
+            desc: None,
+            title: None,
+            is_command,
+            hidden: false,
+            keys: vec![],
+            children,
+        };
+        let nodes = [
+            node(
+                "a",
+                false,
+                vec![node("b", false, vec![node("c", true, vec![])])],
+            ),
+            node("d", true, vec![node("e", false, vec![])]),
+        ];
+        assert_eq!(count_namespaces(&nodes), 3);
+    }
+
+    #[test_case("a", r#""a""# ; "plain")]
+    #[test_case(r#"a "b" \c"#, r#""a \"b\" \\c""# ; "quotes and backslashes")]
+    #[test_case("a\tb\r\n", r#""a\tb\r\n""# ; "whitespace")]
+    #[test_case("\u{1b}[0m", r#""\u001b[0m""# ; "control")]
+    fn test_json_string(s: &str, expected: &str) {
+        assert_eq!(json_string(s), expected);
+    }
+
+    #[test_case(false, "" ; "secrets hidden")]
+    #[test_case(true, r#","env_values":{"TOKEN":"t"}"# ; "show secrets")]
+    fn test_details_json(show_secrets: bool, env_values: &str) {
+        let details = CommandDetails {
+            entry: CommandEntry {
+                path: vec!["g".to_string(), "c".to_string()],
+                desc: Some("c desc".to_string()),
+                title: None,
+                hidden: true,
+                keys: vec![],
+            },
+            steps: vec!["echo c".to_string()],
+            cwd: None,
+            env: BTreeMap::from([("TOKEN".to_string(), "t".to_string())]),
+            timeout: Some(Duration::from_secs(60)),
+            source: PathBuf::from("/c.toml"),
+        };
+        let expected = format!(
+            r#"{{"path":"g c","group":"g","title":null,"desc":"c desc","hidden":true,"steps":["echo c"],"cwd":null,"env":["TOKEN"],"timeout":60,"source":"/c.toml"{}}}"#,
+            env_values
+        );
+        assert_eq!(details_json(&details, show_secrets), expected);
+    }
+
+    #[test_case(3, &[Some(0), Some(2), Some(0)], &["steps: 3 (2 succeeded, 1 failed, 0 not run)", "step 1: exit 0", "step 2: exit 2", "step 3: exit 0"] ; "ignored failure")]
+    #[test_case(3, &[Some(0), None], &["steps: 3 (1 succeeded, 1 failed, 1 not run)", "step 1: exit 0", "step 2: error", "step 3: not run"] ; "stopped")]
+    fn test_run_summary_lines(steps: usize, exit_codes: &[Option<i32>], expected: &[&str]) {
+        let summary = RunSummary {
+            steps,
+            exit_codes: exit_codes.to_vec(),
+            duration: Duration::from_millis(1234),
+        };
+        let mut expected = expected.to_vec();
+        expected.push("duration: 1.23s");
+        assert_eq!(summary.lines(), expected);
+    }
+
+    #[test_case("a b", "a b" ; "plain")]
+    #[test_case("a\tb", "a b" ; "tab")]
+    #[test_case("a\r\nb", "a  b" ; "line breaks")]
+    fn test_fzf_field(s: &str, expected: &str) {
+        assert_eq!(fzf_field(s), expected);
+    }
+
+    #[test_case(&[], None ; "top level")]
+    #[test_case(&[("s", Some("s desc"))], Some("part of: s (s desc)") ; "parent")]
+    #[test_case(&[("a", None), ("b", Some("b desc"))], Some("part of: a > b (b desc)") ; "nested")]
+    fn test_breadcrumb_line(ancestors: &[(&str, Option<&str>)], expected: Option<&str>) {
+        let ancestors: Vec<HelpPair> = ancestors
+            .iter()
+            .map(|(name, desc)| HelpPair(Some(name.to_string()), desc.map(|s| s.to_string())))
+            .collect();
+        assert_eq!(breadcrumb_line(&ancestors).as_deref(), expected);
+    }
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test_case(&[("command", "a")], &[("command", "a")], &[] ; "same")]
+    #[test_case(&[("command", "a")], &[("command", "b")], &["- command: a", "+ command: b"] ; "changed")]
+    #[test_case(&[("command", "a"), ("cwd", "/")], &[("command", "a")], &["- cwd: /"] ; "removed")]
+    #[test_case(&[("command", "a")], &[("command", "a"), ("env A", "b")], &["+ env A: b"] ; "added")]
+    fn test_diff_fields(old: &[(&str, &str)], new: &[(&str, &str)], expected: &[&str]) {
+        assert_eq!(diff_fields(&fields(old), &fields(new)), expected);
+    }
+
+    #[test]
+    fn test_shell_argv_no_shell_args() {
+        assert_eq!(
+            shell_argv("sh", &ShellConvention::builtin("sh"), &[], false, "echo hi"),
+            vec!["-c", "echo hi"]
+        );
+    }
+
+    #[test]
+    fn test_shell_argv_shell_args_before_command() {
+        let shell_args = vec!["-o".to_string(), "pipefail".to_string()];
+        assert_eq!(
+            shell_argv(
+                "sh",
+                &ShellConvention::builtin("sh"),
+                &shell_args,
+                false,
+                "echo hi"
+            ),
+            vec!["-o", "pipefail", "-c", "echo hi"]
+        );
+    }
+
+    #[test]
+    fn test_shell_argv_shell_args_after_interactive() {
+        let shell_args = vec!["-o".to_string(), "pipefail".to_string()];
+        assert_eq!(
+            shell_argv(
+                "/bin/bash",
+                &ShellConvention::builtin("/bin/bash"),
+                &shell_args,
+                false,
+                "echo hi"
+            ),
+            vec!["-i", "-o", "pipefail", "-c", "echo hi"]
+        );
+    }
+
+    #[test]
+    fn test_shell_argv_trace() {
+        let shell_args = vec!["-e".to_string()];
+        assert_eq!(
+            shell_argv(
+                "sh",
+                &ShellConvention::builtin("sh"),
+                &shell_args,
+                true,
+                "echo hi"
+            ),
+            vec!["-e", "-x", "-c", "echo hi"]
+        );
+    }
+
+    #[test]
+    fn test_shell_argv_trace_fish() {
+        assert_eq!(
+            shell_argv(
+                "fish",
+                &ShellConvention::builtin("fish"),
+                &[],
+                true,
+                "echo hi"
+            ),
+            vec!["-i", "-c", "echo hi"]
+        );
+    }
+
+    #[test]
+    fn test_shell_argv_custom_convention() {
+        let convention = ShellConvention {
+            command_flag: "-Command".to_string(),
+            interactive_flag: Some("-Interactive".to_string()),
+        };
+        assert_eq!(
+            shell_argv("pwsh", &convention, &[], false, "echo hi"),
+            vec!["-Interactive", "-Command", "echo hi"]
+        );
+    }
+
+    #[test_case("sh", true ; "in path")]
+    #[test_case("/bin/sh", true ; "path")]
+    #[test_case("/bin", false ; "directory")]
+    #[test_case("/nonexistent/bash", false ; "missing path")]
+    #[test_case("nonexistent-shell", false ; "missing in path")]
+    fn test_shell_exists(shell: &str, expected: bool) {
+        assert_eq!(shell_exists(shell), expected);
+    }
+
+    #[test_case(ExitStatus::from_raw(0), 0 ; "success")]
+    #[test_case(ExitStatus::from_raw(3 << 8), 3 ; "exit code")]
+    #[test_case(ExitStatus::from_raw(9), 137 ; "signal")]
+    fn test_status_exit_code(status: ExitStatus, expected: i32) {
+        assert_eq!(status_exit_code(status), expected);
+    }
+
+    #[test_case(ColorChoice::Auto, false, true, true ; "auto terminal")]
+    #[test_case(ColorChoice::Auto, false, false, false ; "auto not terminal")]
+    #[test_case(ColorChoice::Auto, true, true, false ; "auto no color")]
+    #[test_case(ColorChoice::Always, true, false, true ; "always")]
+    #[test_case(ColorChoice::Never, false, true, false ; "never")]
+    fn test_use_color(choice: ColorChoice, no_color: bool, is_terminal: bool, expected: bool) {
+        assert_eq!(choice.use_color(no_color, is_terminal), expected);
+    }
+
+    #[test]
+    fn test_help_lines_color() {
+        let help = CommandHelp {
+            pairs: vec![
+                HelpPair(None, Some("s desc".to_string())),
+                HelpPair(Some("c1".to_string()), Some("c1 desc".to_string())),
+                HelpPair(Some("c2".to_string()), None),
+            ],
+            exit_codes: vec![],
+            usage: None,
+            titles: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+        };
+        assert_eq!(
+            help_lines(&["s"], help, true),
+            vec![
+                "usage: xrun s [command]",
+                "s desc",
+                "",
+                "commands:",
+                "    \x1b[1mc1\x1b[0m: \x1b[2mc1 desc\x1b[0m",
+                "    \x1b[1mc2\x1b[0m",
+            ]
+        );
+    }
+
+    #[test_case("exit 3", Some(3) ; "exited")]
+    #[test_case("exec sleep 10", None ; "terminated")]
+    #[test_case("trap '' TERM; exec sleep 10", None ; "killed")]
+    #[test_case("sleep 10; echo late", None ; "grandchild")]
+    #[test_case("trap '' TERM; sleep 10; echo late", None ; "grandchild killed")]
+    fn test_wait_until(command: &str, expected: Option<i32>) {
+        let mut proc = Command::new("sh")
+            .args(["-c", command])
+            .stdout(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let start = Instant::now();
+        let status = wait_until(
+            &mut proc,
+            start + Duration::from_millis(500),
+            Duration::from_millis(100),
+        )
+        .unwrap();
+        assert_eq!(status.map(status_exit_code), expected);
+        // Only reaches the end once every process holding the output, such as a sleep started
+        // by the command, has exited.
+        let mut output = String::new();
+        io::Read::read_to_string(&mut proc.stdout.take().unwrap(), &mut output).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    const RUNNER_TOML_COMMAND_DATA: &str = r#"
+        ok = { command = ["true"], shell = false, desc = "ok desc" }
+        fail = { command = ["false"], shell = false }
+    "#;
+
+    #[test_case("ok", 0 ; "success")]
+    #[test_case("fail", 1 ; "failure")]
+    fn test_command_runner(command: &str, expected: i32) {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), RUNNER_TOML_COMMAND_DATA).unwrap();
+        let exit_code = command_runner(
+            temp_file.path(),
+            &[command],
+            false,
+            false,
+            &CliOverrides::default(),
+            &Logger::default(),
+        );
+        assert_eq!(exit_code.unwrap(), expected);
+    }
+
+    #[test_case("ok", 0 ; "description")]
+    #[test_case("fail", 1 ; "no description")]
+    fn test_describe_runner(command: &str, expected: i32) {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), RUNNER_TOML_COMMAND_DATA).unwrap();
+        assert_eq!(
+            describe_runner(temp_file.path(), &[command]).unwrap(),
+            expected
+        );
+    }
+
+    #[test_case(&["--version"], 0 ; "version")]
+    #[test_case(&["--tree=0"], 1 ; "invalid flag value")]
+    #[test_case(&["--nope"], 1 ; "unknown flag")]
+    #[test_case(&["--format"], 1 ; "missing value")]
+    #[test_case(&["--compare-config", "a.toml"], 1 ; "compare usage")]
+    #[test_case(&["--config", "dne.toml", "c"], 1 ; "config not found")]
+    #[test_case(&["ok"], 0 ; "success")]
+    #[test_case(&["fail"], 1 ; "failure")]
+    #[test_case(&["nope"], 1 ; "not found")]
+    fn test_run_args(args: &[&str], expected: i32) {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), RUNNER_TOML_COMMAND_DATA).unwrap();
+        let mut raw_args = vec![
+            "--config".to_string(),
+            temp_file.path().display().to_string(),
+        ];
+        raw_args.extend(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(run_args(&raw_args), expected);
+    }
+
+    #[test]
+    fn test_help_runner() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), RUNNER_TOML_COMMAND_DATA).unwrap();
+        assert_eq!(
+            help_runner(
+                temp_file.path(),
+                &[],
+                false,
+                false,
+                false,
+                &Logger::default()
+            )
+            .unwrap(),
+            0
+        );
+        match help_runner(
+            temp_file.path(),
+            &["nope"],
+            false,
+            false,
+            false,
+            &Logger::default(),
+        )
+        .unwrap_err()
+        {
+            CommandParseError::CommandNotFoundError(component, _) => assert_eq!(component, "nope"),
+            err => panic!("Expected `CommandNotFoundError`, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_find_local_config() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let project = tmp_dir.path().join("project");
+        let sub_dir = project.join("a/b");
+        fs::create_dir_all(&sub_dir).unwrap();
+        fs::write(project.join("command.toml"), "").unwrap();
+        assert_eq!(
+            find_local_config(&sub_dir),
+            Some(project.join("command.toml"))
+        );
+        fs::write(project.join(".xrun.toml"), "").unwrap();
+        assert_eq!(
+            find_local_config(&sub_dir),
+            Some(project.join(".xrun.toml"))
+        );
+        fs::write(sub_dir.join("command.toml"), "").unwrap();
+        assert_eq!(
+            find_local_config(&sub_dir),
+            Some(sub_dir.join("command.toml"))
+        );
+    }
+
+    #[test_case("GITHUB_TOKEN", true ; "token")]
+    #[test_case("db_password", true ; "lowercase")]
+    #[test_case("AWS_SECRET_ACCESS_KEY", true ; "secret")]
+    #[test_case("PATH", false ; "not secret")]
+    #[test_case("KEYMAP", false ; "key prefix")]
+    fn test_is_secret(key: &str, expected: bool) {
+        assert_eq!(is_secret(key), expected);
+    }
+
+    #[test_case("debug", "debug" ; "plain")]
+    #[test_case("/usr/bin:/bin", "/usr/bin:/bin" ; "path")]
+    #[test_case("", r#""""# ; "empty")]
+    #[test_case("two words", r#""two words""# ; "space")]
+    #[test_case(r#"say "$HI"\"#, r#""say \"\$HI\"\\""# ; "escaped")]
+    #[test_case("a\nb", r#""a\nb""# ; "newline")]
+    fn test_dotenv_quote(value: &str, expected: &str) {
+        assert_eq!(dotenv_quote(value), expected);
+    }
+}
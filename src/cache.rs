@@ -0,0 +1,118 @@
+//! Caches the output of commands with a `cache` duration.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    command_parser::{sha256_hex, ResolvedCommand, StdinMode},
+    PROG_NAME,
+};
+
+/// Gets the file the output of a command is cached in, creating its directory if needed. The file
+/// is keyed by everything affecting the output of the command, including its arguments.
+///
+/// * `resolved` - The command.
+/// * `shell` - The shell the command is executed with.
+/// * `cwd` - The directory the command is executed in.
+pub(crate) fn cache_file(
+    resolved: &ResolvedCommand,
+    shell: &str,
+    cwd: &Path,
+) -> io::Result<PathBuf> {
+    let stdin = match &resolved.stdin {
+        StdinMode::Data(data) => data,
+        StdinMode::Inherit | StdinMode::Null => "",
+    };
+    let env: Vec<String> = resolved
+        .env
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let key = [
+        shell,
+        &resolved.shell_args.join(" "),
+        &cwd.to_string_lossy(),
+        &env.join("\0"),
+        &resolved.command,
+        stdin,
+    ]
+    .join("\0");
+    xdg::BaseDirectories::with_prefix(PROG_NAME).place_cache_file(sha256_hex(key.as_bytes()))
+}
+
+/// Reads cached output if it is still fresh.
+///
+/// * `path` - The file the output is cached in.
+/// * `ttl` - How long the output is cached for.
+/// * `now` - The current time.
+///
+/// returns - The cached output, or `None` if there is none or it is stale.
+pub(crate) fn read_fresh(path: &Path, ttl: Duration, now: SystemTime) -> Option<Vec<u8>> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    if is_fresh(modified, ttl, now) {
+        fs::read(path).ok()
+    } else {
+        None
+    }
+}
+
+/// Checks whether output cached at `cached` is still fresh at `now`. Output cached in the future,
+/// e.g. after the clock was turned back, is stale.
+fn is_fresh(cached: SystemTime, ttl: Duration, now: SystemTime) -> bool {
+    now.duration_since(cached).is_ok_and(|age| age < ttl)
+}
+
+/// Copies everything from `reader` to `writer` as it is read.
+///
+/// returns - Everything that was copied, or the error that occurred while copying.
+pub(crate) fn tee(mut reader: impl Read, mut writer: impl Write) -> io::Result<Vec<u8>> {
+    let mut output = vec![];
+    let mut buf = [0; 8192];
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => return Ok(output),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buf[..len])?;
+        writer.flush()?;
+        output.extend_from_slice(&buf[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(0, 300, true ; "just cached")]
+    #[test_case(299, 300, true ; "within ttl")]
+    #[test_case(300, 300, false ; "expired")]
+    #[test_case(10, 0, false ; "zero ttl")]
+    fn test_is_fresh(age: u64, ttl: u64, expected: bool) {
+        let cached = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let now = cached + Duration::from_secs(age);
+        assert_eq!(is_fresh(cached, Duration::from_secs(ttl), now), expected);
+    }
+
+    #[test]
+    fn test_is_fresh_cached_in_future() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let cached = now + Duration::from_secs(1);
+        assert!(!is_fresh(cached, Duration::from_secs(300), now));
+    }
+
+    #[test]
+    fn test_tee() {
+        let mut written = vec![];
+        let output = tee("some output".as_bytes(), &mut written).unwrap();
+        assert_eq!(output, b"some output");
+        assert_eq!(written, b"some output");
+    }
+}
@@ -0,0 +1,230 @@
+//! Splits the command line into xrun's flags and the tokens of the command.
+
+/// Flags understood by xrun, which config defined aliases can't override. Flags taking a value
+/// are listed without it (e.g. `--format` for `--format=json`).
+pub(crate) const BUILTIN_FLAGS: &[&str] = &[
+    "--help",
+    "-h",
+    "--passthrough",
+    "-p",
+    "--all",
+    "--help-all",
+    "--context",
+    "--dump-resolved",
+    "--print-shell",
+    "--version",
+    "-V",
+    "--env-dump",
+    "--print-env-file",
+    "--describe",
+    "--dry-run",
+    "-n",
+    "--trace-resolve",
+    "--check",
+    "--compare-config",
+    "--completions",
+    "--init-completions",
+    "--init",
+    "--where",
+    "--force",
+    "--list",
+    "--count",
+    "--json-lines",
+    "--tree",
+    "--match",
+    "--invert-match",
+    "--match-regex",
+    "--only-with",
+    "--only-without",
+    "--show-secrets",
+    "--verbose",
+    "-v",
+    "--no-stdin",
+    "--stdin-from-args",
+    "--trace-shell",
+    "--no-cache",
+    "--refresh",
+    "--stats",
+    "--steps",
+    "--timeout",
+    "--run-from",
+    "--format",
+    "--color",
+    "--config",
+    "-c",
+    "--no-local",
+];
+
+/// Built-in flags that take a value, given either as `--flag=value` or as the following token.
+pub(crate) const VALUE_FLAGS: &[&str] = &[
+    "--run-from",
+    "--format",
+    "--config",
+    "-c",
+    "--completions",
+    "--color",
+    "--match",
+    "--invert-match",
+    "--only-with",
+    "--only-without",
+    "--steps",
+    "--timeout",
+];
+
+/// Built-in flags choosing the config. As flag aliases are read from the config, they can't
+/// expand to these.
+pub(crate) const CONFIG_FLAGS: &[&str] = &["--config", "-c", "--no-local"];
+
+/// Errors when parsing the command line.
+#[derive(Debug, PartialEq)]
+pub(crate) enum CliError {
+    /// A flag that takes a value is the last token.
+    ///
+    /// * `String` - The flag.
+    MissingValue(String),
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::MissingValue(flag) => write!(f, "Missing value for flag: {}", flag),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// A token, or pair of tokens, of the command line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Arg<'a> {
+    /// A flag along with its value if given one.
+    Flag(&'a str, Option<&'a str>),
+    /// A token of the command.
+    Command(&'a str),
+}
+
+/// Checks whether `option` is a built-in flag, ignoring any `=value`.
+pub(crate) fn is_builtin_flag(option: &str) -> bool {
+    let flag = option.split_once('=').map_or(option, |(flag, _)| flag);
+    BUILTIN_FLAGS.contains(&flag)
+}
+
+/// Parses the command line. Flags may appear anywhere before a `--`, after which every token is
+/// part of the command even if it looks like a flag. Likewise, a token that looks like a flag but
+/// isn't a built-in one, along with every token after it, is part of the command if `is_arg` says
+/// so, e.g. once the command tokens name a command taking arguments.
+///
+/// * `args` - The command line, without the program name.
+/// * `is_arg` - Checks whether a token that looks like a flag but isn't a built-in one is an
+///   argument, given the command tokens before it and the token.
+/// * `takes_value` - Checks whether a flag that isn't a built-in one, e.g. an alias of one, takes
+///   the following token as its value when not given one with `=`.
+///
+/// returns - The flags and command tokens in order, or `CliError::MissingValue` if the last token
+/// is a flag missing its value.
+pub(crate) fn parse_args<'a>(
+    args: &'a [String],
+    mut is_arg: impl FnMut(&[&str], &str) -> bool,
+    mut takes_value: impl FnMut(&str) -> bool,
+) -> Result<Vec<Arg<'a>>, CliError> {
+    let mut parsed = vec![];
+    let mut command = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.extend(args.map(|arg| Arg::Command(arg)));
+            break;
+        }
+        if !arg.starts_with('-') {
+            parsed.push(Arg::Command(arg));
+            command.push(arg.as_str());
+            continue;
+        }
+        if !is_builtin_flag(arg) && is_arg(&command, arg) {
+            parsed.push(Arg::Command(arg));
+            parsed.extend(args.map(|arg| Arg::Command(arg)));
+            break;
+        }
+        parsed.push(match arg.split_once('=') {
+            Some((flag, value)) => Arg::Flag(flag, Some(value)),
+            None if VALUE_FLAGS.contains(&arg.as_str())
+                || (!is_builtin_flag(arg) && takes_value(arg)) =>
+            {
+                match args.next() {
+                    Some(value) => Arg::Flag(arg, Some(value)),
+                    None => return Err(CliError::MissingValue(arg.to_owned())),
+                }
+            }
+            None => Arg::Flag(arg, None),
+        });
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn to_args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    #[test_case("s c1", &[Arg::Command("s"), Arg::Command("c1")] ; "command")]
+    #[test_case("-h s", &[Arg::Flag("-h", None), Arg::Command("s")] ; "flag before")]
+    #[test_case("s --all", &[Arg::Command("s"), Arg::Flag("--all", None)] ; "flag after")]
+    #[test_case("--format=json", &[Arg::Flag("--format", Some("json"))] ; "joined value")]
+    #[test_case("--format json", &[Arg::Flag("--format", Some("json"))] ; "separate value")]
+    #[test_case("--run-from -x c", &[Arg::Flag("--run-from", Some("-x")), Arg::Command("c")] ; "value looks like flag")]
+    #[test_case("-c x.toml c", &[Arg::Flag("-c", Some("x.toml")), Arg::Command("c")] ; "short separate value")]
+    #[test_case("--help=x", &[Arg::Flag("--help", Some("x"))] ; "value for flag without value")]
+    #[test_case("-- -h", &[Arg::Command("-h")] ; "separator")]
+    #[test_case("-p c -- -h -- x", &[Arg::Flag("-p", None), Arg::Command("c"), Arg::Command("-h"), Arg::Command("--"), Arg::Command("x")] ; "separator once")]
+    fn test_parse_args(args: &str, expected: &[Arg]) {
+        assert_eq!(
+            parse_args(&to_args(args), |_, _| false, |_| false).unwrap(),
+            expected
+        );
+    }
+
+    #[test_case("g status --short", &[Arg::Command("g"), Arg::Command("status"), Arg::Command("--short")] ; "arg")]
+    #[test_case("g --short -p -- x", &[Arg::Command("g"), Arg::Command("--short"), Arg::Command("-p"), Arg::Command("--"), Arg::Command("x")] ; "rest args")]
+    #[test_case("g -p --short", &[Arg::Command("g"), Arg::Flag("-p", None), Arg::Command("--short")] ; "builtin flag")]
+    #[test_case("--short g", &[Arg::Flag("--short", None), Arg::Command("g")] ; "before command")]
+    fn test_parse_args_is_arg(args: &str, expected: &[Arg]) {
+        let is_arg = |command: &[&str], _: &str| command.first() == Some(&"g");
+        assert_eq!(
+            parse_args(&to_args(args), is_arg, |_| false).unwrap(),
+            expected
+        );
+    }
+
+    #[test_case("-f json c", &[Arg::Flag("-f", Some("json")), Arg::Command("c")] ; "separate value")]
+    #[test_case("-f=json c", &[Arg::Flag("-f", Some("json")), Arg::Command("c")] ; "joined value")]
+    #[test_case("-q json c", &[Arg::Flag("-q", None), Arg::Command("json"), Arg::Command("c")] ; "no value")]
+    fn test_parse_args_takes_value(args: &str, expected: &[Arg]) {
+        let takes_value = |flag: &str| flag == "-f";
+        assert_eq!(
+            parse_args(&to_args(args), |_, _| false, takes_value).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_args_missing_value() {
+        assert_eq!(
+            parse_args(&to_args("c --format"), |_, _| false, |_| false).unwrap_err(),
+            CliError::MissingValue("--format".to_string())
+        );
+    }
+
+    #[test_case("--help", true ; "long")]
+    #[test_case("-h", true ; "short")]
+    #[test_case("--format=json", true ; "with value")]
+    #[test_case("--formats", false ; "prefix")]
+    #[test_case("-q", false ; "unknown")]
+    fn test_is_builtin_flag(option: &str, expected: bool) {
+        assert_eq!(is_builtin_flag(option), expected);
+    }
+}
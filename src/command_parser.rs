@@ -1,9 +1,20 @@
-use std::{fs, io, path::Path};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    ptr,
+    time::Duration,
+};
 use toml::{self, Table, Value};
 
+use crate::cli::CONFIG_FLAGS;
+
 /// Reason why a toml key/value is considered contextually invalid during command parsing.
 #[derive(Debug)]
-pub(crate) enum InvalidContentReason {
+#[non_exhaustive]
+pub enum InvalidContentReason {
     /// Expected a toml string but got something else.
     ///
     /// * `String` - The key which is not a table.
@@ -14,10 +25,84 @@ pub(crate) enum InvalidContentReason {
     /// * `String` - The key which is not a table.
     /// * `Value` - The actual value received.
     NotTomlTable(String, Value),
+    /// Expected a toml integer but got something else.
+    ///
+    /// * `String` - The key which is not an integer.
+    /// * `Value` - The actual value received.
+    NotTomlInteger(String, Value),
+    /// Expected a toml boolean but got something else.
+    ///
+    /// * `String` - The key which is not a boolean.
+    /// * `Value` - The actual value received.
+    NotTomlBoolean(String, Value),
+    /// Expected a toml array but got something else.
+    ///
+    /// * `String` - The key which is not an array.
+    /// * `Value` - The actual value received.
+    NotTomlArray(String, Value),
     /// A key, such as 'command' is not present when it was expected to be.
     ///
     /// * `String` - The expected key that is not present.
     MissingKey(String),
+    /// The 'command' key is present but empty or only whitespace.
+    EmptyCommand,
+    /// A key has a string value that is not one of its allowed values.
+    ///
+    /// * `String` - The key with the unknown value.
+    /// * `String` - The unknown value.
+    UnknownValue(String, String),
+    /// A key of 'exit_codes' is not an integer.
+    ///
+    /// * `String` - The key which is not an integer.
+    InvalidExitCode(String),
+    /// A key is not a duration such as `5m`.
+    ///
+    /// * `String` - The key which is not a duration.
+    /// * `String` - The actual value received.
+    InvalidDuration(String, String),
+    /// A 'command' references a snippet that is not defined.
+    ///
+    /// * `String` - The name of the snippet.
+    UnknownSnippet(String),
+    /// Snippets reference each other in a cycle.
+    ///
+    /// * `String` - The name of the snippet referenced again.
+    SnippetCycle(String),
+    /// Sibling (sub)commands have the same alias in their 'aliases'.
+    ///
+    /// * `String` - The alias.
+    /// * `String` - The first (sub)command with the alias.
+    /// * `String` - The second (sub)command with the alias.
+    DuplicateAlias(String, String, String),
+    /// A key of a command table is neither a reserved key nor a subcommand.
+    ///
+    /// * `String` - The key.
+    /// * `Value` - The value of the key.
+    UnknownKey(String, Value),
+    /// The 'default' of a group doesn't name one of its subcommands with a 'command'.
+    ///
+    /// * `String` - The value of 'default'.
+    InvalidDefault(String),
+    /// A `${NAME}` in a command executed without a shell names an unset variable.
+    ///
+    /// * `String` - The name of the variable.
+    UndefinedVariable(String),
+    /// Keys that cannot be used together are both present.
+    ///
+    /// * `String` - The first key.
+    /// * `String` - The second key.
+    ConflictingKeys(String, String),
+    /// A command with `shell = false` has a string command, which needs a shell to be split into
+    /// the program and its arguments.
+    ///
+    /// * `String` - The key holding the string, 'command' or 'steps'.
+    NeedsShell(String),
+    /// A flag alias expands to a flag choosing the config, which must be given on the command
+    /// line as the aliases are read from the config.
+    ///
+    /// * `String` - The alias.
+    /// * `String` - The flag it expands to.
+    ConfigFlagAlias(String, String),
 }
 
 /// Gets a string representation of the type (actually enum value) of the Value.
@@ -50,16 +135,96 @@ impl std::fmt::Display for InvalidContentReason {
                 component,
                 value_as_name(value)
             ),
+            InvalidContentReason::NotTomlInteger(component, value) => write!(
+                f,
+                "Expected key '{}' to be Integer but got {}",
+                component,
+                value_as_name(value)
+            ),
+            InvalidContentReason::NotTomlBoolean(component, value) => write!(
+                f,
+                "Expected key '{}' to be Boolean but got {}",
+                component,
+                value_as_name(value)
+            ),
+            InvalidContentReason::NotTomlArray(component, value) => write!(
+                f,
+                "Expected key '{}' to be Array but got {}",
+                component,
+                value_as_name(value)
+            ),
             InvalidContentReason::MissingKey(key) => {
                 write!(f, "Expected key '{}' but it is not present", key)
             }
+            InvalidContentReason::EmptyCommand => {
+                write!(f, "Key 'command' is defined but empty")
+            }
+            InvalidContentReason::UnknownValue(key, value) => {
+                write!(f, "Key '{}' has unknown value '{}'", key, value)
+            }
+            InvalidContentReason::InvalidExitCode(key) => {
+                write!(f, "Expected key '{}' of 'exit_codes' to be an integer", key)
+            }
+            InvalidContentReason::InvalidDuration(key, value) => write!(
+                f,
+                "Expected key '{}' to be a duration such as '30s', '5m', '1h' or '1d' but got '{}'",
+                key, value
+            ),
+            InvalidContentReason::UnknownSnippet(name) => {
+                write!(f, "Snippet '{}' is not defined", name)
+            }
+            InvalidContentReason::SnippetCycle(name) => {
+                write!(f, "Snippet '{}' references itself", name)
+            }
+            InvalidContentReason::DuplicateAlias(alias, first, second) => write!(
+                f,
+                "Alias '{}' is used by both '{}' and '{}'",
+                alias, first, second
+            ),
+            InvalidContentReason::InvalidDefault(default) => write!(
+                f,
+                "Key 'default' is '{}', which is not a subcommand with a 'command'",
+                default
+            ),
+            InvalidContentReason::UndefinedVariable(name) => write!(
+                f,
+                "Variable '{}' is not set, use '${{{}:-default}}' to give it a default or \
+                 '$${{{}}}' for a literal '${{{}}}'",
+                name, name, name, name
+            ),
+            InvalidContentReason::ConflictingKeys(first, second) => {
+                write!(
+                    f,
+                    "Keys '{}' and '{}' cannot be used together",
+                    first, second
+                )
+            }
+            InvalidContentReason::NeedsShell(key) => write!(
+                f,
+                "Key '{}' is a string but 'shell' is false, use an array of the program and its \
+                 arguments instead",
+                key
+            ),
+            InvalidContentReason::ConfigFlagAlias(alias, flag) => write!(
+                f,
+                "Flag alias '{}' expands to '{}', which chooses the config and must be given on \
+                 the command line",
+                alias, flag
+            ),
+            InvalidContentReason::UnknownKey(key, value) => write!(
+                f,
+                "Key '{}' is not a known key, and being {} it is not a subcommand either",
+                key,
+                value_as_name(value)
+            ),
         }
     }
 }
 
 /// Errors when parsing and searching for commands from the config.
 #[derive(Debug)]
-pub(crate) enum CommandParseError {
+#[non_exhaustive]
+pub enum CommandParseError {
     /// Wrapper for `io::Error`
     IoError(io::Error),
     /// Wrapper for `toml::de::Error`
@@ -67,10 +232,75 @@ pub(crate) enum CommandParseError {
     /// An error for when a command is not found in the config files.
     ///
     /// * `String` - The component of the command that is not found.
-    CommandNotFoundError(String),
+    /// * `Option<String>` - The closest (sub)command to the first token of the component, if any
+    ///   is close enough to suggest.
+    CommandNotFoundError(String, Option<String>),
     ///
     /// An error for when an entry is present, but there is no valid execution.
     CommandContentInvalid(InvalidContentReason),
+    /// An error for when a component of the command is a reserved key, which can never name a
+    /// command.
+    ///
+    /// * `String` - The reserved component.
+    ReservedName(String),
+    /// An error for when the directory a command is executed in does not exist.
+    ///
+    /// * `PathBuf` - The directory.
+    CwdNotFound(PathBuf),
+    /// An error for when the command path is nested deeper than allowed.
+    ///
+    /// * `usize` - The maximum allowed depth.
+    MaxDepthExceeded(usize),
+    /// An error for when the config file given explicitly does not exist.
+    ///
+    /// * `PathBuf` - The path of the config file.
+    ConfigNotFound(PathBuf),
+    /// An error for when the config file exists but cannot be read due to its permissions.
+    ///
+    /// * `PathBuf` - The path of the config file.
+    ConfigNotReadable(PathBuf),
+    /// An error for when a file included by the config does not exist.
+    ///
+    /// * `PathBuf` - The path of the included file.
+    IncludeNotFound(PathBuf),
+    /// An error for when a file included by the config includes itself, directly or not.
+    ///
+    /// * `PathBuf` - The path of the file included again.
+    CircularInclude(PathBuf),
+    /// An error for when the config does not have the expected SHA-256 hash.
+    ///
+    /// * `String` - The expected hash.
+    /// * `String` - The actual hash.
+    ConfigHashMismatch(String, String),
+    /// An error for when the config includes other files while its SHA-256 hash is verified, as
+    /// the hash only covers the config itself.
+    ///
+    /// * `PathBuf` - The path of the config file.
+    UnverifiedInclude(PathBuf),
+    /// An error for when the shell commands are executed with does not exist.
+    ///
+    /// * `String` - The shell.
+    ShellNotFound(String),
+    /// An error for when the command has a positional placeholder beyond the arguments given.
+    ///
+    /// * `usize` - The position (from 1) of the missing argument.
+    MissingArgument(usize),
+    /// An error for when the program of a command executed without a shell does not exist.
+    ///
+    /// * `String` - The program.
+    ProgramNotFound(String),
+    /// An error for when the config does not define any commands.
+    ///
+    /// * `PathBuf` - The path of the config file.
+    EmptyConfig(PathBuf),
+    /// An error for when a command was killed for running longer than its 'timeout'.
+    ///
+    /// * `Duration` - The timeout.
+    TimedOut(Duration),
+    /// An error for when a step selected by name is not one of the named steps of the command.
+    ///
+    /// * `String` - The name of the step.
+    StepNotFound(String),
 }
 
 impl std::fmt::Display for CommandParseError {
@@ -78,18 +308,98 @@ impl std::fmt::Display for CommandParseError {
         match self {
             CommandParseError::IoError(err) => write!(f, "{}", err),
             CommandParseError::TomlDeError(err) => write!(f, "TOML parse error - {}", err),
-            CommandParseError::CommandNotFoundError(err) => {
-                write!(f, "Command '{}' not found", err)
+            CommandParseError::CommandNotFoundError(err, suggestion) => {
+                write!(f, "Command '{}' not found", err)?;
+                match suggestion {
+                    Some(suggestion) => write!(f, ". Did you mean '{}'?", suggestion),
+                    None => Ok(()),
+                }
             }
             CommandParseError::CommandContentInvalid(err) => {
                 write!(f, "Command content invalid - {}", err)
             }
+            CommandParseError::ReservedName(name) => write!(
+                f,
+                "'{}' is a reserved key and cannot be used as a command name, rename the command",
+                name
+            ),
+            CommandParseError::CwdNotFound(cwd) => {
+                write!(f, "Working directory '{}' does not exist", cwd.display())
+            }
+            CommandParseError::MaxDepthExceeded(max_depth) => {
+                write!(f, "Command exceeds the maximum depth of {}", max_depth)
+            }
+            CommandParseError::ConfigNotFound(path) => {
+                write!(f, "Config file does not exist: {}", path.display())
+            }
+            CommandParseError::ConfigNotReadable(path) => write!(
+                f,
+                "Config file exists but is not readable: {}",
+                path.display()
+            ),
+            CommandParseError::IncludeNotFound(path) => {
+                write!(f, "Included config file does not exist: {}", path.display())
+            }
+            CommandParseError::CircularInclude(path) => {
+                write!(f, "Config file includes itself: {}", path.display())
+            }
+            CommandParseError::ConfigHashMismatch(expected, actual) => {
+                write!(f, "Config SHA-256 is {} but expected {}", actual, expected)
+            }
+            CommandParseError::UnverifiedInclude(path) => write!(
+                f,
+                "Config file includes other files, which XRUN_CONFIG_SHA256 does not verify: {}",
+                path.display()
+            ),
+            CommandParseError::ShellNotFound(shell) => {
+                write!(f, "Shell '{}' does not exist", shell)
+            }
+            CommandParseError::MissingArgument(position) => {
+                write!(
+                    f,
+                    "Command expects argument {{{}}} but it was not given",
+                    position
+                )
+            }
+            CommandParseError::ProgramNotFound(program) => {
+                write!(f, "Program '{}' does not exist", program)?;
+                // Most likely a sequence of shell commands written as an array 'command'.
+                if program.contains(char::is_whitespace) {
+                    write!(f, "\nUse 'steps' for commands executed in sequence")?;
+                }
+                Ok(())
+            }
+            CommandParseError::EmptyConfig(path) => write!(
+                f,
+                "Config file has no commands: {}\nAdd one such as `hello = {{ command = \"echo hello\" }}`",
+                path.display()
+            ),
+            CommandParseError::TimedOut(timeout) => {
+                write!(f, "Command timed out after {}s", timeout.as_secs())
+            }
+            CommandParseError::StepNotFound(name) => {
+                write!(f, "Command has no step named '{}'", name)
+            }
         }
     }
 }
 
 impl std::error::Error for CommandParseError {}
 
+impl CommandParseError {
+    /// The exit code to exit with when failing with this error.
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            CommandParseError::ConfigHashMismatch(_, _)
+            | CommandParseError::UnverifiedInclude(_) => 3,
+            CommandParseError::ConfigNotReadable(_) => 4,
+            CommandParseError::EmptyConfig(_) => 5,
+            CommandParseError::TimedOut(_) => 124,
+            _ => 1,
+        }
+    }
+}
+
 impl From<io::Error> for CommandParseError {
     fn from(err: io::Error) -> Self {
         CommandParseError::IoError(err)
@@ -108,13 +418,298 @@ impl From<InvalidContentReason> for CommandParseError {
     }
 }
 
+/// Name of the top-level table holding program settings rather than commands.
+pub(crate) const SETTINGS_KEY: &str = "xrun";
+
+/// Default maximum number of tokens in a command path.
+const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Keys of a command table that configure the command rather than name a subcommand.
+pub(crate) const RESERVED_KEYS: &[&str] = &[
+    "aliases",
+    "cache",
+    "command",
+    "cwd",
+    "default",
+    "desc",
+    "env",
+    "exit_codes",
+    "help_args",
+    "help_passthrough",
+    "hidden",
+    "ignore_errors",
+    "post_args",
+    "shell",
+    "shell_args",
+    "stdin",
+    "steps",
+    "strict",
+    "timeout",
+    "title",
+    "trace",
+    "usage",
+];
+
+/// A command resolved from the config along with everything needed to execute it.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ResolvedCommand {
+    /// The shell command to execute.
+    pub command: String,
+    /// The program and its arguments to execute directly instead of through a shell if the
+    /// command does not use a shell. `command` is then the equivalent shell command.
+    pub argv: Option<Vec<String>>,
+    /// The shell the command is executed with instead of `$SHELL` if defined.
+    pub shell: Option<String>,
+    /// Extra arguments passed to the shell before the command.
+    pub shell_args: Vec<String>,
+    /// Whether the command aborts on the first failure. See `strict_prelude`.
+    pub strict: bool,
+    /// Where the command reads its stdin from.
+    pub stdin: StdinMode,
+    /// Whether the shell echoes each line it executes.
+    pub trace: bool,
+    /// The directory the command is executed in, or `None` for the current directory.
+    pub cwd: Option<PathBuf>,
+    /// Environment variables set for the command on top of the inherited environment.
+    pub env: BTreeMap<String, String>,
+    /// Conventions of the shells configured in the settings, by shell name.
+    pub shells: BTreeMap<String, ShellConvention>,
+    /// What happens if the shell does not exist.
+    pub missing_shell: MissingShell,
+    /// How long the output of the command is cached for, or `None` if it is not cached.
+    pub cache: Option<Duration>,
+    /// Whether the steps after this one are executed even if it fails.
+    pub ignore_errors: bool,
+    /// How long the command, including all of its steps, may run before it is killed, or `None`
+    /// if it may run indefinitely.
+    pub timeout: Option<Duration>,
+}
+
+/// What happens when the shell commands are executed with does not exist.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum MissingShell {
+    /// Warn and fall back to `sh`.
+    Fallback,
+    /// Fail with `CommandParseError::ShellNotFound`.
+    Error,
+}
+
+/// How a shell is invoked to execute a command.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ShellConvention {
+    /// Flag preceding the command the shell executes, e.g. `-c`.
+    pub command_flag: String,
+    /// Flag making the shell interactive, or `None` if it is not made interactive.
+    pub interactive_flag: Option<String>,
+}
+
+impl ShellConvention {
+    /// Gets the built-in convention of `shell`. Many programs use isatty for things like whether
+    /// to add colours, so shells supporting it are made interactive to get as close to real shell
+    /// aliases as possible.
+    ///
+    /// * `shell` - The shell, either by name or path.
+    pub(crate) fn builtin(shell: &str) -> ShellConvention {
+        let interactive = matches!(shell_name(shell), "bash" | "zsh" | "fish");
+        ShellConvention {
+            command_flag: "-c".to_string(),
+            interactive_flag: interactive.then(|| "-i".to_string()),
+        }
+    }
+}
+
+/// Where a command reads its stdin from.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum StdinMode {
+    /// Inherit xrun's stdin.
+    Inherit,
+    /// Read from `/dev/null` so reads get EOF immediately.
+    Null,
+    /// Read the given data, e.g. the arguments given with `--stdin-from-args`.
+    Data(String),
+}
+
+impl ResolvedCommand {
+    /// Gets the convention `shell` is invoked with, preferring the one configured in the settings
+    /// over the built-in one.
+    ///
+    /// * `shell` - The shell the command is executed with.
+    pub(crate) fn shell_convention(&self, shell: &str) -> ShellConvention {
+        match self.shells.get(shell_name(shell)) {
+            Some(convention) => convention.to_owned(),
+            None => ShellConvention::builtin(shell),
+        }
+    }
+
+    /// Builds the environment the command is executed with.
+    ///
+    /// returns - The environment variables sorted by name.
+    pub(crate) fn environment(&self) -> BTreeMap<String, String> {
+        let mut environment: BTreeMap<String, String> = env::vars().collect();
+        environment.extend(self.env.to_owned());
+        environment
+    }
+
+    /// Builds the script passed to `shell`, including the strict mode prelude if enabled and
+    /// supported by `shell`, and enabling tracing for shells that can't enable it by flag.
+    ///
+    /// * `shell` - The shell the command is executed with.
+    pub(crate) fn shell_command(&self, shell: &str) -> String {
+        let mut shell_command = match strict_prelude(shell) {
+            Some(prelude) if self.strict => format!("{}\n{}", prelude, self.command),
+            _ => self.command.to_owned(),
+        };
+        // fish has no -x flag so tracing is enabled from within the script instead.
+        if self.trace && shell.ends_with("fish") {
+            shell_command = format!("set fish_trace 1\n{}", shell_command);
+        }
+        shell_command
+    }
+
+    /// Serializes the command into a standalone script reproducing its execution.
+    ///
+    /// * `shell` - The shell the command is executed with.
+    /// * `current_dir` - The current directory, used if the command has no `cwd`.
+    pub(crate) fn to_script(&self, shell: &str, current_dir: &Path) -> String {
+        let cwd = self.cwd.as_deref().unwrap_or(current_dir);
+        let mut script = String::new();
+        let interpreter = if shell.starts_with('/') && self.shell_args.is_empty() {
+            shell.to_string()
+        } else if self.shell_args.is_empty() {
+            format!("/usr/bin/env {}", shell)
+        } else {
+            // Shebangs only reliably support a single argument so let env split them.
+            format!("/usr/bin/env -S {} {}", shell, self.shell_args.join(" "))
+        };
+        script += &format!("#!{}\n", interpreter);
+        script += &format!("cd {}\n", shell_quote(&cwd.to_string_lossy()));
+        for (key, value) in &self.env {
+            script += &format!("export {}={}\n", key, shell_quote(value));
+        }
+        script += &self.shell_command(shell);
+        script += "\n";
+        script
+    }
+}
+
+/// Gets the prelude making `shell` abort on the first failure, unset variable or failed pipeline.
+///
+/// returns - The prelude or `None` if `shell` does not support it.
+pub(crate) fn strict_prelude(shell: &str) -> Option<&'static str> {
+    if shell.ends_with("bash") || shell.ends_with("zsh") {
+        Some("set -euo pipefail")
+    } else {
+        None
+    }
+}
+
+/// Gets the name of `shell`, which may be given as a path.
+fn shell_name(shell: &str) -> &str {
+    shell.rsplit('/').next().unwrap_or(shell)
+}
+
+/// Resolves a user supplied directory, expanding a leading `~` to the home directory.
+///
+/// * `dir` - The directory to resolve.
+/// * `base` - The directory relative paths are resolved against.
+pub(crate) fn resolve_dir(dir: &str, base: &Path) -> PathBuf {
+    let home = || env::var("HOME").map(PathBuf::from).unwrap_or_default();
+    let dir = if dir == "~" {
+        home()
+    } else if let Some(rest) = dir.strip_prefix("~/") {
+        home().join(rest)
+    } else {
+        PathBuf::from(dir)
+    };
+    base.join(dir)
+}
+
+/// Quotes `s` so a POSIX shell treats it as a single literal word.
+pub(crate) fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
 /// Pair of sub(command) name and descriptions if defined for usage when displaying help
 /// information.
 ///
 /// * `String` subcommand name or `None` for the specified command.
 /// * `String` sub(command) description if defined.
 #[derive(Debug, PartialEq)]
-pub(crate) struct HelpPair(pub Option<String>, pub Option<String>);
+pub struct HelpPair(pub Option<String>, pub Option<String>);
+
+/// Help information for a (sub)command.
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub struct CommandHelp {
+    /// The (sub)command and its subcommands with their descriptions. See `HelpPair`.
+    pub pairs: Vec<HelpPair>,
+    /// The documented exit codes of the (sub)command and their meaning, sorted by exit code.
+    pub exit_codes: Vec<(i64, String)>,
+    /// Custom usage replacing the generated one if defined.
+    pub usage: Option<String>,
+    /// Display titles of the subcommands that define one, by subcommand name.
+    pub titles: BTreeMap<String, String>,
+    /// Aliases of the subcommands that define any, by subcommand name.
+    pub aliases: BTreeMap<String, Vec<String>>,
+}
+
+/// A runnable (sub)command, i.e. one with a 'command'.
+#[derive(Debug, PartialEq)]
+pub(crate) struct CommandEntry {
+    /// The path of the (sub)command.
+    pub path: Vec<String>,
+    /// The description of the (sub)command if defined.
+    pub desc: Option<String>,
+    /// The display title of the (sub)command if defined.
+    pub title: Option<String>,
+    /// Whether the (sub)command is hidden, either itself or by being beneath a hidden
+    /// (sub)command.
+    pub hidden: bool,
+    /// The keys configuring the (sub)command (see `RESERVED_KEYS`), sorted.
+    pub keys: Vec<String>,
+}
+
+/// A runnable (sub)command along with the details of how it runs.
+#[derive(Debug, PartialEq)]
+pub(crate) struct CommandDetails {
+    /// The (sub)command.
+    pub entry: CommandEntry,
+    /// The steps of the (sub)command as shell commands, with placeholders left unexpanded.
+    pub steps: Vec<String>,
+    /// The directory the (sub)command runs in if defined.
+    pub cwd: Option<PathBuf>,
+    /// The environment variables the (sub)command sets.
+    pub env: BTreeMap<String, String>,
+    /// The timeout of the (sub)command, falling back to the one in the settings, if any.
+    pub timeout: Option<Duration>,
+    /// The config file defining the (sub)command, which is either the config or a file it
+    /// includes.
+    pub source: PathBuf,
+}
+
+/// A (sub)command along with the (sub)commands beneath it.
+#[derive(Debug, PartialEq)]
+pub(crate) struct CommandNode {
+    /// The name of the (sub)command.
+    pub name: String,
+    /// The description of the (sub)command if defined.
+    pub desc: Option<String>,
+    /// The display title of the (sub)command if defined.
+    pub title: Option<String>,
+    /// Whether the (sub)command is runnable, i.e. has a 'command', rather than only grouping
+    /// (sub)commands.
+    pub is_command: bool,
+    /// Whether the (sub)command itself is hidden. Those beneath it are hidden too.
+    pub hidden: bool,
+    /// The keys configuring the (sub)command (see `RESERVED_KEYS`), sorted.
+    pub keys: Vec<String>,
+    /// The (sub)commands beneath the (sub)command, sorted by name.
+    pub children: Vec<CommandNode>,
+}
 
 /// Creates of a table of the `toml_str` toml data.
 ///
@@ -128,292 +723,3933 @@ pub(crate) fn toml_to_map(
     Ok(toml_data)
 }
 
-/// Parses a .toml file and extracts the action of a specified command.
+/// Parses a .toml file and extracts the action of a specified command, or its first step if it
+/// has several. Use `get_command_steps` to get all of them.
 ///
 /// * `path` - The path to the .toml file of the base command file.
-/// * `command` - The specified command to retrieve the action of.
+/// * `command` - The specified command to retrieve the action of, followed by the arguments
+///   passed to it if it takes any. See `split_args`.
 ///
-/// returns - The command action if the command is present, or the error that occurred while
+/// returns - The resolved command if the command is present, or the error that occurred while
 /// retrieving the command action.
-pub(crate) fn get_command(path: &Path, command: &[&str]) -> Result<String, CommandParseError> {
-    let toml_data = get_command_toml(path, command)?;
-    match toml_data.get("command") {
-        Some(exec_cmd) => match exec_cmd.as_str() {
-            Some(exec_cmd) => Ok(exec_cmd.to_string()),
-            None => Err(CommandParseError::CommandContentInvalid(
-                InvalidContentReason::NotTomlString("command".to_string(), exec_cmd.to_owned()),
-            )),
-        },
-        None => Err(CommandParseError::CommandContentInvalid(
-            InvalidContentReason::MissingKey("command".to_string()),
-        )),
+pub fn get_command(path: &Path, command: &[&str]) -> Result<ResolvedCommand, CommandParseError> {
+    Ok(get_command_steps(path, command)?.remove(0))
+}
+
+/// Parses a .toml file and extracts the steps of a specified command, which are executed in order.
+/// A command with 'steps' has one for each of them, and any other command has a single step.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The specified command to retrieve the steps of, followed by the arguments
+///   passed to it if it takes any. See `split_args`.
+///
+/// returns - The resolved steps if the command is present, or the error that occurred while
+/// retrieving the command action.
+pub fn get_command_steps(
+    path: &Path,
+    command: &[&str],
+) -> Result<Vec<ResolvedCommand>, CommandParseError> {
+    load_steps(path, command, false, None)
+}
+
+/// Parses a .toml file and extracts the steps of a specified command, which read the arguments
+/// passed to it from their stdin instead of taking them as arguments.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The specified command to retrieve the steps of, followed by the arguments to
+///   join with spaces and pass on stdin, ending with a newline. See `split_args`.
+///
+/// returns - The resolved steps if the command is present, or the error that occurred while
+/// retrieving the command action.
+pub(crate) fn get_command_steps_stdin_from_args(
+    path: &Path,
+    command: &[&str],
+) -> Result<Vec<ResolvedCommand>, CommandParseError> {
+    load_steps(path, command, true, None)
+}
+
+/// Parses a .toml file and extracts the named steps of a specified command, as if the command
+/// only had those steps. See `select_steps`.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The specified command to retrieve the steps of, followed by its arguments. See
+///   `split_args`.
+/// * `names` - The names of the steps to retrieve.
+/// * `stdin_from_args` - Whether the steps read the arguments from their stdin. See
+///   `get_command_steps_stdin_from_args`.
+///
+/// returns - The resolved steps if the command is present, or the error that occurred while
+/// retrieving the command action.
+pub(crate) fn get_command_steps_named(
+    path: &Path,
+    command: &[&str],
+    names: &[String],
+    stdin_from_args: bool,
+) -> Result<Vec<ResolvedCommand>, CommandParseError> {
+    load_steps(path, command, stdin_from_args, Some(names))
+}
+
+/// Parses a .toml file and extracts the steps of a specified command. See `get_command_steps`,
+/// `get_command_steps_stdin_from_args` and `get_command_steps_named`.
+fn load_steps(
+    path: &Path,
+    command: &[&str],
+    stdin_from_args: bool,
+    names: Option<&[String]>,
+) -> Result<Vec<ResolvedCommand>, CommandParseError> {
+    let config = load_config(path)?;
+    let (command, args) = split_args(&config, command)?;
+    let mut toml_data = get_command_toml(&config, command)?;
+    if let Some(names) = names {
+        toml_data = select_steps(&toml_data, names)?;
     }
+    let command = canonical_path(&config, command)?;
+    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+    let settings = get_settings(&config)?;
+    if !stdin_from_args {
+        return resolve_steps(&settings, &toml_data, path, &command, args);
+    }
+    let mut steps = resolve_steps(&settings, &toml_data, path, &command, &[])?;
+    for step in &mut steps {
+        step.stdin = StdinMode::Data(args.join(" ") + "\n");
+    }
+    Ok(steps)
 }
 
-/// Parses a .toml file and extracts help data
+/// Parses a .toml file and extracts the action that shows the native help of a command wrapping
+/// another tool.
 ///
 /// * `path` - The path to the .toml file of the base command file.
-/// * `command` - The specified command to retrieve the action of.
+/// * `command` - The specified command to retrieve the help action of.
 ///
-/// returns - Pairs of (sub)commands' and it's (sub)commands', along with their description if
-/// present, or the error that occurred while retrieving the command. The help pair with name
-/// `None` represents `command` and will always be present, even if it contains no description.
-pub(crate) fn get_command_help(
+/// returns - The command with its 'help_args' (default `--help`) appended if it sets
+/// 'help_passthrough', `None` if it does not, or the error that occurred while retrieving it.
+pub(crate) fn get_help_passthrough(
     path: &Path,
     command: &[&str],
-) -> Result<Vec<HelpPair>, CommandParseError> {
-    let mut help_pairs: Vec<HelpPair> = vec![];
-    let toml_data = get_command_toml(path, command)?;
-    if let Some(desc) = toml_data.get("desc").and_then(|s| s.as_str()) {
-        help_pairs.push(HelpPair(None, Some(desc.to_owned())))
-    } else {
-        help_pairs.push(HelpPair(None, None));
+) -> Result<Option<ResolvedCommand>, CommandParseError> {
+    let config = load_config(path)?;
+    let toml_data = get_command_toml(&config, command)?;
+    if !get_bool(&toml_data, "help_passthrough")?.unwrap_or(false) {
+        return Ok(None);
+    }
+    let command = canonical_path(&config, command)?;
+    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+    let mut resolved = resolve_command(&get_settings(&config)?, &toml_data, path, &command, &[])?;
+    let help_args =
+        get_string_array(&toml_data, "help_args")?.unwrap_or(vec!["--help".to_string()]);
+    for arg in help_args {
+        resolved.command += " ";
+        resolved.command += &shell_quote(&arg);
+        if let Some(argv) = &mut resolved.argv {
+            argv.push(arg);
+        }
     }
+    Ok(Some(resolved))
+}
 
-    for (k, v) in &toml_data {
-        if k != "desc" && k != "command" {
-            if let Some(desc) = v.get("desc").and_then(|s| s.as_str()) {
-                help_pairs.push(HelpPair(Some(k.to_owned()), Some(desc.to_owned())))
+/// Resolves the steps of a command from its toml table. A command's 'steps' are executed in
+/// order; a 'command' is a single step.
+///
+/// * `settings` - The program settings table.
+/// * `toml_data` - The toml table of the command.
+/// * `path` - The path to the config file, whose directory a relative 'cwd' is resolved against.
+/// * `command` - The command path `toml_data` was found at.
+/// * `args` - The arguments passed to the command. They go to the steps taking them through
+///   placeholders, or are appended to the last step if none do.
+///
+/// returns - The resolved steps, of which there is at least one, or the reason the command is
+/// invalid.
+fn resolve_steps(
+    settings: &Table,
+    toml_data: &Table,
+    path: &Path,
+    command: &[&str],
+    args: &[&str],
+) -> Result<Vec<ResolvedCommand>, CommandParseError> {
+    let post_args = get_string_array(toml_data, "post_args")?.unwrap_or_default();
+    let env = get_env(toml_data)?;
+    let (shell, use_shell) = get_shell(toml_data)?;
+    let steps = match get_steps(toml_data)? {
+        Some(steps) => steps,
+        None => match get_default(toml_data)? {
+            Some((name, default)) => {
+                let command = [command, &[name.as_str()]].concat();
+                return resolve_steps(settings, default, path, &command, args);
+            }
+            None => {
+                return Err(CommandParseError::CommandContentInvalid(
+                    InvalidContentReason::MissingKey("command".to_string()),
+                ))
+            }
+        },
+    };
+    check_needs_shell(toml_data, &steps, use_shell)?;
+    // Variables set for the command take precedence over those inherited. Only commands executed
+    // without a shell are interpolated, as a shell expands variables itself, including those the
+    // command sets.
+    let lookup = |name: &str| env.get(name).cloned().or_else(|| env::var(name).ok());
+    let steps = steps
+        .into_iter()
+        .map(|step| match step {
+            Step::Shell(exec_cmd) => Ok(Step::Shell(expand_snippet(settings, &exec_cmd)?)),
+            Step::Argv(argv) => argv
+                .iter()
+                .map(|arg| interpolate_env(arg, lookup))
+                .collect::<Result<Vec<String>, _>>()
+                .map(Step::Argv),
+        })
+        .collect::<Result<Vec<Step>, InvalidContentReason>>()?;
+    if steps.is_empty() {
+        return Err(CommandParseError::CommandContentInvalid(
+            InvalidContentReason::EmptyCommand,
+        ));
+    }
+    let any_takes_args = steps.iter().any(Step::takes_args);
+    let last = steps.len() - 1;
+    // Each step is a shell command, along with its program and arguments if it is executed
+    // without a shell.
+    let steps = steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            let step_args = if step.takes_args() || (i == last && !any_takes_args) {
+                args
             } else {
-                help_pairs.push(HelpPair(Some(k.to_owned()), None));
+                &[]
+            };
+            let step_post_args = if i == last { &post_args[..] } else { &[] };
+            match step {
+                Step::Shell(exec_cmd) if exec_cmd.trim().is_empty() => Err(
+                    CommandParseError::CommandContentInvalid(InvalidContentReason::EmptyCommand),
+                ),
+                Step::Shell(exec_cmd) => Ok((
+                    substitute_placeholders(exec_cmd, command, step_args, step_post_args)?,
+                    None,
+                )),
+                Step::Argv(argv) => resolve_argv(argv, command, step_args, step_post_args),
             }
-        }
+        })
+        .collect::<Result<Vec<(String, Option<Vec<String>>)>, CommandParseError>>()?;
+    // A command's own shell_args replace the global ones rather than extending them.
+    let shell_args = match get_string_array(toml_data, "shell_args")? {
+        Some(shell_args) => shell_args,
+        None => get_string_array(settings, "shell_args")?.unwrap_or_default(),
+    };
+    let strict = match get_bool(toml_data, "strict")? {
+        Some(strict) => strict,
+        None => get_bool(settings, "strict")?.unwrap_or(false),
+    };
+    let resolved = ResolvedCommand {
+        command: String::new(),
+        argv: None,
+        shell,
+        shell_args,
+        strict,
+        stdin: get_stdin(toml_data)?,
+        trace: get_bool(toml_data, "trace")?.unwrap_or(false),
+        cwd: get_string(toml_data, "cwd")?
+            .map(|cwd| resolve_dir(&cwd, path.parent().unwrap_or(Path::new(".")))),
+        env,
+        shells: get_shell_conventions(settings)?,
+        cache: get_cache(toml_data)?,
+        missing_shell: match get_string(settings, "missing_shell")?.as_deref() {
+            None | Some("fallback") => MissingShell::Fallback,
+            Some("error") => MissingShell::Error,
+            Some(value) => {
+                return Err(CommandParseError::CommandContentInvalid(
+                    InvalidContentReason::UnknownValue(
+                        "missing_shell".to_string(),
+                        value.to_string(),
+                    ),
+                ))
+            }
+        },
+        ignore_errors: get_bool(toml_data, "ignore_errors")?.unwrap_or(false),
+        timeout: resolve_timeout(settings, toml_data)?,
+    };
+    Ok(steps
+        .into_iter()
+        .map(|(command, argv)| ResolvedCommand {
+            command,
+            argv,
+            ..resolved.clone()
+        })
+        .collect())
+}
+
+/// Extracts the 'shell' of a command.
+///
+/// * `toml_data` - The toml table of the command.
+///
+/// returns - The shell to execute the command with if it names one, and whether the command is
+/// executed through a shell at all, or the reason 'shell' is invalid.
+fn get_shell(toml_data: &Table) -> Result<(Option<String>, bool), InvalidContentReason> {
+    match get_setting(toml_data, "shell") {
+        Some(Value::String(shell)) => Ok((Some(shell.to_owned()), true)),
+        Some(Value::Boolean(use_shell)) => Ok((None, *use_shell)),
+        Some(value) => Err(InvalidContentReason::NotTomlString(
+            "shell".to_string(),
+            value.to_owned(),
+        )),
+        None => Ok((None, true)),
     }
-    Ok(help_pairs)
 }
 
-/// Parses a .toml file and extracts the toml table of the specified
+/// Extracts the subcommand a group without a 'command' runs when invoked directly, named by its
+/// 'default'.
 ///
-/// * `path` - The path to the .toml file of the base command file.
-/// * `command` - The specified command to retrieve the action of.
+/// * `toml_data` - The toml table of the group.
 ///
-/// returns - The toml table of the (sub)command if it is present, or the error that occurred while
-/// retrieving the command action.
-fn get_command_toml(path: &Path, command: &[&str]) -> Result<Table, CommandParseError> {
-    let toml_str = &fs::read_to_string(path)?;
-    let mut toml_data = toml_to_map(toml_str)?;
-    let mut command_not_found = false;
-    let mut error_string: String = Default::default();
-    for token in command {
-        if !command_not_found {
-            match toml_data.get(*token) {
-                Some(Value::Table(next_table)) => {
-                    toml_data = next_table.to_owned();
-                }
-                Some(value) => {
-                    return Err(CommandParseError::CommandContentInvalid(
-                        InvalidContentReason::NotTomlTable(token.to_string(), value.to_owned()),
-                    ));
-                }
-                None => {
-                    command_not_found = true;
-                    error_string += token;
-                }
-            }
-        } else {
-            error_string += " ";
-            error_string += token;
+/// returns - The key and toml table of the subcommand if the group has a 'default', or the
+/// reason 'default' is invalid, including when it doesn't name a subcommand with a 'command'.
+fn get_default(toml_data: &Table) -> Result<Option<(&String, &Table)>, InvalidContentReason> {
+    let default = match get_string(toml_data, "default")? {
+        Some(default) => default,
+        None => return Ok(None),
+    };
+    match get_subcommand_entry(toml_data, &default, false)? {
+        Some((key, Value::Table(sub_table)))
+            if !is_reserved_name(toml_data, &default, false) && has_command(sub_table) =>
+        {
+            Ok(Some((key, sub_table)))
         }
+        _ => Err(InvalidContentReason::InvalidDefault(default)),
     }
-    if command_not_found {
-        // TODO: Improve error messages here.
-        // If the command is 'a b c' and 'c' doesn't exist, we currently show
-        //    `Error: command 'c' not found.`
-        // but this is more confusing than the issue it tries to avoid. Something like
-        //    `Error: command 'c' in 'a b' not found.`
-        // or something like (but not exactly)
-        //    `Error: command 'c' not found but parent 'a b' found.`
-        // Whatever is chosen should be tested properly because there isn't always a parent in the
-        // command.
-        Err(CommandParseError::CommandNotFoundError(error_string))
-    } else {
-        Ok(toml_data)
+}
+
+/// Extracts the 'stdin' of a command.
+///
+/// * `toml_data` - The toml table of the command.
+///
+/// returns - Where the command reads its stdin from, or the reason 'stdin' is invalid.
+fn get_stdin(toml_data: &Table) -> Result<StdinMode, InvalidContentReason> {
+    match get_string(toml_data, "stdin")?.as_deref() {
+        None | Some("inherit") => Ok(StdinMode::Inherit),
+        Some("null") => Ok(StdinMode::Null),
+        Some(stdin) => Err(InvalidContentReason::UnknownValue(
+            "stdin".to_string(),
+            stdin.to_owned(),
+        )),
     }
 }
 
-#[cfg(test)]
-mod tests {
+/// Extracts the 'cache' duration of a command.
+///
+/// * `toml_data` - The toml table of the command.
+///
+/// returns - How long the output of the command is cached for if it is, or the reason 'cache' is
+/// invalid.
+fn get_cache(toml_data: &Table) -> Result<Option<Duration>, InvalidContentReason> {
+    match get_string(toml_data, "cache")? {
+        Some(cache) => match parse_duration(&cache) {
+            Some(duration) => Ok(Some(duration)),
+            None => Err(InvalidContentReason::InvalidDuration(
+                "cache".to_string(),
+                cache,
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Extracts the 'timeout' of a command or the settings. See `parse_timeout`.
+///
+/// * `toml_data` - The toml table of the command or the settings.
+///
+/// returns - How long the command may run for, zero meaning indefinitely, `None` if 'timeout' is
+/// absent, or the reason it is invalid.
+fn get_timeout(toml_data: &Table) -> Result<Option<Duration>, InvalidContentReason> {
+    match get_setting(toml_data, "timeout") {
+        None => Ok(None),
+        Some(Value::Integer(timeout)) if *timeout >= 0 => {
+            Ok(Some(Duration::from_secs(*timeout as u64)))
+        }
+        Some(Value::String(timeout)) => match parse_timeout(timeout) {
+            Some(timeout) => Ok(Some(timeout)),
+            None => Err(InvalidContentReason::InvalidDuration(
+                "timeout".to_string(),
+                timeout.to_owned(),
+            )),
+        },
+        Some(value) => Err(InvalidContentReason::NotTomlInteger(
+            "timeout".to_string(),
+            value.to_owned(),
+        )),
+    }
+}
+
+/// Resolves the timeout of a command, which is its own 'timeout' if defined, or else the one in
+/// the settings.
+///
+/// * `settings` - The program settings table.
+/// * `toml_data` - The toml table of the command.
+///
+/// returns - How long the command may run for, `None` if it may run indefinitely, or the reason a
+/// 'timeout' is invalid.
+fn resolve_timeout(
+    settings: &Table,
+    toml_data: &Table,
+) -> Result<Option<Duration>, InvalidContentReason> {
+    let timeout = match get_timeout(toml_data)? {
+        Some(timeout) => Some(timeout),
+        None => get_timeout(settings)?,
+    };
+    Ok(timeout.filter(|timeout| !timeout.is_zero()))
+}
+
+/// Parses a timeout, which is either a number of seconds or a duration with a unit (see
+/// `parse_duration`), e.g. `300` or `5m`.
+///
+/// returns - The timeout or `None` if it is invalid.
+pub(crate) fn parse_timeout(timeout: &str) -> Option<Duration> {
+    match timeout.parse() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => parse_duration(timeout),
+    }
+}
+
+/// Resolves a command from its toml table, or its first step if it has several. See
+/// `resolve_steps`.
+fn resolve_command(
+    settings: &Table,
+    toml_data: &Table,
+    path: &Path,
+    command: &[&str],
+    args: &[&str],
+) -> Result<ResolvedCommand, CommandParseError> {
+    Ok(resolve_steps(settings, toml_data, path, command, args)?.remove(0))
+}
+
+/// Expands the placeholders in a command executed without a shell.
+///
+/// * `argv` - The program and its arguments.
+/// * `command` - The command path `argv` was resolved from.
+/// * `args` - The arguments passed to the command.
+/// * `post_args` - Arguments appended to the end of the command.
+///
+/// returns - The equivalent shell command along with the expanded program and arguments (see
+/// `substitute_argv`), or the reason the command is invalid.
+fn resolve_argv(
+    argv: &[String],
+    command: &[&str],
+    args: &[&str],
+    post_args: &[String],
+) -> Result<(String, Option<Vec<String>>), CommandParseError> {
+    if argv.is_empty() || argv[0].trim().is_empty() {
+        return Err(CommandParseError::CommandContentInvalid(
+            InvalidContentReason::EmptyCommand,
+        ));
+    }
+    let argv = substitute_argv(argv, command, args, post_args)?;
+    let quoted: Vec<String> = argv.iter().map(|arg| shell_quote(arg)).collect();
+    Ok((quoted.join(" "), Some(argv)))
+}
+
+/// Parses a duration made of a number and a unit, one of `s`, `m`, `h` or `d`, e.g. `5m`.
+///
+/// returns - The duration or `None` if it is invalid.
+fn parse_duration(duration: &str) -> Option<Duration> {
+    let unit = match duration.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let amount: u64 = duration[..duration.len() - 1].parse().ok()?;
+    Some(Duration::from_secs(amount.checked_mul(unit)?))
+}
+
+/// Extracts the shell conventions configured in the settings. Keys left out of a shell's table
+/// fall back to its built-in convention, and an empty `interactive_flag` disables it.
+///
+/// * `settings` - The program settings table.
+///
+/// returns - The conventions by shell name, or the reason 'shells' is invalid.
+fn get_shell_conventions(
+    settings: &Table,
+) -> Result<BTreeMap<String, ShellConvention>, InvalidContentReason> {
+    let shells = match settings.get("shells") {
+        Some(Value::Table(shells)) => shells,
+        Some(value) => {
+            return Err(InvalidContentReason::NotTomlTable(
+                "shells".to_string(),
+                value.to_owned(),
+            ))
+        }
+        None => return Ok(BTreeMap::new()),
+    };
+    let mut conventions = BTreeMap::new();
+    for (shell, value) in shells {
+        let shell_data = match value {
+            Value::Table(shell_data) => shell_data,
+            value => {
+                return Err(InvalidContentReason::NotTomlTable(
+                    shell.to_owned(),
+                    value.to_owned(),
+                ))
+            }
+        };
+        let mut convention = ShellConvention::builtin(shell);
+        if let Some(command_flag) = get_string(shell_data, "command_flag")? {
+            convention.command_flag = command_flag;
+        }
+        if let Some(interactive_flag) = get_string(shell_data, "interactive_flag")? {
+            convention.interactive_flag = Some(interactive_flag).filter(|flag| !flag.is_empty());
+        }
+        conventions.insert(shell.to_owned(), convention);
+    }
+    Ok(conventions)
+}
+
+/// Parses a .toml file and extracts the flag aliases defined in the settings.
+///
+/// * `path` - The path to the .toml file of the base command file.
+///
+/// returns - The flags each alias (without leading dashes) expands to,
+/// `InvalidContentReason::ConfigFlagAlias` if one expands to a flag choosing the config, or the
+/// error that occurred while retrieving them.
+pub(crate) fn get_flag_aliases(path: &Path) -> Result<BTreeMap<String, String>, CommandParseError> {
+    let config = load_config(path)?;
+    let settings = get_settings(&config)?;
+    let flags = match settings.get("flags") {
+        Some(Value::Table(flags)) => flags,
+        Some(value) => {
+            return Err(CommandParseError::CommandContentInvalid(
+                InvalidContentReason::NotTomlTable("flags".to_string(), value.to_owned()),
+            ))
+        }
+        None => return Ok(BTreeMap::new()),
+    };
+    let mut aliases = BTreeMap::new();
+    for alias in flags.keys() {
+        if let Some(expanded) = get_string(flags, alias)? {
+            let flag = expanded
+                .split_once('=')
+                .map_or(expanded.as_str(), |(flag, _)| flag);
+            if CONFIG_FLAGS.contains(&flag) {
+                return Err(CommandParseError::CommandContentInvalid(
+                    InvalidContentReason::ConfigFlagAlias(alias.to_owned(), expanded),
+                ));
+            }
+            aliases.insert(alias.trim_start_matches('-').to_owned(), expanded);
+        }
+    }
+    Ok(aliases)
+}
+
+/// Parses a .toml file and extracts help data
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The specified command to retrieve the action of.
+///
+/// returns - Pairs of (sub)commands' and it's (sub)commands', along with their description if
+/// present, and the documented exit codes, or the error that occurred while retrieving the
+/// command. The help pair with name `None` represents `command` and will always be present and
+/// first, even if it contains no description, followed by the subcommands sorted by name.
+pub fn get_command_help(path: &Path, command: &[&str]) -> Result<CommandHelp, CommandParseError> {
+    let config = load_config(path)?;
+    let toml_data = get_command_toml(&config, command)?;
+    command_help(&toml_data, command.is_empty())
+}
+
+/// Parses a .toml file and extracts the (sub)commands a (sub)command is nested in.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The specified command to retrieve the ancestors of.
+///
+/// returns - The name and description (see `HelpPair`) of each (sub)command on the path to
+/// `command`, outermost first and excluding `command` itself, or the error that occurred while
+/// retrieving them.
+pub(crate) fn get_command_ancestors(
+    path: &Path,
+    command: &[&str],
+) -> Result<Vec<HelpPair>, CommandParseError> {
+    let config = load_config(path)?;
+    let mut ancestors = vec![];
+    for i in 1..command.len() {
+        let toml_data = get_command_toml(&config, &command[..i])?;
+        let desc = toml_data.get("desc").and_then(|s| s.as_str());
+        ancestors.push(HelpPair(
+            Some(command[i - 1].to_string()),
+            desc.map(|s| s.to_owned()),
+        ));
+    }
+    Ok(ancestors)
+}
+
+/// Parses a .toml file and extracts help data of a (sub)command and every (sub)command beneath it.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The specified command to retrieve the help of.
+///
+/// returns - The path and help data (see `get_command_help`) of `command` followed by each
+/// (sub)command beneath it, depth first, or the error that occurred while retrieving them.
+pub(crate) fn get_command_help_all(
+    path: &Path,
+    command: &[&str],
+) -> Result<Vec<(Vec<String>, CommandHelp)>, CommandParseError> {
+    let config = load_config(path)?;
+    let toml_data = get_command_toml(&config, command)?;
+    let mut command_path: Vec<String> = command.iter().map(|s| s.to_string()).collect();
+    let mut helps = vec![];
+    collect_command_help(&toml_data, &mut command_path, &mut helps)?;
+    Ok(helps)
+}
+
+/// Parses a .toml file and extracts every runnable (sub)command at or beneath a (sub)command.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The specified command to list the (sub)commands of.
+/// * `all` - Whether to include hidden (sub)commands and those beneath them.
+///
+/// returns - The runnable (sub)commands sorted by path, or the error that occurred while
+/// retrieving them.
+pub(crate) fn get_command_list(
+    path: &Path,
+    command: &[&str],
+    all: bool,
+) -> Result<Vec<CommandEntry>, CommandParseError> {
+    let config = load_config(path)?;
+    let toml_data = get_command_toml(&config, command)?;
+    let mut command_path: Vec<String> = command.iter().map(|s| s.to_string()).collect();
+    let mut entries = vec![];
+    collect_commands(&toml_data, &mut command_path, all, false, &mut entries)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Recursively collects the runnable (sub)commands at or beneath a (sub)command.
+///
+/// * `toml_data` - The toml table of the (sub)command.
+/// * `command_path` - The path of the (sub)command. Restored to its original value on return.
+/// * `all` - Whether to include hidden (sub)commands and those beneath them.
+/// * `hidden` - Whether the (sub)command is hidden, itself or by being beneath a hidden one.
+/// * `entries` - The collected (sub)commands to append to.
+fn collect_commands(
+    toml_data: &Table,
+    command_path: &mut Vec<String>,
+    all: bool,
+    hidden: bool,
+    entries: &mut Vec<CommandEntry>,
+) -> Result<(), CommandParseError> {
+    if !command_path.is_empty()
+        && (toml_data.contains_key("command") || toml_data.contains_key("steps"))
+    {
+        entries.push(CommandEntry {
+            path: command_path.to_owned(),
+            desc: toml_data
+                .get("desc")
+                .and_then(|s| s.as_str())
+                .map(|s| s.to_owned()),
+            title: get_string(toml_data, "title")?,
+            hidden,
+            keys: reserved_keys(toml_data),
+        });
+    }
+    for (k, v) in subcommands(toml_data, command_path.is_empty()) {
+        if let Value::Table(sub_table) = v {
+            let sub_hidden = hidden || is_hidden(sub_table)?;
+            if !all && sub_hidden {
+                continue;
+            }
+            command_path.push(k.to_owned());
+            collect_commands(sub_table, command_path, all, sub_hidden, entries)?;
+            command_path.pop();
+        }
+    }
+    Ok(())
+}
+
+/// Parses a .toml file and extracts the details of every runnable (sub)command at or beneath a
+/// (sub)command. See `get_command_list`.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The specified command to list the (sub)commands of.
+/// * `all` - Whether to include hidden (sub)commands and those beneath them.
+///
+/// returns - The details of the runnable (sub)commands sorted by path, or the error that occurred
+/// while retrieving them.
+pub(crate) fn get_command_details(
+    path: &Path,
+    command: &[&str],
+    all: bool,
+) -> Result<Vec<CommandDetails>, CommandParseError> {
+    let config = load_config(path)?;
+    let settings = get_settings(&config)?;
+    let mut sources = BTreeMap::new();
+    collect_sources(path, &mut sources)?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut details = vec![];
+    for entry in get_command_list(path, command, all)? {
+        let command: Vec<&str> = entry.path.iter().map(String::as_str).collect();
+        let toml_data = get_command_toml(&config, &command)?;
+        let steps = get_steps(&toml_data)?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|step| match step {
+                Step::Shell(exec_cmd) => exec_cmd,
+                Step::Argv(argv) => argv
+                    .iter()
+                    .map(|arg| shell_quote(arg))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            })
+            .collect();
+        details.push(CommandDetails {
+            steps,
+            cwd: get_string(&toml_data, "cwd")?.map(|cwd| resolve_dir(&cwd, base)),
+            env: get_env(&toml_data)?,
+            timeout: resolve_timeout(&settings, &toml_data)?,
+            source: match sources.get(&entry.path) {
+                Some(source) => source.to_owned(),
+                None => std::path::absolute(path)?,
+            },
+            entry,
+        });
+    }
+    Ok(details)
+}
+
+/// Recursively finds the file defining each runnable (sub)command of a config. The files are
+/// visited in the order their values take precedence when merged (see `load_config_file`), so the
+/// first to define a (sub)command is the one whose definition is used.
+///
+/// * `path` - The path to the .toml file.
+/// * `sources` - The files defining the (sub)commands found so far by path, to add to.
+///
+/// returns - Nothing, or the error that occurred while reading a file.
+fn collect_sources(
+    path: &Path,
+    sources: &mut BTreeMap<Vec<String>, PathBuf>,
+) -> Result<(), CommandParseError> {
+    let toml_str = &String::from_utf8(read_config(path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let config = toml_to_map(toml_str)?;
+    let source = std::path::absolute(path)?;
+    let mut paths = vec![];
+    collect_commands(&config, &mut vec![], true, false, &mut paths)?;
+    for entry in paths {
+        sources
+            .entry(entry.path)
+            .or_insert_with(|| source.to_owned());
+    }
+    let base = path.parent().unwrap_or(Path::new("."));
+    for include in get_string_array(&get_settings(&config)?, "include")?.unwrap_or_default() {
+        collect_sources(&resolve_dir(&include, base), sources)?;
+    }
+    Ok(())
+}
+
+/// Gets the keys of a (sub)command that configure it rather than name a subcommand.
+///
+/// returns - The keys, sorted.
+fn reserved_keys(toml_data: &Table) -> Vec<String> {
+    let mut keys: Vec<String> = toml_data
+        .keys()
+        .filter(|key| RESERVED_KEYS.contains(&key.as_str()))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys
+}
+
+/// Parses a .toml file, along with the files it includes, and serializes a (sub)command and
+/// everything beneath it back into toml. The (sub)command stays nested under its path, so the
+/// result can be used as a config (or included by one) defining the same (sub)command.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The specified command to export, or nothing for the whole config.
+///
+/// returns - The toml, or the error that occurred while retrieving the (sub)command.
+pub(crate) fn export_command_toml(
+    path: &Path,
+    command: &[&str],
+) -> Result<String, CommandParseError> {
+    let config = load_config(path)?;
+    let mut toml_data = get_command_toml(&config, command)?;
+    // Included files are already merged in, so including them again is redundant.
+    if command.is_empty() {
+        if let Some(Value::Table(settings)) = toml_data.get_mut(SETTINGS_KEY) {
+            settings.remove("include");
+        }
+    }
+    for name in command.iter().rev() {
+        let mut parent = Table::new();
+        parent.insert(name.to_string(), Value::Table(toml_data));
+        toml_data = parent;
+    }
+    Ok(toml_data.to_string())
+}
+
+/// Parses a .toml file and extracts the hierarchy of (sub)commands beneath a (sub)command.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The specified command to get the hierarchy beneath.
+/// * `all` - Whether to include hidden (sub)commands and those beneath them.
+///
+/// returns - The (sub)commands directly beneath `command` sorted by name, or the error that
+/// occurred while retrieving them.
+pub(crate) fn get_command_tree(
+    path: &Path,
+    command: &[&str],
+    all: bool,
+) -> Result<Vec<CommandNode>, CommandParseError> {
+    let config = load_config(path)?;
+    let toml_data = get_command_toml(&config, command)?;
+    collect_tree(&toml_data, command.is_empty(), all)
+}
+
+/// Recursively collects the (sub)commands beneath a (sub)command.
+///
+/// * `toml_data` - The toml table of the (sub)command.
+/// * `is_root` - Whether `toml_data` is the root of the config.
+/// * `all` - Whether to include hidden (sub)commands and those beneath them.
+fn collect_tree(
+    toml_data: &Table,
+    is_root: bool,
+    all: bool,
+) -> Result<Vec<CommandNode>, CommandParseError> {
+    let mut nodes = vec![];
+    for (k, v) in subcommands(toml_data, is_root) {
+        if let Value::Table(sub_table) = v {
+            let hidden = is_hidden(sub_table)?;
+            if !all && hidden {
+                continue;
+            }
+            nodes.push(CommandNode {
+                name: k.to_owned(),
+                desc: sub_table
+                    .get("desc")
+                    .and_then(|s| s.as_str())
+                    .map(|s| s.to_owned()),
+                title: get_string(sub_table, "title")?,
+                is_command: sub_table.contains_key("command") || sub_table.contains_key("steps"),
+                hidden,
+                keys: reserved_keys(sub_table),
+                children: collect_tree(sub_table, false, all)?,
+            });
+        }
+    }
+    nodes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(nodes)
+}
+
+/// Recursively collects the help data of a (sub)command and every (sub)command beneath it.
+///
+/// * `toml_data` - The toml table of the (sub)command.
+/// * `command_path` - The path of the (sub)command. Restored to its original value on return.
+/// * `helps` - The collected paths and help data to append to.
+fn collect_command_help(
+    toml_data: &Table,
+    command_path: &mut Vec<String>,
+    helps: &mut Vec<(Vec<String>, CommandHelp)>,
+) -> Result<(), CommandParseError> {
+    helps.push((
+        command_path.to_owned(),
+        command_help(toml_data, command_path.is_empty())?,
+    ));
+    for (k, v) in subcommands(toml_data, command_path.is_empty()) {
+        if let Value::Table(sub_table) = v {
+            if is_hidden(sub_table)? {
+                continue;
+            }
+            command_path.push(k.to_owned());
+            collect_command_help(sub_table, command_path, helps)?;
+            command_path.pop();
+        }
+    }
+    Ok(())
+}
+
+/// Checks whether a (sub)command is hidden, i.e. left out of help and, unless asked for, the
+/// listings of (sub)commands, along with everything beneath it. It still runs as usual.
+///
+/// * `toml_data` - The toml table of the (sub)command.
+///
+/// returns - The 'hidden' of the (sub)command (default `false`), or the reason it is invalid.
+fn is_hidden(toml_data: &Table) -> Result<bool, InvalidContentReason> {
+    Ok(get_bool(toml_data, "hidden")?.unwrap_or(false))
+}
+
+/// Iterates over the entries of a command table that name subcommands.
+///
+/// * `toml_data` - The toml table of the command.
+/// * `is_root` - Whether `toml_data` is the root of the config.
+fn subcommands(toml_data: &Table, is_root: bool) -> impl Iterator<Item = (&String, &Value)> {
+    toml_data.iter().filter(move |(k, v)| {
+        let is_settings = is_root && k.as_str() == SETTINGS_KEY;
+        !is_reserved(k, v) && !is_settings
+    })
+}
+
+/// Checks whether an entry of a command table holds the value of a reserved key rather than a
+/// subcommand. A table under a reserved key is a subcommand if it has a 'command', 'steps' or
+/// tables of its own, e.g. `env = { command = "..." }` is a subcommand named `env`.
+///
+/// * `key` - The key of the entry.
+/// * `value` - The value of the entry.
+fn is_reserved(key: &str, value: &Value) -> bool {
+    RESERVED_KEYS.contains(&key)
+        && value.as_table().is_none_or(|table| {
+            !table.contains_key("command")
+                && !table.contains_key("steps")
+                && !table.values().any(Value::is_table)
+        })
+}
+
+/// Checks whether a token names a reserved key of a command table, or the settings at the root,
+/// rather than one of its subcommands. See `is_reserved`.
+///
+/// * `toml_data` - The toml table of the command.
+/// * `token` - The token.
+/// * `is_root` - Whether `toml_data` is the root of the config.
+fn is_reserved_name(toml_data: &Table, token: &str, is_root: bool) -> bool {
+    (is_root && token == SETTINGS_KEY)
+        || (RESERVED_KEYS.contains(&token)
+            && toml_data
+                .get(token)
+                .is_none_or(|value| is_reserved(token, value)))
+}
+
+/// Gets the value of a key of a table, unless it is a reserved key whose entry is a subcommand.
+/// See `is_reserved`.
+///
+/// * `table` - The table to read from.
+/// * `key` - The key.
+fn get_setting<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    table
+        .get(key)
+        .filter(|value| !RESERVED_KEYS.contains(&key) || is_reserved(key, value))
+}
+
+/// Extracts the help data of a command from its toml table.
+///
+/// * `toml_data` - The toml table of the command.
+/// * `is_root` - Whether `toml_data` is the root of the config.
+///
+/// returns - The help data (see `get_command_help`) or the reason it is invalid.
+fn command_help(toml_data: &Table, is_root: bool) -> Result<CommandHelp, CommandParseError> {
+    let mut help_pairs: Vec<HelpPair> = vec![];
+    if let Some(desc) = toml_data.get("desc").and_then(|s| s.as_str()) {
+        help_pairs.push(HelpPair(None, Some(desc.to_owned())))
+    } else {
+        help_pairs.push(HelpPair(None, None));
+    }
+
+    let mut titles = BTreeMap::new();
+    let mut aliases = BTreeMap::new();
+    let mut subcommands: Vec<(&String, &Value)> = subcommands(toml_data, is_root).collect();
+    // Sorted explicitly so the order doesn't depend on how the table stores its keys.
+    subcommands.sort_by_key(|(k, _)| *k);
+    for (k, v) in subcommands {
+        if let Value::Table(sub_table) = v {
+            if is_hidden(sub_table)? {
+                continue;
+            }
+        }
+        if let Some(desc) = v.get("desc").and_then(|s| s.as_str()) {
+            help_pairs.push(HelpPair(Some(k.to_owned()), Some(desc.to_owned())))
+        } else {
+            help_pairs.push(HelpPair(Some(k.to_owned()), None));
+        }
+        if let Value::Table(sub_table) = v {
+            if let Some(title) = get_string(sub_table, "title")? {
+                titles.insert(k.to_owned(), title);
+            }
+            match get_string_array(sub_table, "aliases")? {
+                Some(sub_aliases) if !sub_aliases.is_empty() => {
+                    aliases.insert(k.to_owned(), sub_aliases);
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(CommandHelp {
+        pairs: help_pairs,
+        exit_codes: get_exit_codes(toml_data)?,
+        usage: get_string(toml_data, "usage")?,
+        titles,
+        aliases,
+    })
+}
+
+/// Extracts the environment variables set for a command.
+///
+/// * `toml_data` - The toml table of the command.
+///
+/// returns - The environment variables by name, or the reason 'env' is invalid.
+fn get_env(toml_data: &Table) -> Result<BTreeMap<String, String>, InvalidContentReason> {
+    let env = match get_setting(toml_data, "env") {
+        Some(Value::Table(env)) => env,
+        Some(value) => {
+            return Err(InvalidContentReason::NotTomlTable(
+                "env".to_string(),
+                value.to_owned(),
+            ))
+        }
+        None => return Ok(BTreeMap::new()),
+    };
+    env.iter()
+        .map(|(key, value)| match value {
+            Value::String(value) => Ok((key.to_owned(), value.to_owned())),
+            value => Err(InvalidContentReason::NotTomlString(
+                key.to_owned(),
+                value.to_owned(),
+            )),
+        })
+        .collect()
+}
+
+/// Extracts the documented exit codes of a command.
+///
+/// * `toml_data` - The toml table of the command.
+///
+/// returns - The exit codes and their meaning sorted by exit code, or the reason 'exit_codes' is
+/// invalid.
+fn get_exit_codes(toml_data: &Table) -> Result<Vec<(i64, String)>, InvalidContentReason> {
+    let exit_codes = match get_setting(toml_data, "exit_codes") {
+        Some(Value::Table(exit_codes)) => exit_codes,
+        Some(value) => {
+            return Err(InvalidContentReason::NotTomlTable(
+                "exit_codes".to_string(),
+                value.to_owned(),
+            ))
+        }
+        None => return Ok(vec![]),
+    };
+    let mut codes = vec![];
+    for (code, meaning) in exit_codes {
+        let code = code
+            .parse::<i64>()
+            .map_err(|_| InvalidContentReason::InvalidExitCode(code.to_owned()))?;
+        match meaning {
+            Value::String(meaning) => codes.push((code, meaning.to_owned())),
+            value => {
+                return Err(InvalidContentReason::NotTomlString(
+                    "exit_codes".to_string(),
+                    value.to_owned(),
+                ))
+            }
+        }
+    }
+    codes.sort_by_key(|(code, _)| *code);
+    Ok(codes)
+}
+
+/// Expands a command string referencing a snippet in the settings, following snippets that
+/// reference other snippets.
+///
+/// * `settings` - The program settings table.
+/// * `exec_cmd` - The command string, which references the snippet `name` if it is `@name`.
+///
+/// returns - The referenced snippet, `exec_cmd` if it references none, or the reason the
+/// reference is invalid.
+fn expand_snippet(settings: &Table, exec_cmd: &str) -> Result<String, InvalidContentReason> {
+    let empty = Table::new();
+    let snippets = match settings.get("snippets") {
+        Some(Value::Table(snippets)) => snippets,
+        Some(value) => {
+            return Err(InvalidContentReason::NotTomlTable(
+                "snippets".to_string(),
+                value.to_owned(),
+            ))
+        }
+        None => &empty,
+    };
+    let mut seen = vec![];
+    let mut exec_cmd = exec_cmd;
+    while let Some(name) = snippet_name(exec_cmd) {
+        if seen.contains(&name) {
+            return Err(InvalidContentReason::SnippetCycle(name.to_string()));
+        }
+        seen.push(name);
+        exec_cmd = match snippets.get(name) {
+            Some(Value::String(snippet)) => snippet,
+            Some(value) => {
+                return Err(InvalidContentReason::NotTomlString(
+                    name.to_string(),
+                    value.to_owned(),
+                ))
+            }
+            None => return Err(InvalidContentReason::UnknownSnippet(name.to_string())),
+        };
+    }
+    Ok(exec_cmd.to_owned())
+}
+
+/// Gets the name of the snippet a command string references.
+///
+/// returns - `name` if the command string is `@name` where `name` only has alphanumerics, `_` and
+/// `-`, or `None` otherwise.
+fn snippet_name(exec_cmd: &str) -> Option<&str> {
+    let name = exec_cmd.trim().strip_prefix('@')?;
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    (!name.is_empty() && name.chars().all(is_name_char)).then_some(name)
+}
+
+/// A placeholder in a command string.
+#[derive(Debug, PartialEq)]
+enum Placeholder {
+    /// `{{command_path}}`, expanding to the shell-quoted command path.
+    CommandPath,
+    /// `{N}`, expanding to the shell-quoted Nth (from 1) argument.
+    Arg(usize),
+    /// `{@}`, expanding to every argument, each shell-quoted.
+    AllArgs,
+}
+
+/// Finds the placeholders in a command string. `{N}` and `{@}` directly after a `$` are shell
+/// parameter expansions rather than placeholders.
+///
+/// * `exec_cmd` - The command string to search.
+///
+/// returns - The byte range and kind of each placeholder, in order.
+fn find_placeholders(exec_cmd: &str) -> Vec<(Range<usize>, Placeholder)> {
+    let mut placeholders = vec![];
+    let mut start = 0;
+    while let Some(offset) = exec_cmd[start..].find('{') {
+        let open = start + offset;
+        start = open + 1;
+        let rest = &exec_cmd[open..];
+        if rest.starts_with("{{command_path}}") {
+            start = open + "{{command_path}}".len();
+            placeholders.push((open..start, Placeholder::CommandPath));
+            continue;
+        }
+        let close = match rest.find('}') {
+            Some(close) if !exec_cmd[..open].ends_with('$') => close,
+            _ => continue,
+        };
+        let placeholder = match &rest[1..close] {
+            "@" => Placeholder::AllArgs,
+            name if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) => {
+                match name.parse() {
+                    Ok(position) if position > 0 => Placeholder::Arg(position),
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+        start = open + close + 1;
+        placeholders.push((open..start, placeholder));
+    }
+    placeholders
+}
+
+/// Replaces each `${NAME}` in `s` with the value of the variable `NAME`, or each
+/// `${NAME:-default}` with `default` if the variable is unset or empty. `$${` is replaced with a
+/// literal `${`, and anything else, such as `$NAME` or `${1}`, is left as is.
+///
+/// * `s` - The string to interpolate.
+/// * `lookup` - Gets the value of a variable, or `None` if it is unset.
+///
+/// returns - The interpolated string, or `InvalidContentReason::UndefinedVariable` if a variable
+/// without a default is unset.
+fn interpolate_env(
+    s: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, InvalidContentReason> {
+    let mut interpolated = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        let after = &rest[start + 2..];
+        if rest[..start].ends_with('$') {
+            interpolated += &rest[..start - 1];
+            interpolated += "${";
+            rest = after;
+            continue;
+        }
+        interpolated += &rest[..start];
+        let inner = match after.find('}') {
+            Some(end) => &after[..end],
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let (name, default) = match inner.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (inner, None),
+        };
+        let is_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_name {
+            interpolated += "${";
+            rest = after;
+            continue;
+        }
+        match (lookup(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => interpolated += default,
+            (Some(value), _) => interpolated += &value,
+            (None, Some(default)) => interpolated += default,
+            (None, None) => return Err(InvalidContentReason::UndefinedVariable(name.to_string())),
+        }
+        rest = &after[inner.len() + 1..];
+    }
+    interpolated += rest;
+    Ok(interpolated)
+}
+
+/// Checks whether a command string takes arguments, i.e. has a `{N}` or `{@}` placeholder.
+fn takes_args(exec_cmd: &str) -> bool {
+    find_placeholders(exec_cmd)
+        .iter()
+        .any(|(_, placeholder)| *placeholder != Placeholder::CommandPath)
+}
+
+/// Expands the placeholders in a command string and appends its trailing arguments.
+///
+/// * `exec_cmd` - The command string to expand.
+/// * `command` - The command path `exec_cmd` was resolved from.
+/// * `args` - The arguments passed to the command. Appended, shell-quoted, if `exec_cmd` does not
+///   take arguments through placeholders.
+/// * `post_args` - Arguments appended, shell-quoted, to the end of the command.
+///
+/// returns - The command string with its placeholders (see `Placeholder`) expanded followed by
+/// `post_args`, or `CommandParseError::MissingArgument` if a `{N}` has no argument.
+fn substitute_placeholders(
+    exec_cmd: &str,
+    command: &[&str],
+    args: &[&str],
+    post_args: &[String],
+) -> Result<String, CommandParseError> {
+    let appended_args = if takes_args(exec_cmd) { &[] } else { args };
+    let mut expanded = expand_placeholders(exec_cmd, command, args, shell_quote)?;
+    for arg in appended_args
+        .iter()
+        .copied()
+        .chain(post_args.iter().map(|s| s.as_str()))
+    {
+        expanded += " ";
+        expanded += &shell_quote(arg);
+    }
+    Ok(expanded)
+}
+
+/// Expands the placeholders in the arguments of a command executed without a shell and appends its
+/// trailing arguments. Arguments are passed as is rather than shell-quoted, and an argument that is
+/// only `{@}` expands to every argument, each as a separate argument.
+///
+/// * `argv` - The program and its arguments to expand.
+/// * `command` - The command path `argv` was resolved from.
+/// * `args` - The arguments passed to the command. Appended if no argument of `argv` takes
+///   arguments through placeholders.
+/// * `post_args` - Arguments appended to the end of the command.
+///
+/// returns - `argv` with its placeholders (see `Placeholder`) expanded followed by `post_args`, or
+/// `CommandParseError::MissingArgument` if a `{N}` has no argument.
+fn substitute_argv(
+    argv: &[String],
+    command: &[&str],
+    args: &[&str],
+    post_args: &[String],
+) -> Result<Vec<String>, CommandParseError> {
+    let mut expanded = vec![];
+    for arg in argv {
+        if arg == "{@}" {
+            expanded.extend(args.iter().map(|arg| arg.to_string()));
+        } else {
+            expanded.push(expand_placeholders(arg, command, args, |arg| {
+                arg.to_string()
+            })?);
+        }
+    }
+    if !argv.iter().any(|arg| takes_args(arg)) {
+        expanded.extend(args.iter().map(|arg| arg.to_string()));
+    }
+    expanded.extend(post_args.iter().cloned());
+    Ok(expanded)
+}
+
+/// Expands the placeholders in a string.
+///
+/// * `exec_cmd` - The string to expand.
+/// * `command` - The command path `exec_cmd` was resolved from.
+/// * `args` - The arguments passed to the command.
+/// * `quote` - Quotes each argument, and the command path, substituted.
+///
+/// returns - The string with its placeholders (see `Placeholder`) expanded, or
+/// `CommandParseError::MissingArgument` if a `{N}` has no argument.
+fn expand_placeholders(
+    exec_cmd: &str,
+    command: &[&str],
+    args: &[&str],
+    quote: impl Fn(&str) -> String,
+) -> Result<String, CommandParseError> {
+    let mut expanded = String::new();
+    let mut end = 0;
+    for (range, placeholder) in find_placeholders(exec_cmd) {
+        expanded += &exec_cmd[end..range.start];
+        expanded += &match placeholder {
+            Placeholder::CommandPath => quote(&command.join(" ")),
+            Placeholder::Arg(position) => match args.get(position - 1) {
+                Some(arg) => quote(arg),
+                None => return Err(CommandParseError::MissingArgument(position)),
+            },
+            Placeholder::AllArgs => {
+                let quoted: Vec<String> = args.iter().map(|arg| quote(arg)).collect();
+                quoted.join(" ")
+            }
+        };
+        end = range.end;
+    }
+    expanded += &exec_cmd[end..];
+    Ok(expanded)
+}
+
+/// Computes the SHA-256 digest of `data`.
+///
+/// returns - The digest as a lowercase hex string.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Verifies the config file at `path` has the expected SHA-256 hash. Configs including other
+/// files are refused, as the hash would not cover them.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `expected` - The expected hash as hex. Case and surrounding whitespace are ignored.
+///
+/// returns - Nothing if the hash matches, `CommandParseError::ConfigHashMismatch` if it does not,
+/// `CommandParseError::UnverifiedInclude` if the config includes other files, or the error that
+/// occurred while reading the file.
+pub(crate) fn verify_config_hash(path: &Path, expected: &str) -> Result<(), CommandParseError> {
+    let contents = read_config(path)?;
+    let actual = sha256_hex(&contents);
+    let expected = expected.trim().to_lowercase();
+    if actual != expected {
+        return Err(CommandParseError::ConfigHashMismatch(expected, actual));
+    }
+    // An unparsable config is reported once it is loaded.
+    let includes = String::from_utf8(contents)
+        .ok()
+        .and_then(|toml_str| toml_to_map(&toml_str).ok())
+        .and_then(|config| get_settings(&config).ok())
+        .and_then(|settings| get_string_array(&settings, "include").ok().flatten())
+        .unwrap_or_default();
+    if includes.is_empty() {
+        Ok(())
+    } else {
+        Err(CommandParseError::UnverifiedInclude(path.to_owned()))
+    }
+}
+
+/// Verifies the config file at `path`, along with the files it includes, defines a command.
+///
+/// * `path` - The path to the .toml file of the base command file.
+///
+/// returns - Nothing if it defines a command, or `CommandParseError::EmptyConfig` if it does not,
+/// or the error that occurred while reading it.
+pub(crate) fn verify_has_commands(path: &Path) -> Result<(), CommandParseError> {
+    let config = load_config(path)?;
+    if subcommands(&config, true).next().is_some() {
+        Ok(())
+    } else {
+        Err(CommandParseError::EmptyConfig(path.to_owned()))
+    }
+}
+
+/// Checks a (sub)command of the config file at `path`, along with the files it includes, and every
+/// (sub)command beneath it for invalid content. Unlike resolving a command, this reports every
+/// problem instead of only the first, including those of commands that are never run.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The (sub)command to check, or an empty slice to check the whole config.
+///
+/// returns - The path of each (sub)command with a problem along with the problem, or the error
+/// that occurred while reading the config or finding `command`.
+pub(crate) fn check_config(
+    path: &Path,
+    command: &[&str],
+) -> Result<Vec<(Vec<String>, InvalidContentReason)>, CommandParseError> {
+    let config = load_config(path)?;
+    let settings = get_settings(&config)?;
+    let toml_data = get_command_toml(&config, command)?;
+    let mut command_path = command.iter().map(|s| s.to_string()).collect();
+    let mut problems = vec![];
+    collect_problems(&settings, &toml_data, &mut command_path, &mut problems);
+    Ok(problems)
+}
+
+/// Recursively collects the problems of a (sub)command and every (sub)command beneath it.
+///
+/// * `settings` - The program settings table, holding the snippets commands may reference.
+/// * `toml_data` - The toml table of the (sub)command.
+/// * `command_path` - The path of the (sub)command. Restored to its original value on return.
+/// * `problems` - The collected paths and problems to append to.
+fn collect_problems(
+    settings: &Table,
+    toml_data: &Table,
+    command_path: &mut Vec<String>,
+    problems: &mut Vec<(Vec<String>, InvalidContentReason)>,
+) {
+    let is_root = command_path.is_empty();
+    let mut reasons = vec![];
+    if !is_root {
+        reasons = check_command_table(settings, toml_data);
+    }
+    let mut alias_owners: BTreeMap<String, &str> = BTreeMap::new();
+    for (k, v) in subcommands(toml_data, is_root) {
+        let sub_table = match v {
+            Value::Table(sub_table) => sub_table,
+            value => {
+                reasons.push(InvalidContentReason::UnknownKey(
+                    k.to_owned(),
+                    value.to_owned(),
+                ));
+                continue;
+            }
+        };
+        // Invalid 'aliases' are reported as a problem of the subcommand itself.
+        for alias in get_string_array(sub_table, "aliases")
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+        {
+            if let Some(first) = alias_owners.insert(alias.to_owned(), k) {
+                reasons.push(InvalidContentReason::DuplicateAlias(
+                    alias,
+                    first.to_owned(),
+                    k.to_owned(),
+                ));
+            }
+        }
+    }
+    problems.extend(
+        reasons
+            .into_iter()
+            .map(|reason| (command_path.to_owned(), reason)),
+    );
+    for (k, v) in subcommands(toml_data, is_root) {
+        if let Value::Table(sub_table) = v {
+            command_path.push(k.to_owned());
+            collect_problems(settings, sub_table, command_path, problems);
+            command_path.pop();
+        }
+    }
+}
+
+/// Checks the keys of a (sub)command's table. Subcommands are not checked.
+///
+/// * `settings` - The program settings table, holding the snippets the command may reference.
+/// * `toml_data` - The toml table of the (sub)command.
+///
+/// returns - The problems found.
+fn check_command_table(settings: &Table, toml_data: &Table) -> Vec<InvalidContentReason> {
+    let checks = [
+        check_command(settings, toml_data),
+        get_shell(toml_data).map(drop),
+        get_default(toml_data).map(drop),
+        get_stdin(toml_data).map(drop),
+        get_cache(toml_data).map(drop),
+        get_timeout(toml_data).map(drop),
+        get_env(toml_data).map(drop),
+        get_exit_codes(toml_data).map(drop),
+        get_string(toml_data, "desc").map(drop),
+        get_string(toml_data, "title").map(drop),
+        get_string(toml_data, "usage").map(drop),
+        get_string(toml_data, "cwd").map(drop),
+        get_bool(toml_data, "strict").map(drop),
+        get_bool(toml_data, "trace").map(drop),
+        get_bool(toml_data, "ignore_errors").map(drop),
+        get_bool(toml_data, "hidden").map(drop),
+        get_bool(toml_data, "help_passthrough").map(drop),
+        get_string_array(toml_data, "aliases").map(drop),
+        get_string_array(toml_data, "post_args").map(drop),
+        get_string_array(toml_data, "help_args").map(drop),
+        get_string_array(toml_data, "shell_args").map(drop),
+    ];
+    checks.into_iter().filter_map(Result::err).collect()
+}
+
+/// Checks the 'command' of a (sub)command's table, which only a table without subcommands needs.
+///
+/// * `settings` - The program settings table, holding the snippets the command may reference.
+/// * `toml_data` - The toml table of the (sub)command.
+///
+/// returns - Nothing if the 'command' is valid, or the reason it is not.
+fn check_command(settings: &Table, toml_data: &Table) -> Result<(), InvalidContentReason> {
+    let steps = match get_steps(toml_data)? {
+        Some(steps) => steps,
+        None if subcommands(toml_data, false).any(|(_, v)| v.is_table()) => return Ok(()),
+        None => return Err(InvalidContentReason::MissingKey("command".to_string())),
+    };
+    if steps.is_empty() {
+        return Err(InvalidContentReason::EmptyCommand);
+    }
+    check_needs_shell(toml_data, &steps, get_shell(toml_data)?.1)?;
+    for step in steps {
+        let is_empty = match step {
+            Step::Shell(exec_cmd) => expand_snippet(settings, &exec_cmd)?.trim().is_empty(),
+            Step::Argv(argv) => argv.first().is_none_or(|program| program.trim().is_empty()),
+        };
+        if is_empty {
+            return Err(InvalidContentReason::EmptyCommand);
+        }
+    }
+    Ok(())
+}
+
+/// Reads and parses the config file at `path` along with the files it includes.
+///
+/// * `path` - The path to the .toml file of the base command file.
+///
+/// returns - The root table of the config, or the error that occurred while reading it.
+fn load_config(path: &Path) -> Result<Table, CommandParseError> {
+    load_config_file(path, &mut vec![])
+}
+
+/// Reads and parses a config file, merging in the files listed in its 'include' setting. Paths
+/// are resolved relative to the directory of the including file, as is a relative 'cwd' of the
+/// commands an included file defines.
+///
+/// * `path` - The path to the .toml file.
+/// * `including` - The canonical paths of the files (transitively) including `path`.
+///
+/// returns - The root table of the config, or the error that occurred while reading it or the
+/// files it includes.
+fn load_config_file(path: &Path, including: &mut Vec<PathBuf>) -> Result<Table, CommandParseError> {
+    let toml_str = &String::from_utf8(read_config(path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let mut config = toml_to_map(toml_str)?;
+    let includes = get_string_array(&get_settings(&config)?, "include")?.unwrap_or_default();
+    if includes.is_empty() {
+        return Ok(config);
+    }
+    including.push(fs::canonicalize(path)?);
+    let base = path.parent().unwrap_or(Path::new("."));
+    for include in includes {
+        let include = resolve_dir(&include, base);
+        let canonical = fs::canonicalize(&include).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => CommandParseError::IncludeNotFound(include.to_owned()),
+            _ => CommandParseError::IoError(err),
+        })?;
+        if including.contains(&canonical) {
+            return Err(CommandParseError::CircularInclude(include));
+        }
+        let mut included = load_config_file(&include, including)?;
+        let include_dir = std::path::absolute(&include)?;
+        resolve_cwds(&mut included, include_dir.parent().unwrap_or(base), true);
+        merge_tables(&mut config, included);
+    }
+    including.pop();
+    Ok(config)
+}
+
+/// Recursively resolves the relative 'cwd' of every (sub)command of an included config against
+/// the directory of the included file, so it isn't resolved against the including file's later.
+///
+/// * `toml_data` - The toml table of the (sub)command.
+/// * `base` - The directory of the included file.
+/// * `is_root` - Whether `toml_data` is the root of the config.
+fn resolve_cwds(toml_data: &mut Table, base: &Path, is_root: bool) {
+    if let Some(Value::String(cwd)) = toml_data.get_mut("cwd") {
+        *cwd = resolve_dir(cwd, base).to_string_lossy().into_owned();
+    }
+    let keys: Vec<String> = subcommands(toml_data, is_root)
+        .map(|(key, _)| key.to_owned())
+        .collect();
+    for key in keys {
+        if let Some(Value::Table(table)) = toml_data.get_mut(&key) {
+            resolve_cwds(table, base, false);
+        }
+    }
+}
+
+/// Recursively merges an included config into a config. Values `config` already defines are kept
+/// rather than replaced, except tables, which are merged.
+///
+/// * `config` - The config to merge into.
+/// * `included` - The included config.
+fn merge_tables(config: &mut Table, included: Table) {
+    for (key, value) in included {
+        match (config.get_mut(&key), value) {
+            (Some(Value::Table(table)), Value::Table(included)) => merge_tables(table, included),
+            (Some(_), _) => {}
+            (None, value) => {
+                config.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Reads the raw contents of the config file at `path`.
+///
+/// returns - The contents, `CommandParseError::ConfigNotReadable` if the file can't be read due to
+/// its permissions, or the error that occurred while reading it.
+fn read_config(path: &Path) -> Result<Vec<u8>, CommandParseError> {
+    fs::read(path).map_err(|err| match err.kind() {
+        io::ErrorKind::PermissionDenied => CommandParseError::ConfigNotReadable(path.to_owned()),
+        _ => CommandParseError::IoError(err),
+    })
+}
+
+/// Extracts the program settings table from the root of the config.
+///
+/// returns - The settings table, which is empty if not defined, or
+/// `InvalidContentReason::NotTomlTable` if it is not a table.
+fn get_settings(config: &Table) -> Result<Table, InvalidContentReason> {
+    match config.get(SETTINGS_KEY) {
+        Some(Value::Table(settings)) => Ok(settings.to_owned()),
+        Some(value) => Err(InvalidContentReason::NotTomlTable(
+            SETTINGS_KEY.to_string(),
+            value.to_owned(),
+        )),
+        None => Ok(Table::new()),
+    }
+}
+
+/// Extracts the maximum command depth from the settings.
+///
+/// returns - The configured maximum depth, `DEFAULT_MAX_DEPTH` if not configured, or
+/// `InvalidContentReason::NotTomlInteger` if it is not a non-negative integer.
+fn get_max_depth(settings: &Table) -> Result<usize, InvalidContentReason> {
+    match settings.get("max_depth") {
+        Some(Value::Integer(max_depth)) if *max_depth >= 0 => Ok(*max_depth as usize),
+        Some(value) => Err(InvalidContentReason::NotTomlInteger(
+            "max_depth".to_string(),
+            value.to_owned(),
+        )),
+        None => Ok(DEFAULT_MAX_DEPTH),
+    }
+}
+
+/// Extracts an optional string from a table.
+///
+/// * `table` - The table to read from.
+/// * `key` - The key of the string.
+///
+/// returns - The string if the key is present, `None` if it is not, or
+/// `InvalidContentReason::NotTomlString` if it is not a string.
+fn get_string(table: &Table, key: &str) -> Result<Option<String>, InvalidContentReason> {
+    match get_setting(table, key) {
+        Some(Value::String(s)) => Ok(Some(s.to_owned())),
+        Some(value) => Err(InvalidContentReason::NotTomlString(
+            key.to_string(),
+            value.to_owned(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Extracts an optional boolean from a table.
+///
+/// * `table` - The table to read from.
+/// * `key` - The key of the boolean.
+///
+/// returns - The boolean if the key is present, `None` if it is not, or
+/// `InvalidContentReason::NotTomlBoolean` if it is not a boolean.
+fn get_bool(table: &Table, key: &str) -> Result<Option<bool>, InvalidContentReason> {
+    match get_setting(table, key) {
+        Some(Value::Boolean(b)) => Ok(Some(*b)),
+        Some(value) => Err(InvalidContentReason::NotTomlBoolean(
+            key.to_string(),
+            value.to_owned(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Extracts an optional array of strings from a table.
+///
+/// * `table` - The table to read from.
+/// * `key` - The key of the array.
+///
+/// returns - The strings if the key is present, `None` if it is not, or the reason the value is
+/// not an array of strings.
+fn get_string_array(table: &Table, key: &str) -> Result<Option<Vec<String>>, InvalidContentReason> {
+    match get_setting(table, key) {
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(s) => Ok(s.to_owned()),
+                value => Err(InvalidContentReason::NotTomlString(
+                    key.to_string(),
+                    value.to_owned(),
+                )),
+            })
+            .collect::<Result<Vec<String>, _>>()
+            .map(Some),
+        Some(value) => Err(InvalidContentReason::NotTomlArray(
+            key.to_string(),
+            value.to_owned(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Splits the tokens of a command into the command path and the arguments passed to it. Tokens
+/// only become arguments after the path reaches a table with a 'command' that has no subcommand
+/// named by the next token; otherwise every token is part of the path, so a mistyped subcommand of
+/// a table without a 'command' is still reported as not found.
+///
+/// * `config` - The root table of the config.
+/// * `command` - The tokens of the command.
+///
+/// returns - The command path and the arguments, or the reason the 'aliases' of a table on the
+/// path are invalid.
+fn split_args<'a, 'b>(
+    config: &Table,
+    command: &'a [&'b str],
+) -> Result<(&'a [&'b str], &'a [&'b str]), InvalidContentReason> {
+    let mut toml_data = config;
+    for (i, token) in command.iter().enumerate() {
+        match get_subcommand(toml_data, token, i == 0)? {
+            Some(Value::Table(next_table)) if !is_reserved_name(toml_data, token, i == 0) => {
+                toml_data = next_table;
+            }
+            _ => {
+                if i > 0 && has_command(toml_data) {
+                    return Ok(command.split_at(i));
+                }
+                break;
+            }
+        }
+    }
+    Ok((command, &[]))
+}
+
+/// Checks whether a command can be run, i.e. its path, once the arguments passed to it are split
+/// off (see `split_args`), names a table with a 'command' or 'steps', or a group with a
+/// 'default'.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The tokens of the command.
+///
+/// returns - Whether the command can be run, which it can't if the config is invalid.
+pub(crate) fn is_runnable(path: &Path, command: &[&str]) -> bool {
+    let config = match load_config(path) {
+        Ok(config) => config,
+        Err(_) => return false,
+    };
+    match split_args(&config, command) {
+        Ok((command, _)) if !command.is_empty() => {
+            get_command_toml(&config, command).is_ok_and(|toml_data| {
+                has_command(&toml_data) || get_default(&toml_data).is_ok_and(|d| d.is_some())
+            })
+        }
+        _ => false,
+    }
+}
+
+/// Checks whether a command table has a 'command' or 'steps' to execute.
+fn has_command(toml_data: &Table) -> bool {
+    get_setting(toml_data, "command").is_some_and(|command| command.is_str() || command.is_array())
+        || get_setting(toml_data, "steps").is_some_and(Value::is_array)
+}
+
+/// A step of a command.
+enum Step {
+    /// A command executed through a shell.
+    Shell(String),
+    /// A program and its arguments executed directly.
+    Argv(Vec<String>),
+}
+
+impl Step {
+    /// Checks whether the step takes the arguments passed to the command through placeholders.
+    fn takes_args(&self) -> bool {
+        match self {
+            Step::Shell(exec_cmd) => takes_args(exec_cmd),
+            Step::Argv(argv) => argv.iter().any(|arg| takes_args(arg)),
+        }
+    }
+}
+
+/// Checks that a command executed without a shell has no string steps, which would need a shell to
+/// be split into the program and its arguments.
+///
+/// * `toml_data` - The toml table of the command.
+/// * `steps` - The steps of the command.
+/// * `use_shell` - Whether the command is executed through a shell.
+///
+/// returns - Nothing if the steps can be executed, or `InvalidContentReason::NeedsShell`.
+fn check_needs_shell(
+    toml_data: &Table,
+    steps: &[Step],
+    use_shell: bool,
+) -> Result<(), InvalidContentReason> {
+    if use_shell || !steps.iter().any(|step| matches!(step, Step::Shell(_))) {
+        return Ok(());
+    }
+    let key = if toml_data.contains_key("steps") {
+        "steps"
+    } else {
+        "command"
+    };
+    Err(InvalidContentReason::NeedsShell(key.to_string()))
+}
+
+/// Extracts the steps of a command: the single step of its 'command', or each of its 'steps'. A
+/// string step is executed through a shell, and an array step is the program and its arguments.
+/// A step of 'steps' can also be a table with a 'name' and the step as its 'command'.
+///
+/// * `toml_data` - The toml table of the command.
+///
+/// returns - The steps if the command has a 'command' or 'steps', or the reason they are invalid,
+/// including when it has both.
+fn get_steps(toml_data: &Table) -> Result<Option<Vec<Step>>, InvalidContentReason> {
+    let to_step = |key: &str, value: &Value| match value {
+        Value::String(exec_cmd) => Ok(Step::Shell(exec_cmd.to_owned())),
+        Value::Array(argv) => argv
+            .iter()
+            .map(|arg| match arg {
+                Value::String(arg) => Ok(arg.to_owned()),
+                arg => Err(InvalidContentReason::NotTomlString(
+                    key.to_string(),
+                    arg.to_owned(),
+                )),
+            })
+            .collect::<Result<Vec<String>, _>>()
+            .map(Step::Argv),
+        value => Err(InvalidContentReason::NotTomlString(
+            key.to_string(),
+            value.to_owned(),
+        )),
+    };
+    match (
+        get_setting(toml_data, "command"),
+        get_setting(toml_data, "steps"),
+    ) {
+        (Some(_), Some(_)) => Err(InvalidContentReason::ConflictingKeys(
+            "command".to_string(),
+            "steps".to_string(),
+        )),
+        (Some(exec_cmd), None) => to_step("command", exec_cmd).map(|step| Some(vec![step])),
+        (None, Some(Value::Array(steps))) => steps
+            .iter()
+            .map(|step| match step {
+                Value::Table(step) => {
+                    get_string(step, "name")?;
+                    match step.get("command") {
+                        Some(exec_cmd) => to_step("command", exec_cmd),
+                        None => Err(InvalidContentReason::MissingKey("command".to_string())),
+                    }
+                }
+                step => to_step("steps", step),
+            })
+            .collect::<Result<Vec<Step>, _>>()
+            .map(Some),
+        (None, Some(steps)) => Err(InvalidContentReason::NotTomlArray(
+            "steps".to_string(),
+            steps.to_owned(),
+        )),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Extracts the names of the steps of a command. Only the steps of 'steps' given as a table have
+/// a 'name'.
+///
+/// * `toml_data` - The toml table of the command.
+///
+/// returns - The name of each step, or `None` for steps without one, or the reason a 'name' is
+/// invalid.
+fn get_step_names(toml_data: &Table) -> Result<Vec<Option<String>>, InvalidContentReason> {
+    match get_setting(toml_data, "steps") {
+        Some(Value::Array(steps)) => steps
+            .iter()
+            .map(|step| match step {
+                Value::Table(step) => get_string(step, "name"),
+                _ => Ok(None),
+            })
+            .collect(),
+        _ => Ok(vec![None]),
+    }
+}
+
+/// Selects the named steps of a command, keeping the order they are defined in rather than the
+/// order they are named in.
+///
+/// * `toml_data` - The toml table of the command.
+/// * `names` - The names of the steps to keep.
+///
+/// returns - The toml table of the command with only the selected 'steps', or
+/// `CommandParseError::StepNotFound` if a name is not the name of any step.
+fn select_steps(toml_data: &Table, names: &[String]) -> Result<Table, CommandParseError> {
+    let step_names = get_step_names(toml_data)?;
+    if let Some(name) = names
+        .iter()
+        .find(|&name| !step_names.contains(&Some(name.to_owned())))
+    {
+        return Err(CommandParseError::StepNotFound(name.to_owned()));
+    }
+    let mut toml_data = toml_data.to_owned();
+    if let Some(Value::Array(steps)) = toml_data.get_mut("steps") {
+        let mut step_names = step_names.iter();
+        steps.retain(|_| {
+            step_names
+                .next()
+                .is_some_and(|name| name.as_ref().is_some_and(|name| names.contains(name)))
+        });
+    }
+    Ok(toml_data)
+}
+
+/// Gets the entry of a command table named by a token, which is either the key of the entry or,
+/// if there is no such key, one of the 'aliases' of a subcommand.
+///
+/// * `toml_data` - The toml table of the command.
+/// * `token` - The token naming the entry.
+/// * `is_root` - Whether `toml_data` is the root of the config.
+///
+/// returns - The entry if there is one, or the reason the 'aliases' of the subcommands are
+/// invalid.
+fn get_subcommand<'a>(
+    toml_data: &'a Table,
+    token: &str,
+    is_root: bool,
+) -> Result<Option<&'a Value>, InvalidContentReason> {
+    Ok(get_subcommand_entry(toml_data, token, is_root)?.map(|(_, v)| v))
+}
+
+/// Gets the entry of a command table named by a token along with its key. See `get_subcommand`.
+///
+/// * `toml_data` - The toml table of the command.
+/// * `token` - The token naming the entry.
+/// * `is_root` - Whether `toml_data` is the root of the config.
+///
+/// returns - The key and value of the entry if there is one, or the reason the 'aliases' of the
+/// subcommands are invalid.
+fn get_subcommand_entry<'a>(
+    toml_data: &'a Table,
+    token: &str,
+    is_root: bool,
+) -> Result<Option<(&'a String, &'a Value)>, InvalidContentReason> {
+    if let Some(entry) = toml_data.get_key_value(token) {
+        return Ok(Some(entry));
+    }
+    let mut found: Option<(&String, &Value)> = None;
+    for (k, v) in subcommands(toml_data, is_root) {
+        let aliases = match v {
+            Value::Table(sub_table) => get_string_array(sub_table, "aliases")?.unwrap_or_default(),
+            _ => continue,
+        };
+        if aliases.iter().any(|alias| alias == token) {
+            if let Some((first, _)) = found {
+                return Err(InvalidContentReason::DuplicateAlias(
+                    token.to_string(),
+                    first.to_owned(),
+                    k.to_owned(),
+                ));
+            }
+            found = Some((k, v));
+        }
+    }
+    Ok(found)
+}
+
+/// Gets the canonical path of a command, naming each (sub)command by its key rather than by the
+/// alias it may have been invoked with.
+///
+/// * `config` - The root table of the config.
+/// * `command` - The command path.
+///
+/// returns - The keys of the (sub)commands on the path, or the reason the 'aliases' of a table on
+/// the path are invalid.
+fn canonical_path(config: &Table, command: &[&str]) -> Result<Vec<String>, InvalidContentReason> {
+    let mut toml_data = config;
+    let mut path = vec![];
+    for (i, token) in command.iter().enumerate() {
+        match get_subcommand_entry(toml_data, token, i == 0)? {
+            Some((key, Value::Table(next_table))) => {
+                path.push(key.to_owned());
+                toml_data = next_table;
+            }
+            _ => path.push(token.to_string()),
+        }
+    }
+    Ok(path)
+}
+
+/// Traces the lookup of each token of a command, as done when resolving it. Each step is a line of
+/// space separated `key=value` fields: the dotted path of the `table` the token is looked up in
+/// (`<root>` for the root), the `keys` of its subcommands, the `token` and the `decision` made,
+/// which is one of
+/// * `key` - The token is the key of a subcommand.
+/// * `alias:<name>` - The token is an alias of the subcommand `<name>`.
+/// * `args` - The token and those after it are arguments.
+/// * `reserved` - The token is a reserved name.
+/// * `not_table` - The token is the key of an entry that isn't a subcommand.
+/// * `not_found` - The token names nothing.
+///
+/// The lookup stops at the first token that isn't a subcommand.
+///
+/// * `path` - The path to the .toml file of the base command file.
+/// * `command` - The tokens of the command.
+///
+/// returns - The trace, or the error that occurred while reading the config.
+pub(crate) fn trace_command_resolution(
+    path: &Path,
+    command: &[&str],
+) -> Result<Vec<String>, CommandParseError> {
+    let config = load_config(path)?;
+    let mut toml_data = &config;
+    let mut table_path: Vec<&str> = vec![];
+    let mut trace = vec![];
+    for (i, token) in command.iter().enumerate() {
+        let table = match i {
+            0 => "<root>".to_string(),
+            _ => table_path.join("."),
+        };
+        let keys: Vec<&str> = subcommands(toml_data, i == 0)
+            .filter(|(_, v)| v.is_table())
+            .map(|(k, _)| k.as_str())
+            .collect();
+        let mut next = None;
+        let decision = if is_reserved_name(toml_data, token, i == 0) {
+            "reserved".to_string()
+        } else {
+            match get_subcommand(toml_data, token, i == 0)? {
+                Some(Value::Table(sub_table)) => {
+                    // Found by alias if the token isn't its key, so find the key by identity.
+                    let (name, _) = subcommands(toml_data, i == 0)
+                        .find(|(_, v)| v.as_table().is_some_and(|t| ptr::eq(t, sub_table)))
+                        .expect("subcommand is an entry of its table");
+                    next = Some((name.as_str(), sub_table));
+                    if name == token {
+                        "key".to_string()
+                    } else {
+                        format!("alias:{}", name)
+                    }
+                }
+                Some(_) => "not_table".to_string(),
+                None if i > 0 && has_command(toml_data) => "args".to_string(),
+                None => "not_found".to_string(),
+            }
+        };
+        trace.push(format!(
+            "table={} keys={} token={} decision={}",
+            table,
+            keys.join(","),
+            token,
+            decision
+        ));
+        match next {
+            Some((name, sub_table)) => {
+                table_path.push(name);
+                toml_data = sub_table;
+            }
+            None => break,
+        }
+    }
+    Ok(trace)
+}
+
+/// Extracts the toml table of the specified command from the config.
+///
+/// * `config` - The root table of the config.
+/// * `command` - The specified command to retrieve the action of.
+///
+/// returns - The toml table of the (sub)command if it is present, or the error that occurred while
+/// retrieving the command action.
+fn get_command_toml(config: &Table, command: &[&str]) -> Result<Table, CommandParseError> {
+    let max_depth = get_max_depth(&get_settings(config)?)?;
+    if command.len() > max_depth {
+        return Err(CommandParseError::MaxDepthExceeded(max_depth));
+    }
+    let mut toml_data = config.to_owned();
+    let mut command_not_found = false;
+    let mut error_string: String = Default::default();
+    let mut suggestion = None;
+    for (i, token) in command.iter().enumerate() {
+        if !command_not_found {
+            if is_reserved_name(&toml_data, token, i == 0) {
+                return Err(CommandParseError::ReservedName(token.to_string()));
+            }
+            match get_subcommand(&toml_data, token, i == 0)? {
+                Some(Value::Table(next_table)) => {
+                    toml_data = next_table.to_owned();
+                }
+                Some(value) => {
+                    return Err(CommandParseError::CommandContentInvalid(
+                        InvalidContentReason::NotTomlTable(token.to_string(), value.to_owned()),
+                    ));
+                }
+                None => {
+                    command_not_found = true;
+                    error_string += token;
+                    let candidates = subcommands(&toml_data, i == 0)
+                        .filter(|(_, v)| v.is_table())
+                        .map(|(k, _)| k.as_str());
+                    suggestion = closest_match(token, candidates).map(|s| s.to_owned());
+                }
+            }
+        } else {
+            error_string += " ";
+            error_string += token;
+        }
+    }
+    if command_not_found {
+        // TODO: Improve error messages here.
+        // If the command is 'a b c' and 'c' doesn't exist, we currently show
+        //    `Error: command 'c' not found.`
+        // but this is more confusing than the issue it tries to avoid. Something like
+        //    `Error: command 'c' in 'a b' not found.`
+        // or something like (but not exactly)
+        //    `Error: command 'c' not found but parent 'a b' found.`
+        // Whatever is chosen should be tested properly because there isn't always a parent in the
+        // command.
+        Err(CommandParseError::CommandNotFoundError(
+            error_string,
+            suggestion,
+        ))
+    } else {
+        Ok(toml_data)
+    }
+}
+
+/// Finds the candidate closest to `target` by edit distance, provided it is close enough to be a
+/// likely typo, i.e. at most a third of the characters of `target` (and at least one) differ.
+///
+/// * `target` - The mistyped string.
+/// * `candidates` - The strings that may have been meant. Ties go to the earliest candidate.
+fn closest_match<'a>(target: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let threshold = (target.chars().count() / 3).max(1);
+    candidates
+        .map(|candidate| (edit_distance(target, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Computes the Levenshtein distance between `a` and `b`, i.e. the number of characters that must
+/// be inserted, deleted or substituted to turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut curr = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(a_char != *b_char);
+            curr.push(substitution.min(prev[j + 1] + 1).min(curr[j] + 1));
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
     use io::Write;
 
-    use tempfile::NamedTempFile;
-    use test_case::test_case;
+    use tempfile::NamedTempFile;
+    use test_case::test_case;
+
+    use super::*;
+
+    const TOML_COMMAND_DATA: &[u8] = r#"
+            [foo]
+            bar = { command = "bar exec", desc = "bar desc" }
+            qux = "quux"
+            desc = "foo desc"
+            command = { }
+            [baz]
+        "#
+    .as_bytes();
+
+    #[test]
+    fn test_toml_to_map_invalid() {
+        let toml_str = "invalid toml";
+        let result = toml_to_map(toml_str);
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            CommandParseError::TomlDeError(_) => {}
+            err => panic!("Expected CommandParseError::TomlDeError, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_get_command_valid() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(TOML_COMMAND_DATA)
+            .unwrap();
+        let result = get_command(
+            temp_file.path(),
+            &"foo bar".split_whitespace().collect::<Vec<&str>>(),
+        );
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().command, "bar exec")
+    }
+
+    #[test_case("foo bar baz", "bar exec 'baz'" ; "bad command child of valid command")]
+    fn test_get_command_child_of_valid_command(cmd_str: &str, expected: &str) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(TOML_COMMAND_DATA)
+            .unwrap();
+        let result = get_command(
+            temp_file.path(),
+            &cmd_str.split_whitespace().collect::<Vec<&str>>(),
+        );
+        assert_eq!(result.unwrap().command, expected);
+    }
+
+    #[test_case("bar",  "bar", Some("baz")  ; "skipped subcommand")]
+    #[test_case("foo baz",  "baz", Some("bar")  ; "bad command child of valid subcommand")]
+    #[test_case("foo baz qux quux",  "baz qux quux", Some("bar")  ; "bad command with multiple invalid component")]
+    #[test_case("xyz",  "xyz", None  ; "nothing close")]
+    fn test_get_command_no_command(
+        cmd_str: &str,
+        invalid_portion: &str,
+        expected_suggestion: Option<&str>,
+    ) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(TOML_COMMAND_DATA)
+            .unwrap();
+        let result = get_command(
+            temp_file.path(),
+            &cmd_str.split_whitespace().collect::<Vec<&str>>(),
+        );
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            CommandParseError::CommandNotFoundError(s, suggestion) => {
+                assert_eq!(s, invalid_portion);
+                assert_eq!(suggestion.as_deref(), expected_suggestion);
+            }
+            err => panic!(
+                "Expected `CommandParseError::CommandNotFoundError`, got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test]
+    fn test_get_command_empty_command() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(TOML_COMMAND_DATA)
+            .unwrap();
+        let result = get_command(temp_file.path(), &["baz"]);
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::MissingKey(key)) => {
+                assert_eq!(key, "command")
+            }
+            err => panic!(
+                "Expected `CommandParseError::CommandNotFoundError`, got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test]
+    fn test_get_command_multiline() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all("c.command = '''\necho a\n  echo b \\\n    c\n'''".as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
+        assert_eq!(result.unwrap().command, "echo a\n  echo b \\\n    c\n");
+    }
+
+    #[test_case(r#"c = { command = "" }"# ; "empty")]
+    #[test_case(r#"c = { command = " \t\n " }"# ; "whitespace only")]
+    fn test_get_command_empty_string(config: &str) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
+        match result.unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::EmptyCommand) => {}
+            err => panic!(
+                "Expected wrapped `InvalidContentReason::EmptyCommand`, but got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test]
+    fn test_get_command_not_table() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(TOML_COMMAND_DATA)
+            .unwrap();
+        let result = get_command(
+            temp_file.path(),
+            &"foo qux".split_whitespace().collect::<Vec<&str>>(),
+        );
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::NotTomlTable(
+                key,
+                value,
+            )) => {
+                assert_eq!(key, "qux");
+                if let Value::String(_) = value {
+                } else {
+                    panic!("Expected a `Value::Table` but got {}", value)
+                }
+            }
+            err => panic!(
+                "Expected wrapped `InvalidContentReason::NotTomlTable`, but got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test]
+    fn test_get_command_not_string() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(TOML_COMMAND_DATA)
+            .unwrap();
+        let result = get_command(
+            temp_file.path(),
+            &"foo".split_whitespace().collect::<Vec<&str>>(),
+        );
+        assert!(result.is_err());
+        match result.unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::NotTomlString(
+                key,
+                value,
+            )) => {
+                assert_eq!(key, "command");
+                if let Value::String(_) = value {
+                    panic!("Expected a `Value::String` but got {}", value)
+                }
+            }
+            err => panic!(
+                "Expected wrapped `InvalidContentReason::NotTomlString`, but got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test_case(r#"c = { command = "x" }"#, &[] ; "none")]
+    #[test_case(r#"
+            xrun = { shell_args = ["-o", "pipefail"] }
+            c = { command = "x" }
+        "#, &["-o", "pipefail"] ; "global")]
+    #[test_case(r#"
+            xrun = { shell_args = ["-o", "pipefail"] }
+            c = { command = "x", shell_args = ["-e"] }
+        "#, &["-e"] ; "command overrides global")]
+    fn test_get_command_shell_args(config: &str, expected: &[&str]) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().shell_args, expected);
+    }
+
+    #[test]
+    fn test_get_command_shell_args_not_string() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(r#"c = { command = "x", shell_args = ["-e", 1] }"#.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
+        match result.unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::NotTomlString(
+                key,
+                _,
+            )) => assert_eq!(key, "shell_args"),
+            err => panic!(
+                "Expected wrapped `InvalidContentReason::NotTomlString`, but got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test_case("", "sh", "-c", None ; "builtin")]
+    #[test_case("", "/bin/bash", "-c", Some("-i") ; "builtin interactive")]
+    #[test_case(r#"pwsh = { command_flag = "-Command" }"#, "pwsh", "-Command", None ; "command flag")]
+    #[test_case(r#"bash = { interactive_flag = "-l" }"#, "/bin/bash", "-c", Some("-l") ; "interactive flag")]
+    #[test_case(r#"bash = { interactive_flag = "" }"#, "/bin/bash", "-c", None ; "interactive flag disabled")]
+    fn test_get_command_shell_convention(
+        shells: &str,
+        shell: &str,
+        command_flag: &str,
+        interactive_flag: Option<&str>,
+    ) {
+        let config = format!("c = {{ command = \"x\" }}\n[xrun.shells]\n{}", shells);
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
+        assert_eq!(
+            result.unwrap().shell_convention(shell),
+            ShellConvention {
+                command_flag: command_flag.to_string(),
+                interactive_flag: interactive_flag.map(|flag| flag.to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_get_command_shell_convention_not_table() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                r#"
+                c = { command = "x" }
+                xrun = { shells = { pwsh = "-Command" } }
+                "#
+                .as_bytes(),
+            )
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
+        match result.unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::NotTomlTable(
+                key,
+                _,
+            )) => assert_eq!(key, "pwsh"),
+            err => panic!(
+                "Expected wrapped `InvalidContentReason::NotTomlTable`, but got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test_case(r#"c = { command = "@base" }"#, "cargo build" ; "snippet")]
+    #[test_case(r#"c = { command = "@alias" }"#, "cargo build" ; "chained")]
+    #[test_case(r#"c = { command = " @base\n" }"#, "cargo build" ; "surrounding whitespace")]
+    #[test_case(r#"c = { command = "@base --release" }"#, "@base --release" ; "not only a reference")]
+    #[test_case(r#"c = { command = "@" }"#, "@" ; "no name")]
+    fn test_get_command_snippet(command: &str, expected: &str) {
+        let config = format!(
+            "{}\n[xrun.snippets]\nbase = \"cargo build\"\nalias = \"@base\"",
+            command
+        );
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
+        assert_eq!(result.unwrap().command, expected);
+    }
+
+    #[test]
+    fn test_get_command_snippet_unknown() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(r#"c = { command = "@dne" }"#.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
+        match result.unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::UnknownSnippet(
+                name,
+            )) => assert_eq!(name, "dne"),
+            err => panic!(
+                "Expected wrapped `InvalidContentReason::UnknownSnippet`, but got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test_case(r#"a = "@a""# ; "self reference")]
+    #[test_case(r#"a = "@b"
+            b = "@a""# ; "mutual")]
+    fn test_get_command_snippet_cycle(snippets: &str) {
+        let config = format!("c = {{ command = \"@a\" }}\n[xrun.snippets]\n{}", snippets);
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
+        match result.unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::SnippetCycle(name)) => {
+                assert_eq!(name, "a")
+            }
+            err => panic!(
+                "Expected wrapped `InvalidContentReason::SnippetCycle`, but got {:?}",
+                err
+            ),
+        }
+    }
 
-    use super::*;
+    #[test_case("30s", Some(30) ; "seconds")]
+    #[test_case("5m", Some(300) ; "minutes")]
+    #[test_case("2h", Some(7200) ; "hours")]
+    #[test_case("1d", Some(86400) ; "days")]
+    #[test_case("5", None ; "no unit")]
+    #[test_case("m", None ; "no amount")]
+    #[test_case("-5m", None ; "negative")]
+    #[test_case("5w", None ; "unknown unit")]
+    #[test_case("", None ; "empty")]
+    fn test_parse_duration(duration: &str, expected: Option<u64>) {
+        assert_eq!(parse_duration(duration), expected.map(Duration::from_secs));
+    }
 
-    const TOML_COMMAND_DATA: &[u8] = r#"
-            [foo]
-            bar = { command = "bar exec", desc = "bar desc" }
-            qux = "quux"
-            desc = "foo desc"
-            command = { }
-            [baz]
-        "#
-    .as_bytes();
+    #[test_case("", Some(None) ; "absent")]
+    #[test_case("timeout = 0", Some(Some(Duration::ZERO)) ; "zero")]
+    #[test_case("timeout = 300", Some(Some(Duration::from_secs(300))) ; "seconds")]
+    #[test_case("timeout = -1", None ; "negative")]
+    #[test_case(r#"timeout = "5m""#, Some(Some(Duration::from_secs(300))) ; "duration")]
+    #[test_case(r#"timeout = "300""#, Some(Some(Duration::from_secs(300))) ; "seconds string")]
+    #[test_case(r#"timeout = "5x""#, None ; "invalid duration")]
+    #[test_case("timeout = true", None ; "not integer or string")]
+    fn test_get_timeout(toml_str: &str, expected: Option<Option<Duration>>) {
+        let toml_data = toml_to_map(toml_str).unwrap();
+        assert_eq!(get_timeout(&toml_data).ok(), expected);
+    }
+
+    #[test_case("", "", None ; "none")]
+    #[test_case("timeout = 60", "", Some(60) ; "global")]
+    #[test_case("timeout = 60", "timeout = 5", Some(5) ; "command wins")]
+    #[test_case("timeout = 60", "timeout = 0", None ; "command disables")]
+    #[test_case("timeout = 0", "", None ; "global zero")]
+    fn test_resolve_timeout(settings: &str, toml_str: &str, expected: Option<u64>) {
+        let settings = toml_to_map(settings).unwrap();
+        let toml_data = toml_to_map(toml_str).unwrap();
+        assert_eq!(
+            resolve_timeout(&settings, &toml_data).unwrap(),
+            expected.map(Duration::from_secs)
+        );
+    }
 
     #[test]
-    fn test_toml_to_map_invalid() {
-        let toml_str = "invalid toml";
-        let result = toml_to_map(toml_str);
-        assert!(result.is_err());
+    fn test_get_command_cache_invalid() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(r#"c = { command = "x", cache = "soon" }"#.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
         match result.unwrap_err() {
-            CommandParseError::TomlDeError(_) => {}
-            err => panic!("Expected CommandParseError::TomlDeError, got {:?}", err),
+            CommandParseError::CommandContentInvalid(InvalidContentReason::InvalidDuration(
+                key,
+                value,
+            )) => {
+                assert_eq!(key, "cache");
+                assert_eq!(value, "soon");
+            }
+            err => panic!(
+                "Expected wrapped `InvalidContentReason::InvalidDuration`, but got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test_case("", DEFAULT_MAX_DEPTH ; "default")]
+    #[test_case("xrun = { max_depth = 2 }", 2 ; "configured")]
+    fn test_get_command_max_depth(config: &str, max_depth: usize) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let command = vec!["a"; max_depth + 1];
+        let result = get_command(temp_file.path(), &command);
+        match result.unwrap_err() {
+            CommandParseError::MaxDepthExceeded(depth) => assert_eq!(depth, max_depth),
+            err => panic!(
+                "Expected `CommandParseError::MaxDepthExceeded`, got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test]
+    fn test_get_command_max_depth_not_integer() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(r#"xrun = { max_depth = "2" }"#.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["a"]);
+        match result.unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::NotTomlInteger(
+                key,
+                _,
+            )) => assert_eq!(key, "max_depth"),
+            err => panic!(
+                "Expected wrapped `InvalidContentReason::NotTomlInteger`, but got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test_case("foo", "'foo'" ; "plain")]
+    #[test_case("foo bar", "'foo bar'" ; "space")]
+    #[test_case("it's", r"'it'\''s'" ; "single quote")]
+    #[test_case("", "''" ; "empty")]
+    fn test_shell_quote(s: &str, expected: &str) {
+        assert_eq!(shell_quote(s), expected);
+    }
+
+    #[test_case("echo {{command_path}}", &["s", "c1"], "echo 's c1'" ; "path")]
+    #[test_case("echo {{command_path}} {{command_path}}", &["c"], "echo 'c' 'c'" ; "repeated")]
+    #[test_case("echo {command_path}", &["c"], "echo {command_path}" ; "single braces")]
+    fn test_substitute_placeholders(exec_cmd: &str, command: &[&str], expected: &str) {
+        let result = substitute_placeholders(exec_cmd, command, &[], &[]);
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_substitute_placeholders_post_args() {
+        let post_args = vec!["--".to_string(), "a b".to_string()];
+        let result = substitute_placeholders("git diff", &["d"], &[], &post_args);
+        assert_eq!(result.unwrap(), "git diff '--' 'a b'");
+    }
+
+    #[test_case("deploy {1} {2}", &["staging", "v2"], "deploy 'staging' 'v2'" ; "positional")]
+    #[test_case("echo {2} {1} {1}", &["a", "b"], "echo 'b' 'a' 'a'" ; "reordered")]
+    #[test_case("echo {@}", &["a b", "it's"], r"echo 'a b' 'it'\''s'" ; "all")]
+    #[test_case("echo {@}", &[], "echo " ; "all empty")]
+    #[test_case("echo {1}", &["{2}"], "echo '{2}'" ; "placeholder in arg")]
+    #[test_case("echo ${1} {0} {x} {}", &["a"], "echo ${1} {0} {x} {} 'a'" ; "not placeholders")]
+    #[test_case("echo {{1}}", &["a"], "echo {'a'}" ; "double braces")]
+    fn test_substitute_placeholders_args(exec_cmd: &str, args: &[&str], expected: &str) {
+        let result = substitute_placeholders(exec_cmd, &["c"], args, &[]);
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_substitute_placeholders_missing_arg() {
+        let result = substitute_placeholders("echo {1} {3}", &["c"], &["a", "b"], &[]);
+        match result.unwrap_err() {
+            CommandParseError::MissingArgument(position) => assert_eq!(position, 3),
+            err => panic!(
+                "Expected `CommandParseError::MissingArgument`, got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test_case("c a b", "echo 'a' 'b'" ; "args")]
+    #[test_case("c sub a", "sub 'a'" ; "subcommand takes precedence")]
+    #[test_case("c desc", "echo 'desc'" ; "reserved key as arg")]
+    #[test_case("d status --short", "git 'status' '--short' '--end'" ; "appended before post args")]
+    #[test_case("d", "git '--end'" ; "no args")]
+    fn test_get_command_args(cmd_str: &str, expected: &str) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                r#"
+                d = { command = "git", post_args = ["--end"] }
+                [c]
+                command = "echo {@}"
+                sub = { command = "sub {1}" }
+                "#
+                .as_bytes(),
+            )
+            .unwrap();
+        let result = get_command(
+            temp_file.path(),
+            &cmd_str.split_whitespace().collect::<Vec<&str>>(),
+        );
+        assert_eq!(result.unwrap().command, expected);
+    }
+
+    #[test_case("sh", &[], "#!/usr/bin/env sh" ; "relative shell")]
+    #[test_case("/bin/bash", &[], "#!/bin/bash" ; "absolute shell")]
+    #[test_case("/bin/bash", &["-o", "pipefail"], "#!/usr/bin/env -S /bin/bash -o pipefail" ; "shell args")]
+    fn test_to_script(shell: &str, shell_args: &[&str], shebang: &str) {
+        let resolved = ResolvedCommand {
+            command: "echo hi".to_string(),
+            argv: None,
+            shell: None,
+            shell_args: shell_args.iter().map(|s| s.to_string()).collect(),
+            strict: false,
+            stdin: StdinMode::Inherit,
+            trace: false,
+            cwd: None,
+            env: BTreeMap::new(),
+            shells: BTreeMap::new(),
+            missing_shell: MissingShell::Fallback,
+            cache: None,
+            ignore_errors: false,
+            timeout: None,
+        };
+        let script = resolved.to_script(shell, Path::new("/tmp/some dir"));
+        assert_eq!(
+            script,
+            format!("{}\ncd '/tmp/some dir'\necho hi\n", shebang)
+        );
+    }
+
+    #[test]
+    fn test_to_script_env() {
+        let resolved = ResolvedCommand {
+            command: "echo hi".to_string(),
+            argv: None,
+            shell: None,
+            shell_args: vec![],
+            strict: false,
+            stdin: StdinMode::Inherit,
+            trace: false,
+            cwd: None,
+            env: BTreeMap::from([("GREETING".to_string(), "it's hi".to_string())]),
+            shells: BTreeMap::new(),
+            missing_shell: MissingShell::Fallback,
+            cache: None,
+            ignore_errors: false,
+            timeout: None,
+        };
+        let script = resolved.to_script("/bin/sh", Path::new("/tmp"));
+        assert_eq!(
+            script,
+            "#!/bin/sh\ncd '/tmp'\nexport GREETING='it'\\''s hi'\necho hi\n"
+        );
+    }
+
+    #[test_case(r#"c = { command = "x", env = { A = "1", B = "2" } }"#, Ok(&[("A", "1"), ("B", "2")]) ; "strings")]
+    #[test_case(r#"c = { command = "x" }"#, Ok(&[]) ; "missing")]
+    #[test_case(r#"c = { command = "x", env = { A = 1 } }"#, Err("Expected key 'A' to be String but got Integer") ; "not string")]
+    #[test_case(r#"c = { command = "x", env = "A=1" }"#, Err("Expected key 'env' to be Table but got String") ; "not table")]
+    fn test_get_command_env(config: &str, expected: Result<&[(&str, &str)], &str>) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
+        match expected {
+            Ok(expected) => {
+                let expected: BTreeMap<String, String> = expected
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect();
+                assert_eq!(result.unwrap().env, expected);
+            }
+            Err(expected) => match result.unwrap_err() {
+                CommandParseError::CommandContentInvalid(reason) => {
+                    assert_eq!(reason.to_string(), expected)
+                }
+                err => panic!(
+                    "Expected `CommandParseError::CommandContentInvalid`, got {:?}",
+                    err
+                ),
+            },
+        }
+    }
+
+    #[test_case("sub", "sub" ; "relative")]
+    #[test_case("/abs", "/abs" ; "absolute")]
+    fn test_get_command_cwd(cwd: &str, expected: &str) {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let config = tmp_dir.path().join("command.toml");
+        fs::write(
+            &config,
+            format!(r#"c = {{ command = "x", cwd = "{}" }}"#, cwd),
+        )
+        .unwrap();
+        let result = get_command(&config, &["c"]).unwrap();
+        assert_eq!(result.cwd, Some(tmp_dir.path().join(expected)));
+    }
+
+    #[test_case("", false ; "empty")]
+    #[test_case("xrun = { strict = true }", false ; "only settings")]
+    #[test_case(r#"c = { command = "x" }"#, true ; "command")]
+    fn test_verify_has_commands(config: &str, expected: bool) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        match verify_has_commands(temp_file.path()) {
+            Ok(()) => assert!(expected),
+            Err(CommandParseError::EmptyConfig(path)) => {
+                assert!(!expected);
+                assert_eq!(path, temp_file.path());
+            }
+            Err(err) => panic!("Expected `CommandParseError::EmptyConfig`, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_get_command_shell() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                r#"
+                    a = { command = "x", shell = "bash" }
+                    b = { command = "x" }
+                "#
+                .as_bytes(),
+            )
+            .unwrap();
+        let result = get_command(temp_file.path(), &["a"]).unwrap();
+        assert_eq!(result.shell.as_deref(), Some("bash"));
+        let result = get_command(temp_file.path(), &["b"]).unwrap();
+        assert_eq!(result.shell, None);
+    }
+
+    #[test_case(&["echo", "a;b"], &["x"], &["echo", "a;b", "x"] ; "appended")]
+    #[test_case(&["cp", "{2}", "{1}"], &["a b", "c"], &["cp", "c", "a b"] ; "positional")]
+    #[test_case(&["echo", "{@}", "end"], &["a b", "c"], &["echo", "a b", "c", "end"] ; "all args")]
+    #[test_case(&["echo", "--name={@}"], &["a", "b"], &["echo", "--name=a b"] ; "all args within")]
+    #[test_case(&["echo", "${1}"], &["a"], &["echo", "${1}", "a"] ; "not placeholder")]
+    fn test_substitute_argv(argv: &[&str], args: &[&str], expected: &[&str]) {
+        let argv: Vec<String> = argv.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            substitute_argv(&argv, &["c"], args, &["--post".to_string()]).unwrap(),
+            [expected, &["--post"]].concat()
+        );
+    }
+
+    #[test_case("${A}/bin", "a/bin" ; "defined")]
+    #[test_case("${A}${B}", "ab" ; "adjacent")]
+    #[test_case("${C:-c d}", "c d" ; "default")]
+    #[test_case("${E:-e}", "e" ; "default when empty")]
+    #[test_case("${E}", "" ; "empty")]
+    #[test_case("${A:-x}", "a" ; "default unused")]
+    #[test_case("$A ${1} ${A", "$A ${1} ${A" ; "not interpolated")]
+    #[test_case("$${A} $${C:-c}", "${A} ${C:-c}" ; "escaped")]
+    fn test_interpolate_env(s: &str, expected: &str) {
+        let lookup = |name: &str| match name {
+            "A" => Some("a".to_string()),
+            "B" => Some("b".to_string()),
+            "E" => Some(String::new()),
+            _ => None,
+        };
+        assert_eq!(interpolate_env(s, lookup).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_interpolate_env_undefined() {
+        match interpolate_env("x ${C} y", |_| None).unwrap_err() {
+            InvalidContentReason::UndefinedVariable(name) => assert_eq!(name, "C"),
+            err => panic!("Expected `UndefinedVariable`, got {:?}", err),
+        }
+    }
+
+    #[test_case(r#"c = { command = ["echo", "a;b"] }"#, Some(&["echo", "a;b", "x y"]), "'echo' 'a;b' 'x y'" ; "array")]
+    #[test_case(r#"c = { command = ["echo", "a;b"], shell = "bash" }"#, Some(&["echo", "a;b", "x y"]), "'echo' 'a;b' 'x y'" ; "array with shell")]
+    #[test_case(r#"c = { command = "echo a;b", shell = true }"#, None, "echo a;b 'x y'" ; "shell")]
+    fn test_get_command_argv(config: &str, expected: Option<&[&str]>, expected_command: &str) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c", "x y"]).unwrap();
+        let expected: Option<Vec<String>> =
+            expected.map(|expected| expected.iter().map(|s| s.to_string()).collect());
+        assert_eq!(result.argv, expected);
+        assert_eq!(result.command, expected_command);
+    }
+
+    #[test_case(r#"c = { command = [] }"# ; "empty array")]
+    #[test_case(r#"c = { command = [], shell = false }"# ; "empty argv")]
+    #[test_case(r#"c = { command = [" "] }"# ; "empty program")]
+    #[test_case(r#"c = { steps = [] }"# ; "empty steps")]
+    #[test_case(r#"c = { steps = ["echo a", " "] }"# ; "empty step")]
+    fn test_get_command_argv_empty(config: &str) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        match get_command(temp_file.path(), &["c"]).unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::EmptyCommand) => {}
+            err => panic!(
+                "Expected `InvalidContentReason::EmptyCommand`, got {:?}",
+                err
+            ),
+        }
+    }
+
+    #[test_case(r#"c = { command = "echo a;b", shell = false }"#, "command" ; "command")]
+    #[test_case(r#"c = { steps = [["echo", "a"], "echo b"], shell = false }"#, "steps" ; "steps")]
+    fn test_get_command_needs_shell(config: &str, expected: &str) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        match get_command_steps(temp_file.path(), &["c"]).unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::NeedsShell(key)) => {
+                assert_eq!(key, expected)
+            }
+            err => panic!("Expected `NeedsShell`, got {:?}", err),
+        }
+    }
+
+    #[test_case(r#"default = "d""# , Some("echo d") ; "default")]
+    #[test_case(r#"default = "a""# , Some("echo d") ; "default alias")]
+    #[test_case(r#"default = "d"
+        command = "echo c""#, Some("echo c") ; "command over default")]
+    #[test_case(r#"default = "e""# , None ; "nonexistent")]
+    #[test_case(r#"default = "g""# , None ; "group")]
+    #[test_case(r#"default = "desc""# , None ; "reserved")]
+    fn test_get_command_default(group: &str, expected: Option<&str>) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = format!(
+            "[c]\n{}\ndesc = \"c desc\"\nd = {{ command = \"echo d\", aliases = [\"a\"] }}\n\
+             g = {{ x = {{ command = \"echo x\" }} }}",
+            group
+        );
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        match (get_command(temp_file.path(), &["c"]), expected) {
+            (Ok(resolved), Some(expected)) => assert_eq!(resolved.command, expected),
+            (
+                Err(CommandParseError::CommandContentInvalid(
+                    InvalidContentReason::InvalidDefault(_),
+                )),
+                None,
+            ) => {}
+            (result, _) => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test_case(r#"c = { steps = ["echo a", "echo b"] }"#, &["echo a", "echo b 'x y'"] ; "args to last")]
+    #[test_case(r#"c = { steps = ["echo {1}", "echo b"] }"#, &["echo 'x y'", "echo b"] ; "args to placeholder")]
+    #[test_case(r#"c = { steps = ["echo a", ["printf", "%s|"]] }"#, &["echo a", "'printf' '%s|' 'x y'"] ; "argv step")]
+    #[test_case(r#"c = { steps = [["echo", "{@}"], "echo b"] }"#, &["'echo' 'x y'", "echo b"] ; "argv step placeholder")]
+    #[test_case(r#"c = { command = "echo a" }"#, &["echo a 'x y'"] ; "single")]
+    fn test_get_command_steps(config: &str, expected: &[&str]) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let steps = get_command_steps(temp_file.path(), &["c", "x y"]).unwrap();
+        let commands: Vec<&str> = steps.iter().map(|step| step.command.as_str()).collect();
+        assert_eq!(commands, expected);
+        assert!(steps.iter().all(|step| !step.ignore_errors));
+    }
+
+    #[test_case(&[], &["echo a", "echo b", "'echo' 'c'"] ; "all")]
+    #[test_case(&["c", "a"], &["echo a", "'echo' 'c' 'x'"] ; "config order")]
+    #[test_case(&["a"], &["echo a 'x'"] ; "args to last selected")]
+    fn test_get_command_steps_named(names: &[&str], expected: &[&str]) {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(
+            temp_file.path(),
+            r#"c = { steps = [{ name = "a", command = "echo a" }, "echo b", { name = "c", command = ["echo", "c"] }] }"#,
+        )
+        .unwrap();
+        let steps = if names.is_empty() {
+            get_command_steps(temp_file.path(), &["c"]).unwrap()
+        } else {
+            let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+            get_command_steps_named(temp_file.path(), &["c", "x"], &names, false).unwrap()
+        };
+        let commands: Vec<&str> = steps.iter().map(|step| step.command.as_str()).collect();
+        assert_eq!(commands, expected);
+    }
+
+    #[test_case(r#"c = { steps = [{ name = "a", command = "a" }] }"#, "b" ; "unknown")]
+    #[test_case(r#"c = { command = "a" }"#, "a" ; "no steps")]
+    fn test_get_command_steps_named_not_found(config: &str, name: &str) {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), config).unwrap();
+        let names = [name.to_string()];
+        match get_command_steps_named(temp_file.path(), &["c"], &names, false).unwrap_err() {
+            CommandParseError::StepNotFound(not_found) => assert_eq!(not_found, name),
+            err => panic!("Expected `StepNotFound`, got {:?}", err),
+        }
+    }
+
+    #[test_case(r#"c = { steps = [{ name = "a" }] }"#, InvalidContentReason::MissingKey("command".to_string()) ; "missing command")]
+    #[test_case(r#"c = { steps = [{ name = 1, command = "a" }] }"#, InvalidContentReason::NotTomlString("name".to_string(), Value::Integer(1)) ; "name not string")]
+    fn test_get_command_steps_named_invalid(config: &str, expected: InvalidContentReason) {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), config).unwrap();
+        match get_command_steps(temp_file.path(), &["c"]).unwrap_err() {
+            CommandParseError::CommandContentInvalid(reason) => {
+                assert_eq!(reason.to_string(), expected.to_string())
+            }
+            err => panic!("Expected `CommandContentInvalid`, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_get_command_steps_conflicting() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(br#"c = { command = "echo a", steps = ["echo b"] }"#)
+            .unwrap();
+        match get_command_steps(temp_file.path(), &["c"]).unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::ConflictingKeys(
+                first,
+                second,
+            )) => assert_eq!((first.as_str(), second.as_str()), ("command", "steps")),
+            err => panic!("Expected `ConflictingKeys`, got {:?}", err),
         }
     }
 
+    #[test_case(&["s", "st", "x"], &[
+        "table=<root> keys=s token=s decision=key",
+        "table=s keys=status token=st decision=alias:status",
+        "table=s.status keys= token=x decision=args",
+    ] ; "alias and args")]
+    #[test_case(&["s", "desc"], &["table=<root> keys=s token=s decision=key", "table=s keys=status token=desc decision=reserved"] ; "reserved")]
+    #[test_case(&["x", "y"], &["table=<root> keys=s token=x decision=not_found"] ; "not found")]
+    #[test_case(&["v"], &["table=<root> keys=s token=v decision=not_table"] ; "not table")]
+    fn test_trace_command_resolution(command: &[&str], expected: &[&str]) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                br#"
+                    v = 1
+                    [s]
+                    desc = "s desc"
+                    command = "echo s"
+                    status = { command = "echo status", aliases = ["st"] }
+                "#,
+            )
+            .unwrap();
+        assert_eq!(
+            trace_command_resolution(temp_file.path(), command).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_check_config() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                br#"
+                    top = "oops"
+                    ok = { command = "echo ok", desc = "ok desc" }
+                    [a]
+                    desc = 3
+                    command = 5
+                    [b]
+                    x = { command = "echo x", aliases = ["y"] }
+                    z = { steps = ["echo z", " "], aliases = ["y"], stdin = "foo" }
+                    [c]
+                    desc = "no command"
+                    [d]
+                    env = { command = "echo e" }
+                "#,
+            )
+            .unwrap();
+        let problems: Vec<(String, String)> = check_config(temp_file.path(), &[])
+            .unwrap()
+            .into_iter()
+            .map(|(path, reason)| (path.join(" "), reason.to_string()))
+            .collect();
+        let expected = [
+            (
+                "",
+                "Key 'top' is not a known key, and being String it is not a subcommand either",
+            ),
+            ("a", "Expected key 'command' to be String but got Integer"),
+            ("a", "Expected key 'desc' to be String but got Integer"),
+            ("b", "Alias 'y' is used by both 'x' and 'z'"),
+            ("b z", "Key 'command' is defined but empty"),
+            ("b z", "Key 'stdin' has unknown value 'foo'"),
+            ("c", "Expected key 'command' but it is not present"),
+        ];
+        let expected: Vec<(String, String)> = expected
+            .iter()
+            .map(|(path, reason)| (path.to_string(), reason.to_string()))
+            .collect();
+        assert_eq!(problems, expected);
+        let problems = check_config(temp_file.path(), &["ok"]).unwrap();
+        assert!(problems.is_empty());
+    }
+
+    // TODO: Fix test cases being a bit haphazard and very ugly.
+    // Likely start providing separate configs for each instead of trying to hammer a single config
+    // into doing it all.
+    #[test_case("foo", vec![
+        (None, Some("foo desc".to_string())),
+        (Some("bar".to_string()),Some("bar desc".to_string())),
+        (Some("qux".to_string()), None)
+    ]; "parent and child")]
+    #[test_case("foo bar", vec![(None, Some("bar desc".to_string()))]; "parent only")]
+    #[test_case("", vec![(None, None),(Some("baz".to_string()), None),(Some("foo".to_string()),Some("foo desc".to_string()))]; "children only")]
+    #[test_case("baz", vec![(None, None)]; "empty")]
+    fn test_get_command_help(command: &str, expected: Vec<(Option<String>, Option<String>)>) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(TOML_COMMAND_DATA)
+            .unwrap();
+        let result = get_command_help(
+            temp_file.path(),
+            &command.split_whitespace().collect::<Vec<&str>>(),
+        );
+
+        let expected: Vec<HelpPair> = expected
+            .into_iter()
+            .map(|(name, desc)| HelpPair(name, desc))
+            .collect();
+        assert_eq!(result.unwrap().pairs, expected);
+    }
+
     #[test]
-    fn test_get_command_valid() {
+    fn test_get_command_help_exit_codes() {
         let temp_file = NamedTempFile::new().unwrap();
         temp_file
             .reopen()
             .unwrap()
-            .write_all(TOML_COMMAND_DATA)
+            .write_all(
+                r#"c = { command = "x", exit_codes = { "2" = "error", "0" = "success" } }"#
+                    .as_bytes(),
+            )
             .unwrap();
-        let result = get_command(
-            temp_file.path(),
-            &"foo bar".split_whitespace().collect::<Vec<&str>>(),
+        let result = get_command_help(temp_file.path(), &["c"]).unwrap();
+        assert_eq!(
+            result.exit_codes,
+            vec![(0, "success".to_string()), (2, "error".to_string())]
         );
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "bar exec")
+        assert_eq!(result.pairs, vec![HelpPair(None, None)]);
     }
 
-    #[test_case("bar",  "bar"  ; "skipped subcommand")]
-    #[test_case("foo baz",  "baz"  ; "bad command child of valid subcommand")]
-    #[test_case("foo bar baz",  "baz"  ; "bad command child of valid command")]
-    #[test_case("foo bar baz qux quux",  "baz qux quux"  ; "bad command with multiple invalid component")]
-    fn test_get_command_no_command(cmd_str: &str, invalid_portion: &str) {
+    #[test]
+    fn test_get_command_help_exit_codes_invalid_key() {
         let temp_file = NamedTempFile::new().unwrap();
         temp_file
             .reopen()
             .unwrap()
-            .write_all(TOML_COMMAND_DATA)
+            .write_all(r#"c = { command = "x", exit_codes = { "ok" = "success" } }"#.as_bytes())
             .unwrap();
-        let result = get_command(
-            temp_file.path(),
-            &cmd_str.split_whitespace().collect::<Vec<&str>>(),
-        );
-        assert!(result.is_err());
+        let result = get_command_help(temp_file.path(), &["c"]);
         match result.unwrap_err() {
-            CommandParseError::CommandNotFoundError(s) => assert_eq!(s, invalid_portion),
+            CommandParseError::CommandContentInvalid(InvalidContentReason::InvalidExitCode(
+                key,
+            )) => assert_eq!(key, "ok"),
             err => panic!(
-                "Expected `CommandParseError::CommandNotFoundError`, got {:?}",
+                "Expected wrapped `InvalidContentReason::InvalidExitCode`, but got {:?}",
                 err
             ),
         }
     }
 
     #[test]
-    fn test_get_command_empty_command() {
+    fn test_get_command_help_all() {
         let temp_file = NamedTempFile::new().unwrap();
         temp_file
             .reopen()
             .unwrap()
             .write_all(TOML_COMMAND_DATA)
             .unwrap();
-        let result = get_command(temp_file.path(), &["baz"]);
-        assert!(result.is_err());
-        match result.unwrap_err() {
-            CommandParseError::CommandContentInvalid(InvalidContentReason::MissingKey(key)) => {
-                assert_eq!(key, "command")
+        let result = get_command_help_all(temp_file.path(), &["foo"]).unwrap();
+        let paths: Vec<Vec<String>> = result.into_iter().map(|(path, _)| path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                vec!["foo".to_string()],
+                vec!["foo".to_string(), "bar".to_string()]
+            ]
+        );
+    }
+
+    #[test_case(r#"c = { command = "x", usage = "xrun c <file>" }"#, Some("xrun c <file>") ; "custom")]
+    #[test_case(r#"c = { command = "x" }"#, None ; "generated")]
+    fn test_get_command_help_usage(config: &str, expected: Option<&str>) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let result = get_command_help(temp_file.path(), &["c"]).unwrap();
+        assert_eq!(result.usage.as_deref(), expected);
+    }
+
+    #[test_case("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad", true ; "matching")]
+    #[test_case(" BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD\n", true ; "case and whitespace")]
+    #[test_case("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", false ; "mismatch")]
+    fn test_verify_config_hash(expected: &str, matches: bool) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file.reopen().unwrap().write_all(b"abc").unwrap();
+        let result = verify_config_hash(temp_file.path(), expected);
+        match result {
+            Ok(()) => assert!(matches),
+            Err(CommandParseError::ConfigHashMismatch(_, actual)) => {
+                assert!(!matches);
+                assert_eq!(
+                    actual,
+                    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+                );
             }
-            err => panic!(
-                "Expected `CommandParseError::CommandNotFoundError`, got {:?}",
+            Err(err) => panic!(
+                "Expected `CommandParseError::ConfigHashMismatch`, got {:?}",
                 err
             ),
         }
     }
 
     #[test]
-    fn test_get_command_not_table() {
+    fn test_verify_config_hash_include() {
         let temp_file = NamedTempFile::new().unwrap();
+        let config = r#"xrun = { include = ["other.toml"] }"#;
         temp_file
             .reopen()
             .unwrap()
-            .write_all(TOML_COMMAND_DATA)
+            .write_all(config.as_bytes())
             .unwrap();
-        let result = get_command(
-            temp_file.path(),
-            &"foo qux".split_whitespace().collect::<Vec<&str>>(),
-        );
-        assert!(result.is_err());
+        let result = verify_config_hash(temp_file.path(), &sha256_hex(config.as_bytes()));
+        assert!(matches!(
+            result,
+            Err(CommandParseError::UnverifiedInclude(_))
+        ));
+    }
+
+    #[test_case(r#"c = { command = "tool" }"#, None ; "disabled")]
+    #[test_case(r#"c = { command = "tool", help_passthrough = true }"#, Some("tool '--help'") ; "default args")]
+    #[test_case(r#"c = { command = "tool", help_passthrough = true, help_args = ["help"] }"#, Some("tool 'help'") ; "custom args")]
+    fn test_get_help_passthrough(config: &str, expected: Option<&str>) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let result = get_help_passthrough(temp_file.path(), &["c"]).unwrap();
+        assert_eq!(result.map(|r| r.command).as_deref(), expected);
+    }
+
+    #[test_case(r#"c = { command = "x" }"#, false ; "default")]
+    #[test_case(r#"c = { command = "x", strict = true }"#, true ; "command")]
+    #[test_case(r#"
+            xrun = { strict = true }
+            c = { command = "x" }
+        "#, true ; "global")]
+    #[test_case(r#"
+            xrun = { strict = true }
+            c = { command = "x", strict = false }
+        "#, false ; "command overrides global")]
+    fn test_get_command_strict(config: &str, expected: bool) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(config.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]).unwrap();
+        assert_eq!(result.strict, expected);
+    }
+
+    #[test_case("/bin/bash", true, "set -euo pipefail\necho hi" ; "bash")]
+    #[test_case("zsh", true, "set -euo pipefail\necho hi" ; "zsh")]
+    #[test_case("sh", true, "echo hi" ; "sh unsupported")]
+    #[test_case("/bin/bash", false, "echo hi" ; "not strict")]
+    fn test_shell_command(shell: &str, strict: bool, expected: &str) {
+        let resolved = ResolvedCommand {
+            command: "echo hi".to_string(),
+            argv: None,
+            shell: None,
+            shell_args: vec![],
+            strict,
+            stdin: StdinMode::Inherit,
+            trace: false,
+            cwd: None,
+            env: BTreeMap::new(),
+            shells: BTreeMap::new(),
+            missing_shell: MissingShell::Fallback,
+            cache: None,
+            ignore_errors: false,
+            timeout: None,
+        };
+        assert_eq!(resolved.shell_command(shell), expected);
+    }
+
+    #[test_case("fish", "set fish_trace 1\necho hi" ; "fish")]
+    #[test_case("bash", "echo hi" ; "traced by flag")]
+    fn test_shell_command_trace(shell: &str, expected: &str) {
+        let resolved = ResolvedCommand {
+            command: "echo hi".to_string(),
+            argv: None,
+            shell: None,
+            shell_args: vec![],
+            strict: false,
+            stdin: StdinMode::Inherit,
+            trace: true,
+            cwd: None,
+            env: BTreeMap::new(),
+            shells: BTreeMap::new(),
+            missing_shell: MissingShell::Fallback,
+            cache: None,
+            ignore_errors: false,
+            timeout: None,
+        };
+        assert_eq!(resolved.shell_command(shell), expected);
+    }
+
+    #[test]
+    fn test_get_command_stdin_unknown() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(r#"c = { command = "x", stdin = "file" }"#.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["c"]);
         match result.unwrap_err() {
-            CommandParseError::CommandContentInvalid(InvalidContentReason::NotTomlTable(
+            CommandParseError::CommandContentInvalid(InvalidContentReason::UnknownValue(
                 key,
                 value,
             )) => {
-                assert_eq!(key, "qux");
-                if let Value::String(_) = value {
-                } else {
-                    panic!("Expected a `Value::Table` but got {}", value)
-                }
+                assert_eq!(key, "stdin");
+                assert_eq!(value, "file");
             }
             err => panic!(
-                "Expected wrapped `InvalidContentReason::NotTomlTable`, but got {:?}",
+                "Expected wrapped `InvalidContentReason::UnknownValue`, but got {:?}",
                 err
             ),
         }
     }
 
-    #[test]
-    fn test_get_command_not_string() {
+    #[test_case(r#"foo = { desc = "d" }"#, "foo desc", "desc" ; "reserved string")]
+    #[test_case(r#"foo.env = { A = "a" }"#, "foo env", "env" ; "reserved table")]
+    #[test_case(r#"xrun = { strict = true }"#, "xrun", "xrun" ; "settings")]
+    fn test_get_command_reserved_name(config: &str, command: &str, name: &str) {
         let temp_file = NamedTempFile::new().unwrap();
         temp_file
             .reopen()
             .unwrap()
-            .write_all(TOML_COMMAND_DATA)
+            .write_all(config.as_bytes())
             .unwrap();
         let result = get_command(
             temp_file.path(),
-            &"foo".split_whitespace().collect::<Vec<&str>>(),
+            &command.split_whitespace().collect::<Vec<&str>>(),
         );
-        assert!(result.is_err());
         match result.unwrap_err() {
-            CommandParseError::CommandContentInvalid(InvalidContentReason::NotTomlString(
-                key,
-                value,
-            )) => {
-                assert_eq!(key, "command");
-                if let Value::String(_) = value {
-                    panic!("Expected a `Value::String` but got {}", value)
-                }
-            }
+            CommandParseError::ReservedName(s) => assert_eq!(s, name),
+            err => panic!("Expected `CommandParseError::ReservedName`, got {:?}", err),
+        }
+    }
+
+    #[test_case("d env", "echo e" ; "table")]
+    #[test_case("d desc", "echo desc" ; "string")]
+    #[test_case("d exit_codes x", "echo x" ; "group")]
+    fn test_get_command_reserved_name_subcommand(command: &str, expected: &str) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                br#"
+                    [d]
+                    env = { command = "echo e", env = { A = "a" } }
+                    desc = { command = "echo desc" }
+                    exit_codes.x = { command = "echo x" }
+                "#,
+            )
+            .unwrap();
+        let resolved = get_command(
+            temp_file.path(),
+            &command.split_whitespace().collect::<Vec<&str>>(),
+        )
+        .unwrap();
+        assert_eq!(resolved.command, expected);
+        let help = get_command_help(temp_file.path(), &["d"]).unwrap();
+        let names: Vec<Option<&str>> = help.pairs.iter().map(|pair| pair.0.as_deref()).collect();
+        assert_eq!(names, [None, Some("desc"), Some("env"), Some("exit_codes")]);
+        assert_eq!(help.pairs[0].1, None);
+    }
+
+    #[test]
+    fn test_get_command_settings_name_nested() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(r#"foo.xrun = { command = "x" }"#.as_bytes())
+            .unwrap();
+        let result = get_command(temp_file.path(), &["foo", "xrun"]);
+        assert_eq!(result.unwrap().command, "x");
+    }
+
+    #[test_case("sub", "/base/sub" ; "relative")]
+    #[test_case("/abs", "/abs" ; "absolute")]
+    #[test_case("~", "{home}" ; "home")]
+    #[test_case("~/sub", "{home}/sub" ; "in home")]
+    #[test_case("~user", "/base/~user" ; "other user not expanded")]
+    fn test_resolve_dir(dir: &str, expected: &str) {
+        let home = env::var("HOME").unwrap_or_default();
+        let expected = expected.replace("{home}", &home);
+        assert_eq!(
+            resolve_dir(dir, Path::new("/base")),
+            PathBuf::from(expected)
+        );
+    }
+
+    #[test]
+    fn test_get_command_ancestors() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                r#"
+                    [a]
+                    desc = "a desc"
+                    b = { c = { command = "c" } }
+                "#
+                .as_bytes(),
+            )
+            .unwrap();
+        let result = get_command_ancestors(temp_file.path(), &["a", "b", "c"]).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                HelpPair(Some("a".to_string()), Some("a desc".to_string())),
+                HelpPair(Some("b".to_string()), None),
+            ]
+        );
+        assert_eq!(
+            get_command_ancestors(temp_file.path(), &["a"]).unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_get_command_help_titles() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                r#"
+                    [s]
+                    c1 = { command = "x", title = "Clean build artifacts" }
+                    c2 = { command = "y" }
+                "#
+                .as_bytes(),
+            )
+            .unwrap();
+        let result = get_command_help(temp_file.path(), &["s"]).unwrap();
+        assert_eq!(
+            result.titles,
+            BTreeMap::from([("c1".to_string(), "Clean build artifacts".to_string())])
+        );
+    }
+
+    const ALIAS_TOML_COMMAND_DATA: &[u8] = r#"
+            [g]
+            command = "git"
+            status = { command = "git status", aliases = ["st", "stat"] }
+            stash = { command = "git stash", aliases = ["sh"] }
+            log = { command = "git log" }
+        "#
+    .as_bytes();
+
+    #[test_case("g status", "git status" ; "name")]
+    #[test_case("g st", "git status" ; "alias")]
+    #[test_case("g stat -s", "git status '-s'" ; "alias with args")]
+    #[test_case("g sta", "git 'sta'" ; "not an alias")]
+    fn test_get_command_aliases(cmd_str: &str, expected: &str) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(ALIAS_TOML_COMMAND_DATA)
+            .unwrap();
+        let command: Vec<&str> = cmd_str.split_whitespace().collect();
+        let result = get_command(temp_file.path(), &command).unwrap();
+        assert_eq!(result.command, expected);
+    }
+
+    #[test]
+    fn test_get_command_duplicate_alias() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                r#"
+                    a = { command = "a", aliases = ["x"] }
+                    b = { command = "b", aliases = ["x"] }
+                "#
+                .as_bytes(),
+            )
+            .unwrap();
+        match get_command(temp_file.path(), &["x"]).unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::DuplicateAlias(
+                alias,
+                first,
+                second,
+            )) => assert_eq!(
+                (alias.as_str(), first.as_str(), second.as_str()),
+                ("x", "a", "b")
+            ),
             err => panic!(
-                "Expected wrapped `InvalidContentReason::NotTomlString`, but got {:?}",
+                "Expected `InvalidContentReason::DuplicateAlias`, got {:?}",
                 err
             ),
         }
     }
 
-    // TODO: Fix test cases being a bit haphazard and very ugly.
-    // Likely start providing separate configs for each instead of trying to hammer a single config
-    // into doing it all.
-    #[test_case("foo", vec![
-        (None, Some("foo desc".to_string())),
-        (Some("bar".to_string()),Some("bar desc".to_string())),
-        (Some("qux".to_string()), None)
-    ]; "parent and child")]
-    #[test_case("foo bar", vec![(None, Some("bar desc".to_string()))]; "parent only")]
-    #[test_case("", vec![(None, None),(Some("foo".to_string()),Some("foo desc".to_string())),(Some("baz".to_string()), None)]; "children only")]
-    #[test_case("baz", vec![(None, None)]; "empty")]
-    fn test_get_command_help(command: &str, expected: Vec<(Option<String>, Option<String>)>) {
+    #[test]
+    fn test_get_command_help_aliases() {
         let temp_file = NamedTempFile::new().unwrap();
         temp_file
             .reopen()
             .unwrap()
-            .write_all(TOML_COMMAND_DATA)
+            .write_all(ALIAS_TOML_COMMAND_DATA)
             .unwrap();
-        let result = get_command_help(
-            temp_file.path(),
-            &command.split_whitespace().collect::<Vec<&str>>(),
+        let result = get_command_help(temp_file.path(), &["g"]).unwrap();
+        assert_eq!(
+            result.aliases,
+            BTreeMap::from([
+                (
+                    "status".to_string(),
+                    vec!["st".to_string(), "stat".to_string()]
+                ),
+                ("stash".to_string(), vec!["sh".to_string()]),
+            ])
         );
+    }
 
-        assert!(result.is_ok());
-        let result = result.unwrap();
+    #[test]
+    fn test_get_command_include() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir(tmp_dir.path().join("sub")).unwrap();
+        let config = tmp_dir.path().join("command.toml");
+        fs::write(
+            &config,
+            r#"
+            xrun = { include = ["sub/git.toml"] }
+            [g]
+            desc = "main desc"
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            tmp_dir.path().join("sub/git.toml"),
+            r#"
+            xrun = { include = ["../docker.toml"] }
+            g = { desc = "git desc", s = { command = "git status" } }
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            tmp_dir.path().join("docker.toml"),
+            r#"d = { command = "docker ps" }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            get_command(&config, &["g", "s"]).unwrap().command,
+            "git status"
+        );
+        assert_eq!(get_command(&config, &["d"]).unwrap().command, "docker ps");
+        let help = get_command_help(&config, &["g"]).unwrap();
+        assert_eq!(help.pairs[0], HelpPair(None, Some("main desc".to_string())));
+    }
+
+    #[test_case("sub", "inc/sub" ; "relative")]
+    #[test_case("/abs", "/abs" ; "absolute")]
+    fn test_get_command_include_cwd(cwd: &str, expected: &str) {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let config = tmp_dir.path().join("command.toml");
+        fs::create_dir(tmp_dir.path().join("inc")).unwrap();
+        fs::write(&config, r#"xrun = { include = ["inc/other.toml"] }"#).unwrap();
+        fs::write(
+            tmp_dir.path().join("inc/other.toml"),
+            format!(r#"g = {{ c = {{ command = "x", cwd = "{}" }} }}"#, cwd),
+        )
+        .unwrap();
+        let result = get_command(&config, &["g", "c"]).unwrap();
+        assert_eq!(result.cwd, Some(tmp_dir.path().join(expected)));
+    }
+
+    #[test_case(r#"xrun = { include = ["dne.toml"] }"#, "dne.toml", false ; "missing")]
+    #[test_case(r#"xrun = { include = ["command.toml"] }"#, "command.toml", true ; "self include")]
+    #[test_case(r#"xrun = { include = ["other.toml"] }"#, "command.toml", true ; "circular include")]
+    fn test_get_command_include_invalid(config_data: &str, include: &str, circular: bool) {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let config = tmp_dir.path().join("command.toml");
+        fs::write(&config, config_data).unwrap();
+        fs::write(
+            tmp_dir.path().join("other.toml"),
+            r#"xrun = { include = ["./command.toml"] }"#,
+        )
+        .unwrap();
+        match get_command(&config, &["c"]).unwrap_err() {
+            CommandParseError::IncludeNotFound(path) if !circular => {
+                assert_eq!(path.file_name().unwrap(), include)
+            }
+            CommandParseError::CircularInclude(path) if circular => {
+                assert_eq!(path.file_name().unwrap(), include)
+            }
+            err => panic!("Expected an include error, got {:?}", err),
+        }
+    }
+
+    #[test_case(&[], &["a", "b", "b c", "b d e", "f"] ; "all")]
+    #[test_case(&["b"], &["b", "b c", "b d e"] ; "beneath command")]
+    fn test_get_command_list(command: &[&str], expected: &[&str]) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                r#"
+                f = { command = "f" }
+                xrun = { strict = true }
+                a = { command = "a", desc = "a desc", title = "A" }
+                [b]
+                command = "b"
+                c = { command = "c" }
+                d = { desc = "namespace", e = { command = "e" } }
+                "#
+                .as_bytes(),
+            )
+            .unwrap();
+        let result = get_command_list(temp_file.path(), command, false).unwrap();
+        let paths: Vec<String> = result.iter().map(|entry| entry.path.join(" ")).collect();
+        assert_eq!(paths, expected);
+        if command.is_empty() {
+            assert_eq!(
+                result[0],
+                CommandEntry {
+                    path: vec!["a".to_string()],
+                    desc: Some("a desc".to_string()),
+                    title: Some("A".to_string()),
+                    hidden: false,
+                    keys: vec![
+                        "command".to_string(),
+                        "desc".to_string(),
+                        "title".to_string()
+                    ],
+                }
+            );
+        }
+    }
+
+    #[test_case(&[] ; "all")]
+    #[test_case(&["b"] ; "beneath command")]
+    fn test_export_command_toml(command: &[&str]) {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let config = tmp_dir.path().join("command.toml");
+        fs::write(
+            &config,
+            r#"
+            xrun = { include = ["b.toml"], strict = true }
+            a = { command = "a", desc = "a desc" }
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            tmp_dir.path().join("b.toml"),
+            r#"b = { c = { command = "c", post_args = ["x"] } }"#,
+        )
+        .unwrap();
+        let exported = export_command_toml(&config, command).unwrap();
+        let expected = if command.is_empty() {
+            r#"
+            xrun = { strict = true }
+            a = { command = "a", desc = "a desc" }
+            b = { c = { command = "c", post_args = ["x"] } }
+            "#
+        } else {
+            r#"b = { c = { command = "c", post_args = ["x"] } }"#
+        };
+        assert_eq!(
+            toml_to_map(&exported).unwrap(),
+            toml_to_map(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_command_tree() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                r#"
+                xrun = { strict = true }
+                b = { desc = "group", c = { command = "c", title = "C" } }
+                a = { command = "a" }
+                "#
+                .as_bytes(),
+            )
+            .unwrap();
+        let leaf = |name: &str, title: Option<&str>| CommandNode {
+            name: name.to_string(),
+            desc: None,
+            title: title.map(|s| s.to_string()),
+            is_command: true,
+            hidden: false,
+            keys: ["command"]
+                .into_iter()
+                .chain(title.map(|_| "title"))
+                .map(|key| key.to_string())
+                .collect(),
+            children: vec![],
+        };
+        assert_eq!(
+            get_command_tree(temp_file.path(), &[], false).unwrap(),
+            vec![
+                leaf("a", None),
+                CommandNode {
+                    name: "b".to_string(),
+                    desc: Some("group".to_string()),
+                    title: None,
+                    is_command: false,
+                    hidden: false,
+                    keys: vec!["desc".to_string()],
+                    children: vec![leaf("c", Some("C"))],
+                },
+            ]
+        );
+        assert_eq!(
+            get_command_tree(temp_file.path(), &["b"], false).unwrap(),
+            vec![leaf("c", Some("C"))]
+        );
+    }
+
+    const HIDDEN_TOML_COMMAND_DATA: &str = r#"
+        a = { command = "a" }
+        b = { command = "b", hidden = true }
+        [g]
+        hidden = true
+        c = { command = "c" }
+    "#;
+
+    #[test_case(false, &[("a", false)] ; "visible")]
+    #[test_case(true, &[("a", false), ("b", true), ("g c", true)] ; "all")]
+    fn test_get_command_list_hidden(all: bool, expected: &[(&str, bool)]) {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), HIDDEN_TOML_COMMAND_DATA).unwrap();
+        let result = get_command_list(temp_file.path(), &[], all).unwrap();
+        let paths: Vec<(String, bool)> = result
+            .iter()
+            .map(|entry| (entry.path.join(" "), entry.hidden))
+            .collect();
+        let expected: Vec<(String, bool)> = expected
+            .iter()
+            .map(|(path, hidden)| (path.to_string(), *hidden))
+            .collect();
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn test_get_command_details() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let config = tmp_dir.path().join("command.toml");
+        fs::write(
+            &config,
+            r#"
+            xrun = { include = ["inc/b.toml"] }
+            a = { command = ["echo", "a b"], cwd = "x", env = { K = "v" }, timeout = 5 }
+            "#,
+        )
+        .unwrap();
+        fs::create_dir(tmp_dir.path().join("inc")).unwrap();
+        let included = tmp_dir.path().join("inc/b.toml");
+        fs::write(&included, r#"b = { steps = ["b1", "b2"], cwd = "y" }"#).unwrap();
+        let details = get_command_details(&config, &[], false).unwrap();
+        assert_eq!(
+            details,
+            vec![
+                CommandDetails {
+                    entry: CommandEntry {
+                        path: vec!["a".to_string()],
+                        desc: None,
+                        title: None,
+                        hidden: false,
+                        keys: ["command", "cwd", "env", "timeout"]
+                            .map(|key| key.to_string())
+                            .to_vec(),
+                    },
+                    steps: vec!["'echo' 'a b'".to_string()],
+                    cwd: Some(tmp_dir.path().join("x")),
+                    env: BTreeMap::from([("K".to_string(), "v".to_string())]),
+                    timeout: Some(Duration::from_secs(5)),
+                    source: config.to_owned(),
+                },
+                CommandDetails {
+                    entry: CommandEntry {
+                        path: vec!["b".to_string()],
+                        desc: None,
+                        title: None,
+                        hidden: false,
+                        keys: vec!["cwd".to_string(), "steps".to_string()],
+                    },
+                    steps: vec!["b1".to_string(), "b2".to_string()],
+                    cwd: Some(tmp_dir.path().join("inc/y")),
+                    env: BTreeMap::new(),
+                    timeout: None,
+                    source: included,
+                },
+            ]
+        );
+    }
+
+    #[test_case(false, &[("a", false)] ; "visible")]
+    #[test_case(true, &[("a", false), ("b", true), ("g", true)] ; "all")]
+    fn test_get_command_tree_hidden(all: bool, expected: &[(&str, bool)]) {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), HIDDEN_TOML_COMMAND_DATA).unwrap();
+        let nodes = get_command_tree(temp_file.path(), &[], all).unwrap();
+        let names: Vec<(&str, bool)> = nodes
+            .iter()
+            .map(|node| (node.name.as_str(), node.hidden))
+            .collect();
+        assert_eq!(names, expected);
+        if all {
+            assert!(!nodes[2].children[0].hidden);
+        }
+    }
+
+    #[test]
+    fn test_get_command_help_hidden() {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), HIDDEN_TOML_COMMAND_DATA).unwrap();
+        assert_eq!(
+            get_command_help(temp_file.path(), &[]).unwrap().pairs,
+            vec![HelpPair(None, None), HelpPair(Some("a".to_string()), None)]
+        );
+        let helps = get_command_help_all(temp_file.path(), &[]).unwrap();
+        let paths: Vec<Vec<String>> = helps.into_iter().map(|(path, _)| path).collect();
+        assert_eq!(paths, vec![vec![], vec!["a".to_string()]]);
+        assert_eq!(
+            get_command(temp_file.path(), &["g", "c"]).unwrap().command,
+            "c"
+        );
+    }
+
+    #[test_case("c3", "c2", 1 ; "substitution")]
+    #[test_case("stats", "status", 1 ; "insertion")]
+    #[test_case("", "abc", 3 ; "empty")]
+    #[test_case("same", "same", 0 ; "equal")]
+    fn test_edit_distance(a: &str, b: &str, expected: usize) {
+        assert_eq!(edit_distance(a, b), expected);
+        assert_eq!(edit_distance(b, a), expected);
+    }
 
-        assert_eq!(result.len(), expected.len());
+    #[test_case("c3", &["a", "c1", "c2"], Some("c1") ; "first of closest")]
+    #[test_case("stauts", &["stash", "status"], Some("status") ; "closest")]
+    #[test_case("dne", &["c1", "c2"], None ; "too far")]
+    fn test_closest_match(target: &str, candidates: &[&str], expected: Option<&str>) {
+        assert_eq!(closest_match(target, candidates.iter().copied()), expected);
+    }
+
+    #[test]
+    fn test_get_command_steps_stdin_from_args() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(r#"fmt = { command = "fmt", sub = { command = "sub" } }"#.as_bytes())
+            .unwrap();
+        let steps =
+            get_command_steps_stdin_from_args(temp_file.path(), &["fmt", "some", "text"]).unwrap();
+        assert_eq!(steps[0].command, "fmt");
+        assert_eq!(steps[0].stdin, StdinMode::Data("some text\n".to_string()));
+        let steps = get_command_steps_stdin_from_args(temp_file.path(), &["fmt", "sub"]).unwrap();
+        assert_eq!(steps[0].command, "sub");
+        assert_eq!(steps[0].stdin, StdinMode::Data("\n".to_string()));
+    }
+
+    #[test]
+    fn test_config_not_readable_error() {
+        let err = CommandParseError::ConfigNotReadable(PathBuf::from("/x/command.toml"));
+        assert_eq!(
+            err.to_string(),
+            "Config file exists but is not readable: /x/command.toml"
+        );
+        assert_eq!(err.exit_code(), 4);
+        assert_eq!(
+            CommandParseError::EmptyConfig(PathBuf::from("/x/command.toml")).exit_code(),
+            5
+        );
+        assert_eq!(
+            CommandParseError::CommandNotFoundError("c".to_string(), None).exit_code(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_get_flag_aliases() {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(
+                r#"
+                    [xrun.flags]
+                    n = "--no-stdin"
+                    "--tr" = "--trace-shell"
+                "#
+                .as_bytes(),
+            )
+            .unwrap();
+        let result = get_flag_aliases(temp_file.path()).unwrap();
+        assert_eq!(
+            result,
+            BTreeMap::from([
+                ("n".to_string(), "--no-stdin".to_string()),
+                ("tr".to_string(), "--trace-shell".to_string()),
+            ])
+        );
+    }
 
-        for (expected_key, expected_val) in expected {
-            assert!(result.contains(&HelpPair(expected_key.to_owned(), expected_val.to_owned())))
+    #[test_case("--config" ; "config")]
+    #[test_case("--config=other.toml" ; "config with value")]
+    #[test_case("-c" ; "short config")]
+    #[test_case("--no-local" ; "no local")]
+    fn test_get_flag_aliases_config_flag(expanded: &str) {
+        let temp_file = NamedTempFile::new().unwrap();
+        temp_file
+            .reopen()
+            .unwrap()
+            .write_all(format!(r#"xrun.flags.q = "{}""#, expanded).as_bytes())
+            .unwrap();
+        match get_flag_aliases(temp_file.path()).unwrap_err() {
+            CommandParseError::CommandContentInvalid(InvalidContentReason::ConfigFlagAlias(
+                alias,
+                flag,
+            )) => assert_eq!((alias.as_str(), flag.as_str()), ("q", expanded)),
+            err => panic!(
+                "Expected `InvalidContentReason::ConfigFlagAlias`, got {:?}",
+                err
+            ),
         }
     }
 }
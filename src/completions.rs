@@ -0,0 +1,362 @@
+//! Generates shell completion scripts for the commands of a config.
+
+use std::{io, path::PathBuf};
+
+use crate::{
+    cli::{BUILTIN_FLAGS, VALUE_FLAGS},
+    command_parser::{shell_quote, CommandNode},
+};
+
+/// Shells a completion script can be generated for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    /// Gets the shell named `name`, which may be given as a path such as `/bin/bash`.
+    ///
+    /// returns - The shell, or `None` if completion scripts can't be generated for it.
+    pub(crate) fn from_name(name: &str) -> Option<Shell> {
+        match name.rsplit('/').next().unwrap_or(name) {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            _ => None,
+        }
+    }
+}
+
+/// Generates a completion script, which completes the (sub)commands that can follow the command
+/// path typed so far, and the built-in long flags when the word being completed starts with `-`.
+/// The command tree is baked into the script, so it has to be generated again for changes to the
+/// config to be completed.
+///
+/// * `shell` - The shell to generate the script for.
+/// * `prog_name` - The name of the program to complete.
+/// * `nodes` - The top-level (sub)commands of the config.
+pub(crate) fn completion_script(shell: Shell, prog_name: &str, nodes: &[CommandNode]) -> String {
+    let function = format!(
+        "_{}",
+        prog_name.replace(|c: char| !c.is_alphanumeric(), "_")
+    );
+    match shell {
+        Shell::Bash => bash_completion(&function, prog_name, nodes),
+        Shell::Zsh => zsh_completion(&function, prog_name, nodes),
+        Shell::Fish => fish_completion(&function, prog_name, nodes),
+    }
+}
+
+/// Gets the file a completion script is installed to, relative to `$XDG_DATA_HOME`. bash-completion
+/// and fish load scripts from there on demand, while zsh only loads them if the directory is in
+/// `$fpath`.
+///
+/// * `shell` - The shell the script is for.
+/// * `prog_name` - The name of the program the script completes.
+fn install_file(shell: Shell, prog_name: &str) -> String {
+    match shell {
+        Shell::Bash => format!("bash-completion/completions/{}", prog_name),
+        Shell::Zsh => format!("zsh/site-functions/_{}", prog_name),
+        Shell::Fish => format!("fish/vendor_completions.d/{}.fish", prog_name),
+    }
+}
+
+/// Gets the path a completion script is installed to, creating its directory if needed. See
+/// `install_file`.
+///
+/// * `shell` - The shell the script is for.
+/// * `prog_name` - The name of the program the script completes.
+pub(crate) fn install_path(shell: Shell, prog_name: &str) -> io::Result<PathBuf> {
+    xdg::BaseDirectories::new().place_data_file(install_file(shell, prog_name))
+}
+
+/// Generates a bash completion script. See `completion_script`.
+fn bash_completion(function: &str, prog_name: &str, nodes: &[CommandNode]) -> String {
+    let mut cases = String::new();
+    case_branches(nodes, "", &bash_words, &mut cases);
+    format!(
+        r#"{function}() {{
+    local cur=${{COMP_WORDS[COMP_CWORD]}}
+    local cmd_path= words= i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case ${{COMP_WORDS[i]}} in
+            {value_flags}) ((i++)) ;;
+            -*) ;;
+            *) cmd_path+=" ${{COMP_WORDS[i]}}" ;;
+        esac
+    done
+    if [[ $cur == -* ]]; then
+        words={flags}
+    else
+        case $cmd_path in
+{cases}        esac
+    fi
+    COMPREPLY=($(compgen -W "$words" -- "$cur"))
+}}
+complete -F {function} {prog_name}
+"#,
+        value_flags = VALUE_FLAGS.join(" | "),
+        flags = shell_quote(&long_flags().join(" ")),
+    )
+}
+
+/// Generates a zsh completion script, which also shows the description of each (sub)command. It
+/// can either be put in a directory of `$fpath` or be evaluated. See `completion_script`.
+fn zsh_completion(function: &str, prog_name: &str, nodes: &[CommandNode]) -> String {
+    let mut cases = String::new();
+    case_branches(nodes, "", &zsh_words, &mut cases);
+    let flags: Vec<String> = long_flags().iter().map(|flag| shell_quote(flag)).collect();
+    format!(
+        r#"#compdef {prog_name}
+
+{function}() {{
+    local cmd_path= i
+    local -a subcommands
+    for ((i = 2; i < CURRENT; i++)); do
+        case ${{words[i]}} in
+            {value_flags}) ((i++)) ;;
+            -*) ;;
+            *) cmd_path+=" ${{words[i]}}" ;;
+        esac
+    done
+    if [[ ${{words[CURRENT]}} == -* ]]; then
+        compadd -- {flags}
+    else
+        case $cmd_path in
+{cases}        esac
+        _describe 'command' subcommands
+    fi
+}}
+
+if [[ $funcstack[1] == {function} ]]; then
+    {function} "$@"
+else
+    compdef {function} {prog_name}
+fi
+"#,
+        value_flags = VALUE_FLAGS.join(" | "),
+        flags = flags.join(" "),
+    )
+}
+
+/// Generates a fish completion script, which also shows the description of each (sub)command.
+/// See `completion_script`.
+fn fish_completion(function: &str, prog_name: &str, nodes: &[CommandNode]) -> String {
+    let mut script = format!(
+        r#"function {function}_at
+    set -l tokens (commandline -opc)
+    set -e tokens[1]
+    set -l cmd_path
+    set -l skip
+    for token in $tokens
+        if set -q skip[1]
+            set -e skip
+        else if contains -- $token {value_flags}
+            set skip 1
+        else if not string match -q -- '-*' $token
+            set -a cmd_path $token
+        end
+    end
+    test "$cmd_path" = "$argv"
+end
+
+complete -c {prog_name} -f
+"#,
+        value_flags = VALUE_FLAGS.join(" "),
+    );
+    for flag in long_flags() {
+        script += &format!("complete -c {} -l {}", prog_name, &flag[2..]);
+        if VALUE_FLAGS.contains(&flag) {
+            script += " -r";
+        }
+        script += "\n";
+    }
+    fish_lines(nodes, &format!("{}_at", function), prog_name, &mut script);
+    script
+}
+
+/// Appends a `complete` line for each of `nodes` and every (sub)command beneath them, offering
+/// it along with the first line of its description once the command path before it is typed.
+///
+/// * `nodes` - The (sub)commands to offer.
+/// * `condition` - The fish command checking the command path before `nodes` was typed.
+/// * `prog_name` - The name of the program to complete.
+/// * `script` - The script to append to.
+fn fish_lines(nodes: &[CommandNode], condition: &str, prog_name: &str, script: &mut String) {
+    for node in nodes {
+        *script += &format!(
+            "complete -c {} -n {} -a {}",
+            prog_name,
+            fish_quote(condition),
+            fish_quote(&node.name)
+        );
+        if let Some(desc) = node.desc.as_deref().and_then(|desc| desc.lines().next()) {
+            *script += &format!(" -d {}", fish_quote(desc));
+        }
+        *script += "\n";
+        let condition = format!("{} {}", condition, fish_quote(&node.name));
+        fish_lines(&node.children, &condition, prog_name, script);
+    }
+}
+
+/// Quotes `s` so fish treats it as a single literal word.
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+/// Gets the built-in long flags.
+fn long_flags() -> Vec<&'static str> {
+    BUILTIN_FLAGS
+        .iter()
+        .copied()
+        .filter(|flag| flag.starts_with("--"))
+        .collect()
+}
+
+/// Gets the bash assignment offering the names of `nodes`.
+fn bash_words(nodes: &[CommandNode]) -> String {
+    let names: Vec<&str> = nodes.iter().map(|node| node.name.as_str()).collect();
+    format!("words={}", shell_quote(&names.join(" ")))
+}
+
+/// Gets the zsh assignment offering the names of `nodes` along with the first line of their
+/// descriptions, in the `name:description` form of `_describe`.
+fn zsh_words(nodes: &[CommandNode]) -> String {
+    let specs: Vec<String> = nodes
+        .iter()
+        .map(|node| {
+            let name = node.name.replace(':', r"\:");
+            match node.desc.as_deref().and_then(|desc| desc.lines().next()) {
+                Some(desc) => shell_quote(&format!("{}:{}", name, desc)),
+                None => shell_quote(&name),
+            }
+        })
+        .collect();
+    format!("subcommands=({})", specs.join(" "))
+}
+
+/// Appends a `case` branch for the command path `path` and for every command path beneath it,
+/// each offering the (sub)commands that can follow it.
+///
+/// * `nodes` - The (sub)commands beneath `path`.
+/// * `path` - The command path typed so far, each token preceded by a space.
+/// * `words` - Gets the assignment offering the (sub)commands of a branch.
+/// * `cases` - The branches to append to.
+fn case_branches(
+    nodes: &[CommandNode],
+    path: &str,
+    words: &dyn Fn(&[CommandNode]) -> String,
+    cases: &mut String,
+) {
+    if nodes.is_empty() {
+        return;
+    }
+    *cases += &format!("            {}) {} ;;\n", shell_quote(path), words(nodes));
+    for node in nodes {
+        case_branches(
+            &node.children,
+            &format!("{} {}", path, node.name),
+            words,
+            cases,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn node(name: &str, desc: Option<&str>, children: Vec<CommandNode>) -> CommandNode {
+        CommandNode {
+            name: name.to_string(),
+            desc: desc.map(|desc| desc.to_string()),
+            title: None,
+            is_command: children.is_empty(),
+            hidden: false,
+            keys: vec![],
+            children,
+        }
+    }
+
+    #[test]
+    fn test_case_branches() {
+        let nodes = [
+            node("a", None, vec![]),
+            node(
+                "b",
+                None,
+                vec![
+                    node("c", None, vec![]),
+                    node("d", None, vec![node("e", None, vec![])]),
+                ],
+            ),
+        ];
+        let mut cases = String::new();
+        case_branches(&nodes, "", &bash_words, &mut cases);
+        assert_eq!(
+            cases,
+            "            '') words='a b' ;;\n            \
+             ' b') words='c d' ;;\n            \
+             ' b d') words='e' ;;\n"
+        );
+    }
+
+    #[test_case("bash", Some(Shell::Bash) ; "bash")]
+    #[test_case("/usr/bin/zsh", Some(Shell::Zsh) ; "path")]
+    #[test_case("fish", Some(Shell::Fish) ; "fish")]
+    #[test_case("tcsh", None ; "unsupported")]
+    fn test_shell_from_name(name: &str, expected: Option<Shell>) {
+        assert_eq!(Shell::from_name(name), expected);
+    }
+
+    #[test_case(Shell::Bash, "bash-completion/completions/xrun" ; "bash")]
+    #[test_case(Shell::Zsh, "zsh/site-functions/_xrun" ; "zsh")]
+    #[test_case(Shell::Fish, "fish/vendor_completions.d/xrun.fish" ; "fish")]
+    fn test_install_file(shell: Shell, expected: &str) {
+        assert_eq!(install_file(shell, "xrun"), expected);
+    }
+
+    #[test]
+    fn test_zsh_words() {
+        let nodes = [
+            node("a", Some("a desc\nmore"), vec![]),
+            node("b:c", None, vec![]),
+            node("it's", Some("x: y"), vec![]),
+        ];
+        assert_eq!(
+            zsh_words(&nodes),
+            r"subcommands=('a:a desc' 'b\:c' 'it'\''s:x: y')"
+        );
+    }
+
+    #[test_case(Shell::Bash, "_xrun() {\n", "complete -F _xrun xrun\n", "            '') words='a' ;;\n", "--run-from | --format | --config | -c" ; "bash")]
+    #[test_case(Shell::Zsh, "#compdef xrun\n", "    compdef _xrun xrun\nfi\n", "            '') subcommands=('a') ;;\n", "--run-from | --format | --config | -c" ; "zsh")]
+    #[test_case(Shell::Fish, "function _xrun_at\n", "complete -c xrun -n '_xrun_at' -a 'a'\n", "complete -c xrun -l format -r\n", "--run-from --format --config -c" ; "fish")]
+    fn test_completion_script(shell: Shell, start: &str, end: &str, case: &str, value_flags: &str) {
+        let script = completion_script(shell, "xrun", &[node("a", None, vec![])]);
+        assert!(script.starts_with(start));
+        assert!(script.ends_with(end));
+        assert!(script.contains(case));
+        assert!(script.contains(value_flags));
+    }
+
+    #[test]
+    fn test_fish_lines() {
+        let nodes = [
+            node("a", Some("a desc\nmore"), vec![]),
+            node("b", None, vec![node("it's", Some("x"), vec![])]),
+        ];
+        let mut script = String::new();
+        fish_lines(&nodes, "_xrun_at", "xrun", &mut script);
+        assert_eq!(
+            script,
+            "complete -c xrun -n '_xrun_at' -a 'a' -d 'a desc'\n\
+             complete -c xrun -n '_xrun_at' -a 'b'\n\
+             complete -c xrun -n '_xrun_at \\'b\\'' -a 'it\\'s' -d 'x'\n"
+        );
+    }
+}
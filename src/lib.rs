@@ -0,0 +1,28 @@
+//! Runs commands defined in a toml config. Besides the `xrun` binary, the config resolution is
+//! available as a library, e.g. to resolve the command `xrun deploy staging` would run:
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let resolved = xrun::get_command(Path::new("command.toml"), &["deploy", "staging"])?;
+//! println!("{}", resolved.command);
+//! # Ok::<(), xrun::CommandParseError>(())
+//! ```
+//!
+//! A command with 'steps' resolves to several commands executed in order, which
+//! `get_command_steps` returns, whereas `get_command` only returns the first.
+
+mod app;
+mod cache;
+mod cli;
+mod command_parser;
+mod completions;
+
+const PROG_NAME: &str = "xrun";
+
+#[doc(hidden)]
+pub use app::run;
+pub use command_parser::{
+    get_command, get_command_help, get_command_steps, CommandHelp, CommandParseError, HelpPair,
+    InvalidContentReason, MissingShell, ResolvedCommand, ShellConvention, StdinMode,
+};
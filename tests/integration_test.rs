@@ -1,10 +1,11 @@
 // TODO: Make tests that test output be more robust somehow.
 
 use assert_cmd::Command;
-use std::fs;
+use std::{fs, os::unix::fs::PermissionsExt};
 
 use rstest::{fixture, rstest};
 use tempfile::TempDir;
+use test_case::test_case;
 
 const BASIC_TOML_COMMAND_DATA: &[u8] = r#"
     [s]
@@ -26,7 +27,11 @@ fn create_test_setup(config: &[u8]) -> TestSetup {
     let _ = fs::write(tmp_dir.path().join("xrun/command.toml"), config);
     let mut cmd = Command::cargo_bin("xrun").unwrap();
     cmd.env("XDG_CONFIG_HOME", tmp_dir.path());
+    cmd.env("XDG_CACHE_HOME", tmp_dir.path().join("cache"));
     cmd.env("SHELL", "sh");
+    cmd.env_remove("XRUN_CONFIG");
+    // Keep project-local configs of wherever the tests run from out of the way.
+    cmd.current_dir(tmp_dir.path());
 
     TestSetup {
         _tmp_dir: tmp_dir,
@@ -86,6 +91,12 @@ fn test_exec_command_dne(basic_cmd: TestSetup) {
     test_cmd(basic_cmd, "s dne", "", stderr, 1);
 }
 
+#[rstest]
+fn test_exec_command_typo(basic_cmd: TestSetup) {
+    let stderr = "Error: Command 'cc2' not found. Did you mean 'c2'?\n";
+    test_cmd(basic_cmd, "s cc2", "", stderr, 1);
+}
+
 #[rstest]
 fn test_passthrough_exec_command_dne(basic_cmd: TestSetup) {
     let stderr = "Error: Command 'dne' not found\n";
@@ -123,6 +134,295 @@ fn test_exec_stdin() {
     let _ = assert.success().stdout(stdout).stderr("");
 }
 
+#[test_case("s c1" ; "path")]
+#[test_case("s a" ; "alias")]
+#[test_case("t c1" ; "group alias")]
+#[test_case("s" ; "default")]
+fn test_exec_command_path(args: &str) {
+    let toml_command_data = r#"
+        [s]
+        aliases = ["t"]
+        default = "a"
+        c1 = { command = "echo {{command_path}}", aliases = ["a"] }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, args, "s c1\n", "", 0);
+}
+
+#[test]
+fn test_exec_post_args() {
+    let toml_command_data = r#"c = { command = "echo a", post_args = ["--", "b  c"] }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "c", "a -- b  c\n", "", 0);
+}
+
+#[test]
+fn test_exec_positional_args() {
+    let toml_command_data = r#"deploy = { command = "echo {2} {1}" }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "deploy staging v2", "v2 staging\n", "", 0);
+}
+
+#[test]
+fn test_exec_all_args_quoted() {
+    let toml_command_data = r#"c = { command = "printf '[%s]' {@}" }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let assert = test_setup.cmd.args(["c", "a b", "it's", "$HOME"]).assert();
+    assert.success().stdout("[a b][it's][$HOME]").stderr("");
+}
+
+#[test]
+fn test_exec_missing_positional_arg() {
+    let toml_command_data = r#"deploy = { command = "echo {1} {2}" }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stderr = "Error: Command expects argument {2} but it was not given\n";
+    test_cmd(test_setup, "deploy staging", "", stderr, 1);
+}
+
+#[rstest]
+fn test_exec_args_appended(basic_cmd: TestSetup) {
+    test_cmd(basic_cmd, "s c1 extra", "c1 ran extra\n", "", 0);
+}
+
+#[test]
+fn test_exec_args_appended_quoted() {
+    let toml_command_data = r#"c = { command = "printf '[%s]'" }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let assert = test_setup.cmd.args(["c", "a \"b\"", "it's"]).assert();
+    assert.success().stdout("[a \"b\"][it's]").stderr("");
+}
+
+#[test_case("c -- --short -p", "[--short][-p]", "", 0 ; "separator")]
+#[test_case("c -- a -- b", "[a][--][b]", "", 0 ; "second separator")]
+#[test_case("c a -- b -- c", "[a][b][--][c]", "", 0 ; "separator after arg")]
+#[test_case("c --short -p", "[--short][-p]", "", 0 ; "rest after flag")]
+#[test_case("c x --short", "[x][--short]", "", 0 ; "after arg")]
+#[test_case("s --short", "", "Unknown flag: --short\n", 1 ; "group")]
+#[test_case("--short c", "", "Unknown flag: --short\n", 1 ; "before command")]
+fn test_exec_args_appended_flags(args: &str, stdout: &str, stderr: &str, ret: i32) {
+    let toml_command_data = r#"
+        c = { command = "printf '[%s]'" }
+        s.c1 = { command = "echo c1" }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, args, stdout, stderr, ret);
+}
+
+#[rstest]
+fn test_exec_args_flags_before_separator(basic_cmd: TestSetup) {
+    test_cmd(
+        basic_cmd,
+        "s c1 --passthrough -- -x",
+        "echo c1 ran '-x'\n",
+        "",
+        125,
+    );
+}
+
+#[rstest]
+fn test_exec_args_namespace(basic_cmd: TestSetup) {
+    let stderr = "Error: Command 'c3 extra' not found. Did you mean 'c1'?\n";
+    test_cmd(basic_cmd, "s c3 extra", "", stderr, 1);
+}
+
+#[test]
+fn test_exec_snippet() {
+    let toml_command_data = r#"
+        b = { command = "@greet" }
+        [xrun.snippets]
+        greet = "echo hi"
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "b there", "hi there\n", "", 0);
+}
+
+#[test]
+fn test_exec_snippet_unknown() {
+    let toml_command_data = r#"c = { command = "@dne" }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stderr = "Error: Command content invalid - Snippet 'dne' is not defined\n";
+    test_cmd(test_setup, "c", "", stderr, 1);
+}
+
+#[test]
+fn test_exec_strict_unsupported_shell() {
+    let toml_command_data = r#"c = { command = "false; echo ran", strict = true }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stderr = "Warning: strict mode is not supported by sh, ignoring\n";
+    test_cmd(test_setup, "c", "ran\n", stderr, 0);
+}
+
+#[test_case(r#"c = { command = "echo hi" }"#, "--trace-shell c" ; "flag")]
+#[test_case(r#"c = { command = "echo hi", trace = true }"#, "c" ; "key")]
+fn test_exec_trace_shell(toml_command_data: &str, args: &str) {
+    let test_setup = create_test_setup(toml_command_data.as_bytes());
+    test_cmd(test_setup, args, "hi\n", "+ echo hi\n", 0);
+}
+
+#[test]
+fn test_exec_run_from() {
+    let toml_command_data = r#"c = { command = "pwd" }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let tmp_dir = test_setup._tmp_dir.path().canonicalize().unwrap();
+    test_setup.cmd.current_dir(&tmp_dir);
+    let stdout = format!("{}\n", tmp_dir.join("xrun").display());
+    test_cmd(test_setup, "--run-from=xrun c", &stdout, "", 0);
+}
+
+#[test]
+fn test_exec_run_from_separate_value() {
+    let toml_command_data = r#"c = { command = "pwd" }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let tmp_dir = test_setup._tmp_dir.path().canonicalize().unwrap();
+    test_setup.cmd.current_dir(&tmp_dir);
+    let stdout = format!("{}\n", tmp_dir.join("xrun").display());
+    test_cmd(test_setup, "c --run-from xrun", &stdout, "", 0);
+}
+
+#[rstest]
+fn test_flag_missing_value(basic_cmd: TestSetup) {
+    let stderr = "Missing value for flag: --run-from\n";
+    test_cmd(basic_cmd, "s c1 --run-from", "", stderr, 1);
+}
+
+#[rstest]
+fn test_separator_literal_help_flag(basic_cmd: TestSetup) {
+    test_cmd(basic_cmd, "s c1 -- -h", "c1 ran -h\n", "", 0);
+}
+
+#[test]
+fn test_exec_run_from_dne() {
+    let toml_command_data = r#"c = { command = "pwd" }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let tmp_dir = test_setup._tmp_dir.path().canonicalize().unwrap();
+    test_setup.cmd.current_dir(&tmp_dir);
+    let stderr = format!(
+        "Error: Working directory '{}' does not exist\n",
+        tmp_dir.join("dne").display()
+    );
+    test_cmd(test_setup, "--run-from=dne c", "", &stderr, 1);
+}
+
+#[test]
+fn test_exec_multiline() {
+    let toml_command_data = r#"
+        [c]
+        command = """
+        echo a
+        echo b
+        """
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "c", "a\nb\n", "", 0);
+}
+
+#[test]
+fn test_exec_multiline_line_continuation() {
+    let toml_command_data = r#"
+        [c]
+        command = '''
+        echo a \
+          b
+        '''
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "c", "a b\n", "", 0);
+}
+
+#[rstest]
+fn test_print_shell(basic_cmd: TestSetup) {
+    test_cmd(basic_cmd, "--print-shell s c1", "sh\n", "", 0);
+}
+
+#[rstest]
+fn test_print_shell_default(mut basic_cmd: TestSetup) {
+    basic_cmd.cmd.env_remove("SHELL");
+    test_cmd(basic_cmd, "--print-shell s c1", "sh\n", "", 0);
+}
+
+#[rstest]
+fn test_exec_missing_shell_fallback(mut basic_cmd: TestSetup) {
+    basic_cmd.cmd.env("SHELL", "/nonexistent/bash");
+    let stderr = "Warning: shell '/nonexistent/bash' does not exist, falling back to sh\n";
+    test_cmd(basic_cmd, "s c1", "c1 ran\n", stderr, 0);
+}
+
+#[test]
+fn test_exec_missing_shell_error() {
+    let toml_command_data = r#"
+        xrun = { missing_shell = "error" }
+        c = { command = "echo ran" }
+    "#
+    .as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    test_setup.cmd.env("SHELL", "/nonexistent/bash");
+    let stderr = "Error: Shell '/nonexistent/bash' does not exist\n";
+    test_cmd(test_setup, "c", "", stderr, 1);
+}
+
+#[test]
+fn test_print_shell_interactive_with_shell_args() {
+    let toml_command_data = r#"
+        xrun = { shell_args = ["-o", "pipefail"] }
+        c = { command = "true" }
+    "#
+    .as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    test_setup.cmd.env("SHELL", "/bin/bash");
+    let stdout = "/bin/bash -i -o pipefail\n";
+    test_cmd(test_setup, "--print-shell c", stdout, "", 0);
+}
+
+#[test]
+fn test_print_shell_custom_convention() {
+    let toml_command_data = r#"
+        c = { command = "true" }
+        [xrun.shells.sh]
+        interactive_flag = "-e"
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "--print-shell c", "sh -e\n", "", 0);
+}
+
+#[test]
+fn test_exec_custom_command_flag() {
+    let toml_command_data = r#"
+        c = { command = "echo hi" }
+        [xrun.shells.sh]
+        command_flag = "-ec"
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "c", "hi\n", "", 0);
+}
+
+#[test]
+fn test_exec_stdin_null() {
+    let toml_command_data =
+        r#"c = { command = "read line; echo \"$? [$line]\"", stdin = "null" }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let assert = test_setup.cmd.arg("c").write_stdin("foo\n").assert();
+    let _ = assert.success().stdout("1 []\n").stderr("");
+}
+
+#[test]
+fn test_exec_no_stdin_flag() {
+    let toml_command_data = r#"c = { command = "read line; echo \"$? [$line]\"" }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let assert = test_setup
+        .cmd
+        .args(["--no-stdin", "c"])
+        .write_stdin("foo\n")
+        .assert();
+    let _ = assert.success().stdout("1 []\n").stderr("");
+}
+
 /// Test help for a subcommand with a description and child commands.
 #[rstest]
 fn test_help_subcommand(basic_cmd: TestSetup) {
@@ -137,6 +437,65 @@ fn test_help_subcommand(basic_cmd: TestSetup) {
     test_cmd(basic_cmd, "s --help", stdout, "", 0);
 }
 
+#[rstest]
+fn test_help_color(basic_cmd: TestSetup) {
+    let stdout = concat!(
+        "usage: xrun s [command]\n",
+        "s desc\n",
+        "\n",
+        "commands:\n",
+        "    \x1b[1mc1\x1b[0m: \x1b[2mc1 desc\x1b[0m\n",
+        "    \x1b[1mc2\x1b[0m\n",
+    );
+    test_cmd(basic_cmd, "--color=always s --help", stdout, "", 0);
+}
+
+#[rstest]
+fn test_help_color_never(mut basic_cmd: TestSetup) {
+    basic_cmd.cmd.env("NO_COLOR", "1");
+    let stdout = concat!(
+        "usage: xrun s [command]\n",
+        "s desc\n",
+        "\n",
+        "commands:\n",
+        "    c1: c1 desc\n",
+        "    c2\n",
+    );
+    test_cmd(basic_cmd, "--color never s --help", stdout, "", 0);
+}
+
+#[rstest]
+fn test_help_color_unknown(basic_cmd: TestSetup) {
+    test_cmd(
+        basic_cmd,
+        "--color=rainbow s --help",
+        "",
+        "Unknown color: rainbow\n",
+        1,
+    );
+}
+
+const HIDDEN_TOML_COMMAND_DATA: &[u8] = r#"
+    a = { command = "echo a ran", desc = "a desc" }
+    b = { command = "echo b ran", hidden = true }
+    [g]
+    hidden = true
+    c = { command = "echo c ran" }
+"#
+.as_bytes();
+
+#[test_case("b", "b ran\n" ; "hidden")]
+#[test_case("g c", "c ran\n" ; "in hidden group")]
+#[test_case("--help", "usage: xrun [command]\ncommands:\n    a: a desc\n" ; "help")]
+#[test_case("--list", "a: a desc\n" ; "list")]
+#[test_case("--list --all", "a: a desc\nb [hidden]\ng c [hidden]\n" ; "list all")]
+#[test_case("--tree", "└── a: a desc\n" ; "tree")]
+#[test_case("--tree --all", "├── a: a desc\n├── b [hidden]\n└── g/ [hidden]\n    └── c\n" ; "tree all")]
+fn test_hidden(args: &str, stdout: &str) {
+    let test_setup = create_test_setup(HIDDEN_TOML_COMMAND_DATA);
+    test_cmd(test_setup, args, stdout, "", 0);
+}
+
 /// Test help for a subcommand with a description and child commands.
 #[test]
 fn test_help_subcommand_no_desc() {
@@ -162,12 +521,149 @@ fn test_help_command(basic_cmd: TestSetup) {
     test_cmd(basic_cmd, "s c1 --help", stdout, "", 0);
 }
 
+#[rstest]
+fn test_help_command_context(basic_cmd: TestSetup) {
+    let stdout = concat!("usage: xrun s c1\n", "part of: s (s desc)\n", "c1 desc\n");
+    test_cmd(basic_cmd, "s c1 --help --context", stdout, "", 0);
+}
+
 #[rstest]
 fn test_help_command_no_desc(basic_cmd: TestSetup) {
     let stdout = "usage: xrun s c2\n";
     test_cmd(basic_cmd, "s c2 --help", stdout, "", 0);
 }
 
+#[test]
+fn test_help_all() {
+    let toml_command_data: &[u8] = r#"
+        [s]
+        desc = "s desc"
+        c1 = { command = "echo c1 ran", desc = "c1 desc" }
+        [s.n]
+        c2 = { command = "echo c2 ran" }
+    "#
+    .as_bytes();
+    let stdout = concat!(
+        "usage: xrun s [command]\n",
+        "s desc\n",
+        "\n",
+        "commands:\n",
+        "    c1: c1 desc\n",
+        "    n\n",
+        "\n",
+        "    usage: xrun s c1\n",
+        "    c1 desc\n",
+        "\n",
+        "    usage: xrun s n [command]\n",
+        "    commands:\n",
+        "        c2\n",
+        "\n",
+        "        usage: xrun s n c2\n",
+    );
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "s --help --all", stdout, "", 0);
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "s --help-all", stdout, "", 0);
+}
+
+#[test]
+fn test_help_custom_usage() {
+    let toml_command_data = r#"
+        c = { command = "grep \"$@\"", desc = "greps", usage = "xrun c <pattern> [file]" }
+    "#
+    .as_bytes();
+    let stdout = concat!("usage: xrun c <pattern> [file]\n", "greps\n");
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "c --help", stdout, "", 0);
+}
+
+#[test]
+fn test_help_passthrough() {
+    let toml_command_data = r#"
+        c = { command = "echo tool", desc = "wraps tool", help_passthrough = true }
+        d = { command = "echo tool", help_passthrough = true, help_args = ["help", "all"] }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "c --help", "tool --help\n", "", 0);
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "d --help", "tool help all\n", "", 0);
+}
+
+#[test]
+fn test_help_titles() {
+    let toml_command_data: &[u8] = r#"
+        [s]
+        c1 = { command = "echo c1 ran", desc = "c1 desc", title = "Clean build artifacts" }
+        c2 = { command = "echo c2 ran", title = "Compile" }
+        c3 = { command = "echo c3 ran" }
+    "#
+    .as_bytes();
+    let stdout = concat!(
+        "usage: xrun s [command]\n",
+        "commands:\n",
+        "    c1 (Clean build artifacts): c1 desc\n",
+        "    c2 (Compile)\n",
+        "    c3\n",
+    );
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "s --help", stdout, "", 0);
+}
+
+#[test]
+fn test_aliases() {
+    let toml_command_data: &[u8] = r#"
+        [g]
+        status = { command = "echo status ran", desc = "status desc", aliases = ["st"] }
+        log = { command = "echo log ran" }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "g st", "status ran\n", "", 0);
+    let stdout = concat!(
+        "usage: xrun g [command]\n",
+        "commands:\n",
+        "    log\n",
+        "    status (aliases: st): status desc\n",
+    );
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "g --help", stdout, "", 0);
+}
+
+#[test]
+fn test_help_exit_codes() {
+    let toml_command_data = r#"
+        [c]
+        command = "grep foo"
+        desc = "finds foo"
+        exit_codes = { "0" = "found", "1" = "no matches", "2" = "error" }
+    "#
+    .as_bytes();
+    let stdout = concat!(
+        "usage: xrun c\n",
+        "finds foo\n",
+        "\n",
+        "exit codes:\n",
+        "    0: found\n",
+        "    1: no matches\n",
+        "    2: error\n",
+    );
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "c --help", stdout, "", 0);
+}
+
+#[test_case("s desc", "", "Error: 'desc' is a reserved key and cannot be used as a command name, rename the command\n", 1 ; "reserved")]
+#[test_case("s env", "env ran\n", "", 0 ; "subcommand")]
+fn test_exec_reserved_name(args: &str, stdout: &str, stderr: &str, ret: i32) {
+    let toml_command_data = r#"[s]
+        desc = "s desc"
+        env = { command = "echo env ran" }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, args, stdout, stderr, ret);
+}
+
 #[rstest]
 fn test_help_subcommand_dne(basic_cmd: TestSetup) {
     let stderr = "Error: Command 'dne dne' not found\n";
@@ -179,3 +675,1182 @@ fn test_help_command_dne(basic_cmd: TestSetup) {
     let stderr = "Error: Command 'dne' not found\n";
     test_cmd(basic_cmd, "s dne --help", "", stderr, 1);
 }
+
+#[rstest]
+fn test_dump_resolved(mut basic_cmd: TestSetup) {
+    let cwd = basic_cmd._tmp_dir.path().canonicalize().unwrap();
+    basic_cmd.cmd.current_dir(&cwd);
+    let stdout = format!("#!/usr/bin/env sh\ncd '{}'\necho c1 ran\n", cwd.display());
+    test_cmd(basic_cmd, "--dump-resolved s c1", &stdout, "", 0);
+}
+
+#[rstest]
+fn test_dump_resolved_command_dne(basic_cmd: TestSetup) {
+    let stderr = "Error: Command 'dne' not found\n";
+    test_cmd(basic_cmd, "--dump-resolved s dne", "", stderr, 1);
+}
+
+#[rstest]
+fn test_version(basic_cmd: TestSetup) {
+    let stdout = format!("xrun {}\n", env!("CARGO_PKG_VERSION"));
+    test_cmd(basic_cmd, "--version", &stdout, "", 0);
+}
+
+#[rstest]
+fn test_version_json(basic_cmd: TestSetup) {
+    let stdout = format!(
+        "{{\"name\":\"xrun\",\"version\":\"{}\"}}\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    test_cmd(basic_cmd, "--version --format=json", &stdout, "", 0);
+}
+
+#[rstest]
+fn test_unknown_format(basic_cmd: TestSetup) {
+    test_cmd(
+        basic_cmd,
+        "--version --format=xml",
+        "",
+        "Unknown format: xml\n",
+        1,
+    );
+}
+
+#[rstest]
+#[case("--env-dump s c1", "***")]
+#[case("--env-dump --show-secrets s c1", "abc")]
+fn test_env_dump(mut basic_cmd: TestSetup, #[case] args: &str, #[case] token: &str) {
+    let config_home = basic_cmd._tmp_dir.path().to_owned();
+    basic_cmd
+        .cmd
+        .env_clear()
+        .env("XDG_CONFIG_HOME", &config_home)
+        .env("MY_TOKEN", "abc")
+        .env("FOO", "bar");
+    let stdout = format!(
+        "FOO=bar\nMY_TOKEN={}\nXDG_CONFIG_HOME={}\n",
+        token,
+        config_home.display()
+    );
+    test_cmd(basic_cmd, args, &stdout, "", 0);
+}
+
+#[test]
+fn test_config_sha256_match() {
+    let toml_command_data = r#"c = { command = "echo ran" }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    test_setup.cmd.env(
+        "XRUN_CONFIG_SHA256",
+        "b75e07b6e72e96292a8b8d9ae4427b6a4b375542b4d968803619123e41be2c66",
+    );
+    test_cmd(test_setup, "c", "ran\n", "", 0);
+}
+
+#[rstest]
+fn test_config_sha256_mismatch(mut basic_cmd: TestSetup) {
+    let expected = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    basic_cmd.cmd.env("XRUN_CONFIG_SHA256", expected);
+    let assert = basic_cmd.cmd.args(["s", "c1"]).assert().code(3).stdout("");
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.starts_with("Error: Config SHA-256 is "));
+    assert!(stderr.ends_with(&format!("but expected {}\n", expected)));
+}
+
+#[test]
+fn test_config_sha256_include() {
+    let toml_command_data = r#"xrun = { include = ["other.toml"] }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    fs::write(
+        test_setup._tmp_dir.path().join("xrun/other.toml"),
+        r#"c = { command = "echo ran" }"#,
+    )
+    .unwrap();
+    test_setup.cmd.env(
+        "XRUN_CONFIG_SHA256",
+        "2cf6488a572e6799238d9a4fb42ede14922707bf455b6e7b7d58bfd2ef11fe53",
+    );
+    let assert = test_setup.cmd.arg("c").assert().code(3).stdout("");
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.starts_with("Error: Config file includes other files"));
+}
+
+#[rstest]
+fn test_describe(basic_cmd: TestSetup) {
+    test_cmd(basic_cmd, "--describe s c1", "c1 desc\n", "", 0);
+}
+
+#[rstest]
+fn test_describe_no_desc(basic_cmd: TestSetup) {
+    test_cmd(basic_cmd, "--describe s c2", "", "", 1);
+}
+
+#[test]
+fn test_describe_first_line() {
+    let toml_command_data = r#"c = { command = "true", desc = "first\nsecond" }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, "--describe c", "first\n", "", 0);
+}
+
+#[rstest]
+fn test_config_not_readable(basic_cmd: TestSetup) {
+    let config = basic_cmd._tmp_dir.path().join("xrun/command.toml");
+    fs::set_permissions(&config, fs::Permissions::from_mode(0o000)).unwrap();
+    if fs::read(&config).is_ok() {
+        // Permissions aren't enforced for this user (e.g. root) so there is nothing to test.
+        return;
+    }
+    let stderr = format!(
+        "Error: Config file exists but is not readable: {}\n",
+        config.display()
+    );
+    test_cmd(basic_cmd, "s c1", "", &stderr, 4);
+}
+
+const FLAG_ALIAS_TOML_COMMAND_DATA: &[u8] = r#"
+    c = { command = "read line; echo \"$? [$line]\"", desc = "c desc" }
+
+    [xrun.flags]
+    q = "--no-stdin"
+    h = "--no-stdin"
+    z = "--not-a-flag"
+"#
+.as_bytes();
+
+#[test]
+fn test_flag_alias() {
+    let mut test_setup = create_test_setup(FLAG_ALIAS_TOML_COMMAND_DATA);
+    let assert = test_setup
+        .cmd
+        .args(["-q", "c"])
+        .write_stdin("foo\n")
+        .assert();
+    let _ = assert.success().stdout("1 []\n").stderr("");
+}
+
+#[test]
+fn test_flag_alias_builtin_not_overridden() {
+    let test_setup = create_test_setup(FLAG_ALIAS_TOML_COMMAND_DATA);
+    test_cmd(test_setup, "-h c", "usage: xrun c\nc desc\n", "", 0);
+}
+
+#[test]
+fn test_flag_alias_to_unknown_flag() {
+    let test_setup = create_test_setup(FLAG_ALIAS_TOML_COMMAND_DATA);
+    test_cmd(test_setup, "-z c", "", "Unknown flag: -z\n", 1);
+}
+
+#[test_case("-f toml" ; "separate value")]
+#[test_case("-f=toml" ; "joined value")]
+fn test_flag_alias_value(flag: &str) {
+    let toml_command_data = b"c = { command = \"echo hi\" }\nxrun.flags.f = \"--format\"";
+    let test_setup = create_test_setup(toml_command_data);
+    let stdout = "[c]\ncommand = \"echo hi\"\n\n[xrun.flags]\nf = \"--format\"\n";
+    test_cmd(test_setup, &format!("{} --list", flag), stdout, "", 0);
+}
+
+#[test_case("--config=other.toml" ; "config")]
+#[test_case("-c" ; "short config")]
+#[test_case("--no-local" ; "no local")]
+fn test_flag_alias_to_config_flag(expanded: &str) {
+    let toml_command_data = format!(
+        "c = {{ command = \"echo hi\" }}\nxrun.flags.q = \"{}\"",
+        expanded
+    );
+    let test_setup = create_test_setup(toml_command_data.as_bytes());
+    let stderr = format!(
+        "Error: Command content invalid - Flag alias 'q' expands to '{}', which chooses the config and \
+         must be given on the command line\n",
+        expanded
+    );
+    test_cmd(test_setup, "-q c", "", &stderr, 1);
+}
+
+#[rstest]
+#[case("--config")]
+#[case("-c")]
+fn test_config_flag(mut basic_cmd: TestSetup, #[case] flag: &str) {
+    let config = basic_cmd._tmp_dir.path().join("other.toml");
+    fs::write(&config, r#"c = { command = "echo other ran" }"#).unwrap();
+    let assert = basic_cmd
+        .cmd
+        .args([flag, config.to_str().unwrap(), "c"])
+        .assert();
+    assert.success().stdout("other ran\n").stderr("");
+}
+
+#[rstest]
+fn test_config_flag_aliases(mut basic_cmd: TestSetup) {
+    let config = basic_cmd._tmp_dir.path().join("other.toml");
+    fs::write(
+        &config,
+        "c = { command = \"echo hi\" }\nxrun.flags.q = \"-p\"",
+    )
+    .unwrap();
+    let assert = basic_cmd
+        .cmd
+        .args(["-q", "c", "--config", config.to_str().unwrap()])
+        .assert();
+    assert.code(125).stdout("echo hi\n").stderr("");
+}
+
+#[rstest]
+fn test_config_flag_dne(mut basic_cmd: TestSetup) {
+    let config = basic_cmd._tmp_dir.path().join("dne.toml");
+    let stderr = format!("Error: Config file does not exist: {}\n", config.display());
+    let assert = basic_cmd
+        .cmd
+        .args(["--config", config.to_str().unwrap(), "s", "c1"])
+        .assert();
+    assert.code(1).stdout("").stderr(stderr);
+}
+
+#[rstest]
+fn test_config_env(mut basic_cmd: TestSetup) {
+    let config = basic_cmd._tmp_dir.path().join("other.toml");
+    fs::write(&config, r#"c = { command = "echo other ran" }"#).unwrap();
+    basic_cmd.cmd.env("XRUN_CONFIG", &config);
+    test_cmd(basic_cmd, "c", "other ran\n", "", 0);
+}
+
+#[rstest]
+fn test_config_flag_over_env(mut basic_cmd: TestSetup) {
+    let config = basic_cmd._tmp_dir.path().join("other.toml");
+    fs::write(&config, r#"c = { command = "echo other ran" }"#).unwrap();
+    basic_cmd
+        .cmd
+        .env("XRUN_CONFIG", basic_cmd._tmp_dir.path().join("dne.toml"));
+    let assert = basic_cmd
+        .cmd
+        .args(["-c", config.to_str().unwrap(), "c"])
+        .assert();
+    assert.success().stdout("other ran\n").stderr("");
+}
+
+#[test]
+fn test_config_none_found() {
+    let mut cmd = Command::cargo_bin("xrun").unwrap();
+    let tmp_dir = TempDir::new().unwrap();
+    cmd.env("XDG_CONFIG_HOME", tmp_dir.path())
+        .env("XDG_CONFIG_DIRS", tmp_dir.path())
+        .env_remove("XRUN_CONFIG")
+        .current_dir(tmp_dir.path());
+    let stderr = "Error: No config found in --config, $XRUN_CONFIG, the current directory or its \
+                  parents, or the config directory (in that order of precedence)\nRun `xrun --init` \
+                  to create a sample config\n";
+    cmd.arg("c").assert().code(1).stdout("").stderr(stderr);
+}
+
+#[rstest]
+fn test_config_local(mut basic_cmd: TestSetup) {
+    let project = basic_cmd._tmp_dir.path().join("project");
+    fs::create_dir_all(project.join("sub")).unwrap();
+    fs::write(
+        project.join(".xrun.toml"),
+        r#"c = { command = "echo local ran" }"#,
+    )
+    .unwrap();
+    basic_cmd.cmd.current_dir(project.join("sub"));
+    test_cmd(basic_cmd, "c", "local ran\n", "", 0);
+}
+
+#[rstest]
+fn test_config_no_local(mut basic_cmd: TestSetup) {
+    let project = basic_cmd._tmp_dir.path().join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(
+        project.join(".xrun.toml"),
+        r#"c = { command = "echo local ran" }"#,
+    )
+    .unwrap();
+    basic_cmd.cmd.current_dir(&project);
+    test_cmd(basic_cmd, "--no-local s c1", "c1 ran\n", "", 0);
+}
+
+#[test]
+fn test_dry_run() {
+    let toml_command_data = r#"cp = { command = "cp {1} {@}" }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let assert = test_setup
+        .cmd
+        .args([
+            "--dry-run",
+            "--run-from=/",
+            "cp",
+            "--",
+            "-a",
+            "b c",
+            "it's $HOME",
+        ])
+        .assert();
+    let stdout = "shell: sh\ncwd: /\ncommand: cp '-a' '-a' 'b c' 'it'\\''s $HOME'\n";
+    assert.success().stdout(stdout).stderr("");
+}
+
+#[test_case("-n c", "env: A=b c\nenv: API_TOKEN=***\ncommand: echo a\n" ; "env")]
+#[test_case("-n --show-secrets c", "env: A=b c\nenv: API_TOKEN=x\ncommand: echo a\n" ; "show secrets")]
+#[test_case("-n s", "step 1: echo a\nstep 2: echo b\n" ; "steps")]
+#[test_case("-n a", "command: 'echo' 'a'\n" ; "no shell")]
+fn test_dry_run_summary(args: &str, stdout: &str) {
+    let toml_command_data = r#"
+        c = { command = "echo a", cwd = "/", env = { A = "b c", API_TOKEN = "x" } }
+        s = { steps = ["echo a", "echo b"], cwd = "/" }
+        a = { command = ["echo", "a"], shell = false, cwd = "/" }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let shell = if args.ends_with(" a") { "none" } else { "sh" };
+    let stdout = format!("shell: {}\ncwd: /\n{}", shell, stdout);
+    test_cmd(test_setup, args, &stdout, "", 0);
+}
+
+#[test]
+fn test_dry_run_missing_arg() {
+    let toml_command_data = r#"cp = { command = "cp {1} {2}" }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stderr = "Error: Command expects argument {2} but it was not given\n";
+    test_cmd(test_setup, "--dry-run cp a", "", stderr, 1);
+}
+
+#[rstest]
+#[case("xrun ''", "s")]
+#[case("xrun s c", "c1 c2")]
+#[case("xrun -c x.toml s ''", "c1 c2")]
+#[case("xrun s c1 ''", "")]
+#[case("xrun --pass", "--passthrough")]
+fn test_completions_bash(mut basic_cmd: TestSetup, #[case] words: &str, #[case] expected: &str) {
+    let output = basic_cmd
+        .cmd
+        .args(["--completions", "bash"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let script = String::from_utf8(output.stdout).unwrap();
+    let test = format!(
+        "{}\nCOMP_WORDS=({}); COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1)); _xrun; echo \"${{COMPREPLY[*]}}\"",
+        script, words
+    );
+    let assert = Command::new("bash").args(["-c", &test]).assert();
+    assert
+        .success()
+        .stdout(format!("{}\n", expected))
+        .stderr("");
+}
+
+#[rstest]
+fn test_completions_zsh(mut basic_cmd: TestSetup) {
+    let output = basic_cmd
+        .cmd
+        .args(["--completions", "zsh"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.starts_with("#compdef xrun\n"));
+    assert!(script.contains("            '') subcommands=('s:s desc') ;;\n"));
+    assert!(script.contains("            ' s') subcommands=('c1:c1 desc' 'c2') ;;\n"));
+}
+
+#[rstest]
+#[case(
+    "/bin/bash",
+    ".local/share/bash-completion/completions/xrun",
+    "complete -F _xrun xrun"
+)]
+#[case("zsh", ".local/share/zsh/site-functions/_xrun", "#compdef xrun")]
+#[case(
+    "fish",
+    ".local/share/fish/vendor_completions.d/xrun.fish",
+    "complete -c xrun -f"
+)]
+fn test_init_completions(
+    mut basic_cmd: TestSetup,
+    #[case] shell: &str,
+    #[case] file: &str,
+    #[case] script: &str,
+) {
+    let home = basic_cmd._tmp_dir.path().to_owned();
+    let installed = home.join(file);
+    basic_cmd.cmd.env("HOME", &home).env_remove("XDG_DATA_HOME");
+    basic_cmd.cmd.env("SHELL", shell).arg("--init-completions");
+    let stdout = format!("Wrote completion script to {}\n", installed.display());
+    let assert = basic_cmd.cmd.assert().success().stderr("");
+    assert!(String::from_utf8_lossy(&assert.get_output().stdout).starts_with(&stdout));
+    assert!(fs::read_to_string(&installed).unwrap().contains(script));
+
+    let stderr = format!(
+        "Error: Completion script already exists: {}\nUse --force to overwrite it\n",
+        installed.display()
+    );
+    basic_cmd.cmd.assert().code(1).stdout("").stderr(stderr);
+
+    fs::write(&installed, "old").unwrap();
+    basic_cmd.cmd.arg("--force").assert().success().stderr("");
+    assert!(fs::read_to_string(&installed).unwrap().contains(script));
+}
+
+#[rstest]
+fn test_completions_fish(mut basic_cmd: TestSetup) {
+    let output = basic_cmd
+        .cmd
+        .args(["--completions", "fish"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.starts_with("function _xrun_at\n"));
+    assert!(script.contains("complete -c xrun -n '_xrun_at' -a 's' -d 's desc'\n"));
+    assert!(script.contains("complete -c xrun -n '_xrun_at \\'s\\'' -a 'c1' -d 'c1 desc'\n"));
+    assert!(script.contains("complete -c xrun -n '_xrun_at \\'s\\'' -a 'c2'\n"));
+}
+
+#[rstest]
+fn test_init_completions_unsupported_shell(mut basic_cmd: TestSetup) {
+    basic_cmd.cmd.env("SHELL", "/usr/bin/tcsh");
+    let stderr = "Error: Unsupported shell for --init-completions: '/usr/bin/tcsh', use \
+                  --completions with bash, zsh or fish instead\n";
+    test_cmd(basic_cmd, "--init-completions", "", stderr, 1);
+}
+
+#[rstest]
+fn test_completions_unsupported_shell(basic_cmd: TestSetup) {
+    let stderr = "Unsupported shell for --completions: tcsh\n";
+    test_cmd(basic_cmd, "--completions tcsh", "", stderr, 1);
+}
+
+#[rstest]
+#[case(r#"s = { c1 = { command = "echo a" } }"#, "", 0)]
+#[case(
+    r#"s = { c1 = { command = "echo b", cwd = "/" } }"#,
+    "- command: echo a\n+ command: echo b\n+ cwd: /\n",
+    1
+)]
+#[case(
+    r#"s = { c2 = { command = "echo a" } }"#,
+    "- command: echo a\n- shell: $SHELL\n+ error: Command 'c1' not found. Did you mean 'c2'?\n",
+    1
+)]
+fn test_compare_config(
+    mut basic_cmd: TestSetup,
+    #[case] new_config: &str,
+    #[case] stdout: &str,
+    #[case] ret: i32,
+) {
+    let dir = basic_cmd._tmp_dir.path();
+    fs::write(
+        dir.join("old.toml"),
+        r#"s = { c1 = { command = "echo a" } }"#,
+    )
+    .unwrap();
+    fs::write(dir.join("new.toml"), new_config).unwrap();
+    let assert = basic_cmd
+        .cmd
+        .args(["--compare-config", "old.toml", "new.toml", "s", "c1"])
+        .assert();
+    assert.code(ret).stdout(stdout.to_owned()).stderr("");
+}
+
+#[rstest]
+fn test_compare_config_missing_command(basic_cmd: TestSetup) {
+    let stderr = "Error: --compare-config expects two config files followed by a command\n";
+    test_cmd(
+        basic_cmd,
+        "--compare-config old.toml new.toml",
+        "",
+        stderr,
+        1,
+    );
+}
+
+#[rstest]
+fn test_where(mut basic_cmd: TestSetup) {
+    let config = basic_cmd._tmp_dir.path().join("xrun/command.toml");
+    let assert = basic_cmd.cmd.arg("--where").assert();
+    assert
+        .success()
+        .stdout(format!("{}\n", config.display()))
+        .stderr("");
+}
+
+#[rstest]
+fn test_where_config_flag(mut basic_cmd: TestSetup) {
+    let config = basic_cmd._tmp_dir.path().join("other.toml");
+    fs::write(&config, "not = [valid toml").unwrap();
+    let assert = basic_cmd.cmd.args(["--where", "-c", "other.toml"]).assert();
+    assert
+        .success()
+        .stdout(format!("{}\n", config.display()))
+        .stderr("");
+}
+
+#[test]
+fn test_init() {
+    let tmp_dir = TempDir::new().unwrap();
+    let config = tmp_dir.path().join("xrun/command.toml");
+    let mut cmd = Command::cargo_bin("xrun").unwrap();
+    cmd.env("XDG_CONFIG_HOME", tmp_dir.path())
+        .env_remove("XRUN_CONFIG")
+        .current_dir(tmp_dir.path());
+    let stdout = format!("Wrote sample config to {}\n", config.display());
+    cmd.arg("--init")
+        .assert()
+        .success()
+        .stdout(stdout.to_owned());
+
+    let stderr = format!(
+        "Error: Config already exists: {}\nUse --force to overwrite it\n",
+        config.display()
+    );
+    cmd.assert().code(1).stdout("").stderr(stderr);
+    fs::write(&config, "").unwrap();
+    cmd.arg("--force").assert().success().stdout(stdout);
+
+    for (args, stdout) in [(["--check"], ""), (["hello"], "hello\n")] {
+        let mut cmd = Command::cargo_bin("xrun").unwrap();
+        cmd.env("XDG_CONFIG_HOME", tmp_dir.path())
+            .env("SHELL", "sh")
+            .env_remove("XRUN_CONFIG")
+            .current_dir(tmp_dir.path());
+        cmd.args(args).assert().success().stdout(stdout).stderr("");
+    }
+}
+
+#[rstest]
+fn test_init_config_flag(mut basic_cmd: TestSetup) {
+    let config = basic_cmd._tmp_dir.path().join("new/dir/x.toml");
+    let assert = basic_cmd
+        .cmd
+        .args(["--init", "-c", config.to_str().unwrap()])
+        .assert();
+    let stdout = format!("Wrote sample config to {}\n", config.display());
+    assert.success().stdout(stdout).stderr("");
+    assert!(config.is_file());
+}
+
+#[rstest]
+fn test_check_valid(basic_cmd: TestSetup) {
+    test_cmd(basic_cmd, "--check", "", "", 0);
+}
+
+#[test]
+fn test_check() {
+    let toml_command_data = r#"
+        a = { command = 1, desc = 2 }
+        [s]
+        c = { desc = "c desc" }
+        d = { command = "echo d", stdin = "none" }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stdout = "a: Expected key 'command' to be String but got Integer\n\
+                  a: Expected key 'desc' to be String but got Integer\n\
+                  s c: Expected key 'command' but it is not present\n\
+                  s d: Key 'stdin' has unknown value 'none'\n";
+    test_cmd(test_setup, "--check", stdout, "", 1);
+}
+
+#[rstest]
+fn test_trace_resolve(basic_cmd: TestSetup) {
+    let stderr = "table=<root> keys=s token=s decision=key\n\
+                  table=s keys=c1,c2 token=c1 decision=key\n";
+    test_cmd(basic_cmd, "--trace-resolve s c1", "", stderr, 0);
+}
+
+#[rstest]
+fn test_trace_resolve_not_found(basic_cmd: TestSetup) {
+    let stderr = "table=<root> keys=s token=s decision=key\n\
+                  table=s keys=c1,c2 token=c3 decision=not_found\n\
+                  Error: Command 'c3' not found. Did you mean 'c1'?\n";
+    test_cmd(basic_cmd, "--trace-resolve s c3", "", stderr, 1);
+}
+
+#[test_case("--list", "a (A): a desc\nb\nb c\n" ; "all")]
+#[test_case("--list b", "b\nb c\n" ; "beneath command")]
+fn test_list(args: &str, stdout: &str) {
+    let toml_command_data = r#"
+        a = { command = "a", desc = "a desc", title = "A" }
+        b = { command = "b", c = { command = "c" }, d = { desc = "namespace" } }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, args, stdout, "", 0);
+}
+
+#[test_case("--list --match B", "b\nb c\n" ; "match path")]
+#[test_case("--list --match DESC", "a (A): a desc\n" ; "match desc")]
+#[test_case("--list --invert-match b", "a (A): a desc\n" ; "invert match")]
+#[test_case("--list --match b --invert-match c", "b\n" ; "match and invert match")]
+#[test_case("--list --match-regex --match ^b$", "b\n" ; "regex")]
+#[test_case("--list --match ^b$", "" ; "not regex")]
+#[test_case("--tree --match-regex --match b.c", "└── b\n    └── c\n" ; "tree")]
+#[test_case("--tree --invert-match b", "└── a (A): a desc\n" ; "tree invert match")]
+fn test_list_match(args: &str, stdout: &str) {
+    let toml_command_data = r#"
+        a = { command = "a", desc = "a desc", title = "A" }
+        b = { command = "b", c = { command = "c" }, d = { desc = "namespace" } }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, args, stdout, "", 0);
+}
+
+#[test_case("--list --only-without desc", "b\nb c\n" ; "without desc")]
+#[test_case("--list --only-with title", "a (A): a desc\n" ; "with title")]
+#[test_case("--list --only-with timeout", "" ; "with none")]
+#[test_case("--tree --only-without desc", "└── b\n    └── c\n" ; "tree")]
+fn test_list_only_with(args: &str, stdout: &str) {
+    let toml_command_data = r#"
+        a = { command = "a", desc = "a desc", title = "A" }
+        b = { command = "b", c = { command = "c" }, d = { desc = "namespace" } }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, args, stdout, "", 0);
+}
+
+#[test]
+fn test_list_only_with_unknown_key() {
+    let test_setup = create_test_setup(r#"a = { command = "a" }"#.as_bytes());
+    test_cmd(
+        test_setup,
+        "--list --only-with a",
+        "",
+        "Unknown key for --only-with: a\n",
+        1,
+    );
+}
+
+#[test_case("--list --count", "runnable: 4\nnamespaces: 2\n" ; "text")]
+#[test_case("--list --count --format json", "{\"runnable\":4,\"namespaces\":2}\n" ; "json")]
+#[test_case("--list --count b", "runnable: 2\nnamespaces: 1\n" ; "beneath command")]
+#[test_case("--list --count --match g", "runnable: 2\nnamespaces: 1\n" ; "filtered")]
+#[test_case("--list --count --all", "runnable: 5\nnamespaces: 2\n" ; "all")]
+fn test_list_count(args: &str, stdout: &str) {
+    let toml_command_data = r#"
+        a = { command = "a", desc = "a desc", hidden = true }
+        b = { command = "b", c = { command = "c" }, d = { desc = "namespace" } }
+        g = { e = { command = "e" }, f = { command = "f" } }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, args, stdout, "", 0);
+}
+
+#[test]
+fn test_list_match_invalid_regex() {
+    let test_setup = create_test_setup(r#"a = { command = "a" }"#.as_bytes());
+    let stderr =
+        "Invalid regex for --match: regex parse error:\n    (\n    ^\nerror: unclosed group\n";
+    test_cmd(test_setup, "--list --match-regex --match (", "", stderr, 1);
+}
+
+#[test]
+fn test_list_toml() {
+    let toml_command_data = r#"
+        a = { command = "a" }
+        b = { c = { command = "c", desc = "c desc" } }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stdout = "[b.c]\ncommand = \"c\"\ndesc = \"c desc\"\n";
+    test_cmd(test_setup, "--list --format toml b", stdout, "", 0);
+}
+
+#[test]
+fn test_list_fzf() {
+    let toml_command_data = r#"
+        a = { command = "a", desc = "a\tdesc\nmore", title = "A" }
+        b = { command = "b", c = { command = "c" } }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stdout = "a\ta desc more\nb\t\nb c\t\n";
+    test_cmd(test_setup, "--list --format fzf", stdout, "", 0);
+}
+
+#[test_case("--list --json-lines", "" ; "json lines")]
+#[test_case("--list --format json-lines", "" ; "json lines format")]
+#[test_case("--list --json-lines --show-secrets", r#","env_values":{"A":"1","B":"2"}"# ; "show secrets")]
+fn test_list_json_lines(args: &str, env_values: &str) {
+    let toml_command_data = r#"
+        [g.c]
+        command = "echo c"
+        desc = "c desc"
+        title = "C"
+        env = { A = "1", B = "2" }
+        timeout = 30
+        [h]
+        command = ["echo", "h"]
+        hidden = true
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let source = test_setup._tmp_dir.path().join("xrun/command.toml");
+    let stdout = format!(
+        "{{\"path\":\"g c\",\"group\":\"g\",\"title\":\"C\",\"desc\":\"c desc\",\
+         \"hidden\":false,\"steps\":[\"echo c\"],\"cwd\":null,\"env\":[\"A\",\"B\"],\
+         \"timeout\":30,\"source\":\"{}\"{}}}\n",
+        source.display(),
+        env_values
+    );
+    test_cmd(test_setup, args, &stdout, "", 0);
+}
+
+#[test]
+fn test_list_json() {
+    let toml_command_data = r#"
+        a = { command = "a" }
+        b = { command = "b" }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let source = test_setup._tmp_dir.path().join("xrun/command.toml");
+    let object = |path: &str| {
+        format!(
+            "{{\"path\":\"{0}\",\"group\":null,\"title\":null,\"desc\":null,\"hidden\":false,\
+             \"steps\":[\"{0}\"],\"cwd\":null,\"env\":[],\"timeout\":null,\"source\":\"{1}\"}}",
+            path,
+            source.display()
+        )
+    };
+    let stdout = format!("[{},{}]\n", object("a"), object("b"));
+    test_cmd(test_setup, "--list --format json", &stdout, "", 0);
+}
+
+#[test_case("--tree", "├── a (A): a desc\n└── b/\n    └── c\n" ; "all")]
+#[test_case("--tree=1", "├── a (A): a desc\n└── b/\n" ; "depth")]
+#[test_case("--tree b", "b\n└── c\n" ; "beneath command")]
+fn test_tree(args: &str, stdout: &str) {
+    let toml_command_data = r#"
+        b = { c = { command = "c" } }
+        a = { command = "a", desc = "a desc", title = "A" }
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, args, stdout, "", 0);
+}
+
+#[test]
+fn test_tree_invalid_depth() {
+    let test_setup = create_test_setup(r#"a = { command = "a" }"#.as_bytes());
+    test_cmd(
+        test_setup,
+        "--tree=0",
+        "",
+        "Invalid depth for --tree: 0\n",
+        1,
+    );
+}
+
+#[test]
+fn test_stdin_from_args() {
+    let toml_command_data = r#"upper = { command = "tr a-z A-Z" }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(
+        test_setup,
+        "--stdin-from-args upper some text",
+        "SOME TEXT\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_stdin_from_args_no_stdin() {
+    let toml_command_data = r#"upper = { command = "tr a-z A-Z" }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stderr = "Error: --stdin-from-args cannot be used with --no-stdin\n";
+    test_cmd(
+        test_setup,
+        "--stdin-from-args --no-stdin upper a",
+        "",
+        stderr,
+        1,
+    );
+}
+
+#[test]
+fn test_env() {
+    let toml_command_data =
+        r#"c = { command = "echo $GREETING $HOME", env = { GREETING = "hi" } }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    test_setup.cmd.env("HOME", "/home/me");
+    test_cmd(test_setup, "c", "hi /home/me\n", "", 0);
+}
+
+#[test]
+fn test_env_dump_env() {
+    let toml_command_data = r#"c = { command = "x", env = { FOO = "baz" } }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let config_home = test_setup._tmp_dir.path().to_owned();
+    test_setup
+        .cmd
+        .env_clear()
+        .env("XDG_CONFIG_HOME", &config_home)
+        .env("FOO", "bar");
+    let stdout = format!("FOO=baz\nXDG_CONFIG_HOME={}\n", config_home.display());
+    test_cmd(test_setup, "--env-dump c", &stdout, "", 0);
+}
+
+#[test]
+fn test_cwd() {
+    let toml_command_data = r#"c = { command = "pwd", cwd = "sub" }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let sub = test_setup._tmp_dir.path().join("xrun/sub");
+    fs::create_dir(&sub).unwrap();
+    let stdout = format!("{}\n", sub.canonicalize().unwrap().display());
+    test_cmd(test_setup, "c", &stdout, "", 0);
+}
+
+#[test]
+fn test_cwd_dne() {
+    let toml_command_data = r#"c = { command = "pwd", cwd = "dne" }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stderr = format!(
+        "Error: Working directory '{}' does not exist\n",
+        test_setup._tmp_dir.path().join("xrun/dne").display()
+    );
+    test_cmd(test_setup, "c", "", &stderr, 1);
+}
+
+#[test_case("c" ; "command")]
+#[test_case("--list" ; "list")]
+fn test_empty_config(args: &str) {
+    let test_setup = create_test_setup(b"xrun = { strict = true }\n");
+    let stderr = format!(
+        "Error: Config file has no commands: {}\nAdd one such as `hello = {{ command = \"echo hello\" }}`\n",
+        test_setup._tmp_dir.path().join("xrun/command.toml").display()
+    );
+    test_cmd(test_setup, args, "", &stderr, 5);
+}
+
+#[test]
+fn test_print_env_file() {
+    let toml_command_data =
+        r#"c = { command = "x", env = { RUST_LOG = "debug", GREETING = "hi there" } }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stdout = "GREETING=\"hi there\"\nRUST_LOG=debug\n";
+    test_cmd(test_setup, "--print-env-file c", stdout, "", 0);
+}
+
+#[test]
+fn test_command_shell() {
+    let toml_command_data = r#"c = { command = "echo $0", shell = "sh" }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    test_setup.cmd.env("SHELL", "/dne/fish");
+    test_cmd(test_setup, "c", "sh\n", "", 0);
+}
+
+#[test]
+fn test_command_shell_dne() {
+    let toml_command_data = r#"c = { command = "echo hi", shell = "/dne/bash" }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(
+        test_setup,
+        "c",
+        "",
+        "Error: Shell '/dne/bash' does not exist\n",
+        1,
+    );
+}
+
+#[test_case(r#"c = { command = ["echo", "a;", "echo", "$HOME"] }"#, "a; echo $HOME\n", "", 0 ; "array")]
+#[test_case(r#"c = { command = ["echo", "a;", "echo", "$HOME"], shell = false }"#, "a; echo $HOME\n", "", 0 ; "array no shell")]
+#[test_case(r#"c = { command = "echo a; echo $HOME", shell = false }"#, "", "Error: Command content invalid - Key 'command' is a string but 'shell' is false, use an array of the program and its arguments instead\n", 1 ; "string no shell")]
+fn test_no_shell(toml_command_data: &str, stdout: &str, stderr: &str, ret: i32) {
+    let test_setup = create_test_setup(toml_command_data.as_bytes());
+    test_cmd(test_setup, "c", stdout, stderr, ret);
+}
+
+#[test]
+fn test_no_shell_args() {
+    let toml_command_data = r#"c = { command = ["printf", "%s|", "{@}"] }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let assert = test_setup.cmd.args(["c", "a;b", "$(c)"]).assert();
+    assert.success().stdout("a;b|$(c)|").stderr("");
+}
+
+#[test_case(r#"c = { command = ["echo", "${XRUN_TEST_DIR}/bin", "${HOME}"], shell = false }"#, "/opt/bin /home\n", "", 0 ; "defined")]
+#[test_case(r#"c = { command = ["echo", "${XRUN_TEST_DIR}"], shell = false, env = { XRUN_TEST_DIR = "/env" } }"#, "/env\n", "", 0 ; "command env")]
+#[test_case(r#"c = { command = ["echo", "${XRUN_TEST_UNSET:-d}"] }"#, "d\n", "", 0 ; "default")]
+#[test_case(r#"c = { command = ["echo", "${XRUN_TEST_UNSET}"] }"#, "", "Error: Command content invalid - Variable 'XRUN_TEST_UNSET' is not set, use '${XRUN_TEST_UNSET:-default}' to give it a default or '$${XRUN_TEST_UNSET}' for a literal '${XRUN_TEST_UNSET}'\n", 1 ; "undefined")]
+#[test_case(r#"c = { command = "echo ${XRUN_TEST_DIR}/bin", env = { XRUN_TEST_DIR = "/env" } }"#, "/env/bin\n", "", 0 ; "shell")]
+#[test_case(r#"c = { command = "echo ${XRUN_TEST_UNSET}." }"#, ".\n", "", 0 ; "shell unset")]
+#[test_case(r#"c = { command = 'for f in a b; do echo "${f}"; done' }"#, "a\nb\n", "", 0 ; "shell local")]
+#[test_case(r#"c = { command = ["echo", "$${XRUN_TEST_DIR}"] }"#, "${XRUN_TEST_DIR}\n", "", 0 ; "escaped")]
+#[test_case(r#"c = { steps = ["echo ${XRUN_TEST_DIR}", ["echo", "${HOME}"]] }"#, "/opt\n/home\n", "", 0 ; "steps")]
+fn test_env_interpolation(toml_command_data: &str, stdout: &str, stderr: &str, ret: i32) {
+    let mut test_setup = create_test_setup(toml_command_data.as_bytes());
+    test_setup
+        .cmd
+        .env("XRUN_TEST_DIR", "/opt")
+        .env("HOME", "/home")
+        .env_remove("XRUN_TEST_UNSET");
+    test_cmd(test_setup, "c", stdout, stderr, ret);
+}
+
+#[test_case(r#"c = { command = ["sleep", "5"], shell = false, timeout = 1 }"#, "", "Error: Command timed out after 1s\n", 124 ; "timed out")]
+#[test_case(r#"c = { steps = ["sleep 0.6", "sleep 0.6", "echo ran"], timeout = 1 }"#, "", "Error: Command timed out after 1s\n", 124 ; "sequence")]
+#[test_case(r#"c = { command = "echo ran", timeout = 5 }"#, "ran\n", "", 0 ; "in time")]
+#[test_case(r#"c = { command = "exit 3", timeout = 5 }"#, "", "", 3 ; "exit code")]
+#[test_case(r#"c = { command = "echo ran", timeout = 0 }"#, "ran\n", "", 0 ; "zero")]
+fn test_timeout(toml_command_data: &str, stdout: &str, stderr: &str, ret: i32) {
+    let test_setup = create_test_setup(toml_command_data.as_bytes());
+    test_cmd(test_setup, "c", stdout, stderr, ret);
+}
+
+const TIMED_OUT: &str = "Error: Command timed out after 1s\n";
+
+#[test_case("", "", "", TIMED_OUT, 124 ; "global")]
+#[test_case(", timeout = 5", "", "ran\n", "", 0 ; "command wins over global")]
+#[test_case(", timeout = 0", "", "ran\n", "", 0 ; "command disables global")]
+#[test_case(", timeout = 5", "--timeout 1", "", TIMED_OUT, 124 ; "cli wins over command")]
+#[test_case("", "--timeout 5s", "ran\n", "", 0 ; "cli wins over global")]
+#[test_case("", "--timeout 0", "ran\n", "", 0 ; "cli disables")]
+#[test_case("", "--timeout 5x", "", "Invalid timeout: 5x\n", 1 ; "invalid cli")]
+fn test_timeout_precedence(
+    command_timeout: &str,
+    flags: &str,
+    stdout: &str,
+    stderr: &str,
+    ret: i32,
+) {
+    let toml_command_data = format!(
+        r#"
+        xrun = {{ timeout = "1s" }}
+        c = {{ command = "sleep 1.5 && echo ran"{} }}
+        "#,
+        command_timeout
+    );
+    let test_setup = create_test_setup(toml_command_data.as_bytes());
+    test_cmd(test_setup, &format!("{} c", flags), stdout, stderr, ret);
+}
+
+#[test]
+fn test_timeout_interactive_shell() {
+    // bash is run interactively, so it ignores SIGTERM itself while the sleep it starts doesn't.
+    let toml_command_data =
+        r#"c = { command = "sleep 20; echo late", shell = "bash", timeout = 1 }"#;
+    let mut test_setup = create_test_setup(toml_command_data.as_bytes());
+    let home = test_setup._tmp_dir.path().to_owned();
+    let start = std::time::Instant::now();
+    test_setup.cmd.env("HOME", home).arg("c").assert().code(124);
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+}
+
+#[test]
+fn test_verbose() {
+    let mut test_setup = create_test_setup(
+        r#"c = { command = "echo {1}", cwd = "/", env = { A = "a", API_TOKEN = "t" } }"#.as_bytes(),
+    );
+    let config = test_setup._tmp_dir.path().join("xrun/command.toml");
+    let stderr = format!(
+        "xrun: config: {}\nxrun: shell: sh\nxrun: cwd: /\nxrun: env: A=a\nxrun: env: API_TOKEN=***\n\
+         xrun: command: echo 'x'\n",
+        config.display()
+    );
+    test_setup.cmd.args(["-v", "c", "x"]);
+    test_setup.cmd.assert().code(0).stdout("x\n").stderr(stderr);
+}
+
+#[test]
+fn test_verbose_resolution() {
+    let mut test_setup = basic_cmd();
+    let config = test_setup._tmp_dir.path().join("xrun/command.toml");
+    let stderr = format!(
+        "xrun: config: {}\nxrun: resolve: table=<root> keys=s token=s decision=key\n\
+         xrun: resolve: table=s keys=c1,c2 token=c1 decision=key\n",
+        config.display()
+    );
+    test_setup.cmd.args(["--verbose", "-v", "-p", "s", "c1"]);
+    test_setup
+        .cmd
+        .assert()
+        .code(125)
+        .stdout("echo c1 ran\n")
+        .stderr(stderr);
+}
+
+#[test_case(r#"c = { command = ["dne-program"] }"#, "Error: Program 'dne-program' does not exist\n" ; "program")]
+#[test_case(r#"c = { command = ["echo a", "echo b"] }"#, "Error: Program 'echo a' does not exist\nUse 'steps' for commands executed in sequence\n" ; "sequence")]
+fn test_no_shell_program_dne(toml_command_data: &str, stderr: &str) {
+    let test_setup = create_test_setup(toml_command_data.as_bytes());
+    test_cmd(test_setup, "c", "", stderr, 1);
+}
+
+#[test_case(r#"c = { steps = ["echo a", "exit 3", "echo b"] }"#, "a\n", 3 ; "stops on failure")]
+#[test_case(r#"c = { steps = ["echo a", "exit 3", "echo b"], ignore_errors = true }"#, "a\nb x\n", 0 ; "ignore errors")]
+#[test_case(r#"c = { steps = ["echo a", "echo {1}"] }"#, "a\nx\n", 0 ; "args")]
+#[test_case(r#"c = { steps = ["echo a", ["printf", "%s|", "a;b"]] }"#, "a\na;b|x|", 0 ; "argv step")]
+fn test_command_steps(toml_command_data: &str, stdout: &str, ret: i32) {
+    let test_setup = create_test_setup(toml_command_data.as_bytes());
+    test_cmd(test_setup, "c x", stdout, "", ret);
+}
+
+#[test_case(r#"c = { steps = ["echo a", "exit 3", "echo b"] }"#, "a\n", 3, "steps: 3 (1 succeeded, 1 failed, 1 not run)\nstep 1: exit 0\nstep 2: exit 3\nstep 3: not run" ; "stops on failure")]
+#[test_case(r#"c = { steps = ["echo a", "exit 3", "echo b"], ignore_errors = true }"#, "a\nb\n", 0, "steps: 3 (2 succeeded, 1 failed, 0 not run)\nstep 1: exit 0\nstep 2: exit 3\nstep 3: exit 0" ; "ignore errors")]
+#[test_case(r#"c = { command = "echo a" }"#, "a\n", 0, "steps: 1 (1 succeeded, 0 failed, 0 not run)\nstep 1: exit 0" ; "single command")]
+fn test_command_steps_stats(toml_command_data: &str, stdout: &str, ret: i32, summary: &str) {
+    let mut test_setup = create_test_setup(toml_command_data.as_bytes());
+    let assert = test_setup.cmd.args(["--stats", "c"]).assert();
+    let assert = assert.code(ret).stdout(stdout.to_owned());
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    let (stats, duration) = stderr.rsplit_once("\nxrun: duration: ").unwrap();
+    let expected: Vec<String> = summary
+        .lines()
+        .map(|line| format!("xrun: {}", line))
+        .collect();
+    assert_eq!(stats, expected.join("\n"));
+    assert!(duration.ends_with("s\n"));
+}
+
+#[test_case("--steps build,test", "build\ntest x\n", "", 0 ; "subset")]
+#[test_case("--steps test,build", "build\ntest x\n", "", 0 ; "config order")]
+#[test_case("--steps lint", "lint x\n", "", 0 ; "args to last selected")]
+#[test_case("--steps deploy", "", "Error: Command has no step named 'deploy'\n", 1 ; "unknown")]
+fn test_command_steps_named(flags: &str, stdout: &str, stderr: &str, ret: i32) {
+    let toml_command_data = r#"
+        [c]
+        steps = [
+            { name = "build", command = "echo build" },
+            "echo unnamed",
+            { name = "test", command = "echo test" },
+            { name = "lint", command = "echo lint" },
+        ]
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, &format!("{} c x", flags), stdout, stderr, ret);
+}
+
+#[test]
+fn test_command_steps_passthrough() {
+    let toml_command_data = r#"c = { steps = ["echo a", "echo b"] }"#.as_bytes();
+    let mut test_setup = create_test_setup(toml_command_data);
+    let assert = test_setup.cmd.args(["-p", "c"]).assert();
+    assert.code(125).stdout("echo a && echo b\n").stderr("");
+}
+
+#[test_case("s", "c2 ran\n", "", 0 ; "default")]
+#[test_case("s c1", "c1 ran\n", "", 0 ; "explicit")]
+#[test_case("t", "", "Error: Command content invalid - Key 'default' is 'c', which is not a subcommand with a 'command'\n", 1 ; "invalid")]
+fn test_default_subcommand(args: &str, stdout: &str, stderr: &str, ret: i32) {
+    let toml_command_data = r#"
+        [s]
+        default = "c2"
+        c1 = { command = "echo c1 ran" }
+        c2 = { command = "echo c2 ran" }
+        [t]
+        default = "c"
+    "#
+    .as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    test_cmd(test_setup, args, stdout, stderr, ret);
+}
+
+#[test]
+fn test_config_include() {
+    let toml_command_data = r#"xrun = { include = ["git.toml"] }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let git_config = test_setup._tmp_dir.path().join("xrun/git.toml");
+    fs::write(git_config, r#"g = { command = "echo git ran" }"#).unwrap();
+    test_cmd(test_setup, "g", "git ran\n", "", 0);
+}
+
+#[test]
+fn test_config_include_dne() {
+    let toml_command_data = r#"xrun = { include = ["dne.toml"] }"#.as_bytes();
+    let test_setup = create_test_setup(toml_command_data);
+    let stderr = format!(
+        "Error: Included config file does not exist: {}\n",
+        test_setup._tmp_dir.path().join("xrun/dne.toml").display()
+    );
+    test_cmd(test_setup, "g", "", &stderr, 1);
+}
+
+const CACHE_TOML_COMMAND_DATA: &[u8] = r#"
+    c = { command = "echo ran {@} >> runs; wc -l < runs", cache = "5m" }
+    f = { command = "echo ran >> runs; wc -l < runs; exit 1", cache = "5m" }
+"#
+.as_bytes();
+
+fn run_cached(setup: &TestSetup, args: &[&str]) -> (String, i32) {
+    let mut cmd = Command::cargo_bin("xrun").unwrap();
+    let tmp_dir = setup._tmp_dir.path();
+    cmd.env("XDG_CONFIG_HOME", tmp_dir)
+        .env("XDG_CACHE_HOME", tmp_dir.join("cache"))
+        .env("SHELL", "sh")
+        .env_remove("XRUN_CONFIG")
+        .current_dir(tmp_dir);
+    let output = cmd.args(args).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    (stdout.trim().to_string(), output.status.code().unwrap())
+}
+
+#[test]
+fn test_cache_hit() {
+    let setup = create_test_setup(CACHE_TOML_COMMAND_DATA);
+    assert_eq!(run_cached(&setup, &["c"]), ("1".to_string(), 0));
+    assert_eq!(run_cached(&setup, &["c"]), ("1".to_string(), 0));
+}
+
+#[test]
+fn test_cache_keyed_by_args() {
+    let setup = create_test_setup(CACHE_TOML_COMMAND_DATA);
+    assert_eq!(run_cached(&setup, &["c"]), ("1".to_string(), 0));
+    assert_eq!(run_cached(&setup, &["c", "a"]), ("2".to_string(), 0));
+}
+
+#[test]
+fn test_cache_failure_not_cached() {
+    let setup = create_test_setup(CACHE_TOML_COMMAND_DATA);
+    assert_eq!(run_cached(&setup, &["f"]), ("1".to_string(), 1));
+    assert_eq!(run_cached(&setup, &["f"]), ("2".to_string(), 1));
+}
+
+#[test]
+fn test_cache_refresh() {
+    let setup = create_test_setup(CACHE_TOML_COMMAND_DATA);
+    assert_eq!(run_cached(&setup, &["c"]), ("1".to_string(), 0));
+    assert_eq!(
+        run_cached(&setup, &["--refresh", "c"]),
+        ("2".to_string(), 0)
+    );
+    assert_eq!(run_cached(&setup, &["c"]), ("2".to_string(), 0));
+}
+
+#[test]
+fn test_cache_no_cache() {
+    let setup = create_test_setup(CACHE_TOML_COMMAND_DATA);
+    assert_eq!(run_cached(&setup, &["c"]), ("1".to_string(), 0));
+    assert_eq!(
+        run_cached(&setup, &["--no-cache", "c"]),
+        ("2".to_string(), 0)
+    );
+    assert_eq!(run_cached(&setup, &["c"]), ("1".to_string(), 0));
+}
+
+#[test]
+fn test_library() {
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("command.toml");
+    fs::write(&path, BASIC_TOML_COMMAND_DATA).unwrap();
+
+    let resolved = xrun::get_command(&path, &["s", "c1"]).unwrap();
+    assert_eq!(resolved.command, "echo c1 ran");
+    let help = xrun::get_command_help(&path, &["s"]).unwrap();
+    assert_eq!(
+        help.pairs[0],
+        xrun::HelpPair(None, Some("s desc".to_string()))
+    );
+    match xrun::get_command(&path, &["s", "c3"]).unwrap_err() {
+        xrun::CommandParseError::CommandNotFoundError(component, _) => {
+            assert_eq!(component, "c3")
+        }
+        err => panic!("Expected `CommandNotFoundError`, got {:?}", err),
+    }
+
+    fs::write(&path, r#"q = { steps = ["echo a", "echo b"] }"#).unwrap();
+    let steps = xrun::get_command_steps(&path, &["q"]).unwrap();
+    let commands: Vec<&str> = steps.iter().map(|step| step.command.as_str()).collect();
+    assert_eq!(commands, ["echo a", "echo b"]);
+}